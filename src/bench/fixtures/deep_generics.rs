@@ -0,0 +1,107 @@
+//! Token-borrowing code buried under several layers of generic wrappers, closures, and trait
+//! dispatch. This stresses the instantiation of generic templates and the tracing of monomorphized
+//! call graphs.
+
+use autoken::{cap, tie};
+
+cap! {
+    pub Counter = u32;
+    pub Log = Vec<String>;
+}
+
+trait Visitor {
+    fn visit(&mut self, depth: u32);
+}
+
+struct Count;
+
+impl Visitor for Count {
+    fn visit(&mut self, _depth: u32) {
+        *cap!(mut Counter) += 1;
+    }
+}
+
+struct Record;
+
+impl Visitor for Record {
+    fn visit(&mut self, depth: u32) {
+        cap!(mut Log).push(format!("depth {depth}"));
+    }
+}
+
+struct Both<A, B>(A, B);
+
+impl<A: Visitor, B: Visitor> Visitor for Both<A, B> {
+    fn visit(&mut self, depth: u32) {
+        self.0.visit(depth);
+        self.1.visit(depth);
+    }
+}
+
+struct Nested<V>(V);
+
+impl<V: Visitor> Visitor for Nested<V> {
+    fn visit(&mut self, depth: u32) {
+        self.0.visit(depth + 1);
+    }
+}
+
+fn walk<V: Visitor>(visitor: &mut V, depth: u32) {
+    if depth == 0 {
+        return;
+    }
+
+    visitor.visit(depth);
+    with_each(depth, |d| visitor.visit(d));
+}
+
+fn with_each<F: FnMut(u32)>(count: u32, mut f: F) {
+    for i in 0..count {
+        f(i);
+    }
+}
+
+fn map_all<T, U, F: FnMut(T) -> U>(values: Vec<T>, f: F) -> Vec<U> {
+    values.into_iter().map(f).collect()
+}
+
+fn counter_ref<'a>() -> &'a u32 {
+    tie!('a => ref Counter);
+    cap!(ref Counter)
+}
+
+fn summarize<V: Visitor>(mut visitor: V, depth: u32) -> String {
+    walk(&mut visitor, depth);
+    let count = *counter_ref();
+    let lines = map_all(cap!(ref Log).clone(), |line| line.len());
+    format!("{count} visits, {} log bytes", lines.iter().sum::<usize>())
+}
+
+// Wraps a visitor in one `Nested` layer per listed level.
+macro_rules! nest {
+    ($v:expr) => { $v };
+    ($v:expr, $_level:tt $(, $rest:tt)*) => { Nested(nest!($v $(, $rest)*)) };
+}
+
+fn main() {
+    cap! {
+        Counter: &mut 0,
+        Log: &mut Vec::new()
+    =>
+        eprintln!("{}", summarize(Count, 4));
+        eprintln!("{}", summarize(Record, 4));
+        eprintln!("{}", summarize(Both(Count, Record), 4));
+        eprintln!("{}", summarize(nest!(Both(Count, Record), 1, 2, 3), 4));
+        eprintln!("{}", summarize(Both(nest!(Count, 1, 2), nest!(Record, 1, 2, 3, 4)), 4));
+        eprintln!(
+            "{}",
+            summarize(
+                Both(
+                    Both(nest!(Count, 1), nest!(Record, 1, 2)),
+                    Nested(Both(Count, nest!(Record, 1, 2, 3))),
+                ),
+                4,
+            )
+        );
+    }
+}
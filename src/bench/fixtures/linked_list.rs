@@ -0,0 +1,238 @@
+//! The linked list recipe from the `autoken` crate documentation. A minimal arena stands in for
+//! `generational_arena` so that the fixture can be compiled without any other dependencies.
+
+#![feature(arbitrary_self_types)]
+
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+mod arena {
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct Index {
+        slot: usize,
+        generation: u64,
+    }
+
+    pub struct Arena<T> {
+        slots: Vec<(u64, Option<T>)>,
+        free: Vec<usize>,
+    }
+
+    impl<T> Arena<T> {
+        pub fn new() -> Self {
+            Self {
+                slots: Vec::new(),
+                free: Vec::new(),
+            }
+        }
+
+        pub fn insert(&mut self, value: T) -> Index {
+            match self.free.pop() {
+                Some(slot) => {
+                    let entry = &mut self.slots[slot];
+                    entry.0 += 1;
+                    entry.1 = Some(value);
+
+                    Index {
+                        slot,
+                        generation: entry.0,
+                    }
+                }
+                None => {
+                    self.slots.push((0, Some(value)));
+
+                    Index {
+                        slot: self.slots.len() - 1,
+                        generation: 0,
+                    }
+                }
+            }
+        }
+
+        pub fn remove(&mut self, index: Index) -> Option<T> {
+            let entry = self.slots.get_mut(index.slot)?;
+            if entry.0 != index.generation {
+                return None;
+            }
+
+            let value = entry.1.take()?;
+            self.free.push(index.slot);
+            Some(value)
+        }
+    }
+
+    impl<T> std::ops::Index<Index> for Arena<T> {
+        type Output = T;
+
+        fn index(&self, index: Index) -> &T {
+            let entry = &self.slots[index.slot];
+            assert_eq!(entry.0, index.generation);
+            entry.1.as_ref().unwrap()
+        }
+    }
+
+    impl<T> std::ops::IndexMut<Index> for Arena<T> {
+        fn index_mut(&mut self, index: Index) -> &mut T {
+            let entry = &mut self.slots[index.slot];
+            assert_eq!(entry.0, index.generation);
+            entry.1.as_mut().unwrap()
+        }
+    }
+}
+
+use arena::Arena;
+
+type PointeeCap<T> = <T as Pointee>::Cap;
+
+trait Pointee: Sized {
+    type Cap;
+
+    fn arena<'a>() -> &'a Arena<Self>;
+
+    fn arena_mut<'a>() -> &'a mut Arena<Self>;
+}
+
+struct Handle<T: Pointee> {
+    _ty: PhantomData<fn(T) -> T>,
+    handle: arena::Index,
+}
+
+impl<T: Pointee> Copy for Handle<T> {}
+
+impl<T: Pointee> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Pointee> Handle<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            _ty: PhantomData,
+            handle: T::arena_mut().insert(value),
+        }
+    }
+
+    pub fn destroy(self) {
+        T::arena_mut().remove(self.handle);
+    }
+}
+
+impl<T: Pointee> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref<'a>(&'a self) -> &'a T {
+        autoken::tie!(unsafe 'a => ref T::Cap);
+        &T::arena()[self.handle]
+    }
+}
+
+impl<T: Pointee> DerefMut for Handle<T> {
+    fn deref_mut<'a>(&'a mut self) -> &'a mut T {
+        autoken::tie!(unsafe 'a => mut T::Cap);
+        &mut T::arena_mut()[self.handle]
+    }
+}
+
+macro_rules! pointee {
+    ($($ty:ty),*$(,)?) => {$(
+        const _: () = {
+            autoken::cap! {
+                pub Cap = Arena<$ty>;
+            }
+
+            impl Pointee for $ty {
+                type Cap = Cap;
+
+                fn arena<'a>() -> &'a Arena<Self> {
+                    autoken::tie!('a => ref Cap);
+                    autoken::cap!(ref Cap)
+                }
+
+                fn arena_mut<'a>() -> &'a mut Arena<Self> {
+                    autoken::tie!('a => mut Cap);
+                    autoken::cap!(mut Cap)
+                }
+            }
+        };
+    )*};
+}
+
+struct Node {
+    value: u32,
+    prev: Option<Handle<Self>>,
+    next: Option<Handle<Self>>,
+}
+
+pointee!(Node);
+
+impl Node {
+    pub fn new(value: u32) -> Self {
+        Self {
+            value,
+            prev: None,
+            next: None,
+        }
+    }
+
+    pub fn remove(mut self: Handle<Self>) {
+        if let Some(mut prev) = self.prev {
+            prev.next = self.next;
+        }
+
+        if let Some(mut next) = self.next {
+            next.prev = self.prev;
+        }
+    }
+
+    pub fn insert_right(mut self: Handle<Self>, mut next: Handle<Self>) {
+        next.remove();
+
+        if let Some(mut old_next) = self.next {
+            old_next.prev = Some(next);
+        }
+
+        next.next = self.next;
+        next.prev = Some(self);
+        self.next = Some(next);
+    }
+
+    pub fn iter(self: Handle<Self>) -> impl Iterator<Item = Handle<Self>> {
+        let mut state = Some(self);
+
+        std::iter::from_fn(move || {
+            let curr = state?;
+            state = curr.next;
+            Some(curr)
+        })
+    }
+}
+
+fn main() {
+    autoken::cap! {
+        PointeeCap<Node>: &mut Arena::new()
+    =>
+        let first = Handle::new(Node::new(1));
+        let second = Handle::new(Node::new(2));
+        let third = Handle::new(Node::new(3));
+
+        first.insert_right(second);
+        second.insert_right(third);
+
+        for node in first.iter() {
+            eprintln!("Value: {}", node.value);
+        }
+
+        second.remove();
+
+        for node in first.iter() {
+            eprintln!("Value: {}", node.value);
+        }
+
+        for node in [first, second, third] {
+            node.destroy();
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Many small functions borrowing overlapping subsets of many tokens. This stresses the borrow
+//! checking of templates and the propagation of facts through a wide call graph.
+
+use autoken::cap;
+
+cap! {
+    pub A = u32;
+    pub B = u32;
+    pub C = u32;
+    pub D = u32;
+    pub E = u32;
+    pub F = u32;
+}
+
+macro_rules! layer {
+    ($($name:ident => [$($ref:ident),*] [$($mut:ident),*] [$($callee:ident),*];)*) => {$(
+        fn $name() -> u32 {
+            let mut sum = 0;
+            $(sum += *cap!(ref $ref);)*
+            $(*cap!(mut $mut) += 1;)*
+            $(sum += $callee();)*
+            sum
+        }
+    )*};
+}
+
+layer! {
+    leaf_a => [A] [] [];
+    leaf_b => [B] [] [];
+    leaf_c => [] [C] [];
+    leaf_d => [] [D] [];
+    leaf_e => [E, F] [] [];
+    leaf_f => [] [F] [];
+
+    mid_1 => [A] [] [leaf_a, leaf_b, leaf_c];
+    mid_2 => [B] [] [leaf_b, leaf_d, leaf_e];
+    mid_3 => [] [C] [leaf_a, leaf_d];
+    mid_4 => [E] [] [leaf_b, leaf_f];
+    mid_5 => [] [] [leaf_c, leaf_e, leaf_a];
+    mid_6 => [A, B] [] [leaf_d, leaf_f];
+
+    top_1 => [] [] [mid_1, mid_2, mid_3];
+    top_2 => [] [] [mid_4, mid_5, mid_6];
+    top_3 => [] [] [mid_1, mid_4, mid_6];
+    top_4 => [] [] [mid_2, mid_3, mid_5];
+
+    root => [] [] [top_1, top_2, top_3, top_4];
+}
+
+fn main() {
+    cap! {
+        A: &mut 1,
+        B: &mut 2,
+        C: &mut 3,
+        D: &mut 4,
+        E: &mut 5,
+        F: &mut 6
+    =>
+        for _ in 0..4 {
+            eprintln!("{}", root());
+        }
+    }
+}
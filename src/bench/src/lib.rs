@@ -0,0 +1,343 @@
+//! Timing and regression-test harnesses for AuToken's analyzer.
+//!
+//! For timing, each file in the `fixtures` directory is compiled as a standalone binary crate by
+//! `autoken-rustc` with `-Z time-passes` enabled. The analyzer wraps each of its phases in a rustc
+//! timer whose name starts with `autoken_` so we can pick those out of rustc's own timing report.
+//!
+//! Run it with `cargo bench -Z bindeps`. Arguments which don't start with `--` are treated as
+//! substrings of the fixtures to run and `AUTOKEN_BENCH_ITERATIONS` overrides the number of timed
+//! runs per fixture.
+//!
+//! The regression tests are described in the [`ui`] and [`manifest`] modules and are run with
+//! `cargo test -Z bindeps`.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use rustc_build_sysroot::{SysrootBuilder, SysrootConfig};
+
+pub mod manifest;
+pub mod ui;
+
+const RUSTC_WRAPPER: &str = env!("CARGO_BIN_FILE_AUTOKEN_RUSTC");
+
+const DEFAULT_ITERATIONS: usize = 10;
+
+// === Driver === //
+
+pub fn run(work_dir: &Path) -> anyhow::Result<()> {
+    let iterations = match env::var("AUTOKEN_BENCH_ITERATIONS") {
+        Ok(count) => count
+            .parse::<usize>()
+            .ok()
+            .filter(|&count| count > 0)
+            .context("`AUTOKEN_BENCH_ITERATIONS` must be a positive integer")?,
+        Err(_) => DEFAULT_ITERATIONS,
+    };
+
+    // Collect fixtures
+    let fixtures = collect_fixtures(&Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures"))?;
+
+    if fixtures.is_empty() {
+        eprintln!("No fixtures matched the provided filters.");
+        return Ok(());
+    }
+
+    let toolchain = Toolchain::prepare(work_dir)?;
+
+    // Time each fixture
+    for fixture in &fixtures {
+        let name = fixture_name(fixture);
+
+        // Phases are listed in the order in which rustc reports them finishing.
+        let mut timings = Vec::<(String, Vec<Duration>)>::new();
+
+        // N.B. the first run is only used to warm up the file system cache.
+        for i in 0..=iterations {
+            let start = Instant::now();
+            let output = toolchain
+                .compile_cmd(fixture, &name, "bin")
+                .args(["--emit", "metadata", "-Z", "time-passes"])
+                .arg("--out-dir")
+                .arg(work_dir.join("out"))
+                .stderr(Stdio::piped())
+                .output()
+                .context("failed to spawn the rustc wrapper")?;
+
+            let wall_time = start.elapsed();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+
+            anyhow::ensure!(
+                output.status.success(),
+                "failed to analyze fixture {name}:\n{}",
+                stderr
+                    .lines()
+                    .filter(|line| !line.starts_with("time:"))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+
+            if i == 0 {
+                continue;
+            }
+
+            for (phase, time) in std::iter::once(("(wall clock)".to_string(), wall_time))
+                .chain(parse_time_passes(&stderr))
+            {
+                match timings.iter_mut().find(|(other, _)| *other == phase) {
+                    Some((_, times)) => times.push(time),
+                    None => timings.push((phase, vec![time])),
+                }
+            }
+        }
+
+        print_report(&name, iterations, timings);
+    }
+
+    Ok(())
+}
+
+/// Lists the `.rs` files in `dir` whose names contain one of the substrings passed on the command
+/// line, or all of them if there are no such arguments.
+pub(crate) fn collect_fixtures(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let filters = test_filters();
+
+    let mut fixtures = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    fixtures.retain(|path| {
+        path.extension().is_some_and(|ext| ext == "rs")
+            && (filters.is_empty()
+                || filters
+                    .iter()
+                    .any(|filter| fixture_name(path).contains(filter.as_str())))
+    });
+    fixtures.sort();
+
+    Ok(fixtures)
+}
+
+/// Lists the arguments which don't start with `--`, which are substrings of the names of the
+/// fixtures or tests to run.
+pub(crate) fn test_filters() -> Vec<String> {
+    env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect()
+}
+
+pub(crate) fn fixture_name(path: &Path) -> String {
+    path.file_stem().unwrap().to_string_lossy().into_owned()
+}
+
+fn print_report(name: &str, iterations: usize, mut timings: Vec<(String, Vec<Duration>)>) {
+    println!("{name} ({iterations} iterations)");
+    println!("    {:<32} {:>10} {:>10}", "phase", "min", "median");
+
+    for (phase, times) in &mut timings {
+        times.sort_unstable();
+
+        println!(
+            "    {phase:<32} {:>10} {:>10}",
+            format_duration(times[0]),
+            format_duration(times[times.len() / 2]),
+        );
+    }
+
+    println!();
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.)
+}
+
+// === Rustc === //
+
+/// A sysroot and a build of the `autoken` crate against which fixtures can be compiled.
+pub(crate) struct Toolchain {
+    sysroot: PathBuf,
+    deps_dir: PathBuf,
+}
+
+impl Toolchain {
+    pub fn prepare(work_dir: &Path) -> anyhow::Result<Self> {
+        let sysroot = work_dir.join("sysroot");
+        let target = host_target()?;
+
+        if !sysroot
+            .join("lib/rustlib")
+            .join(&target)
+            .join("lib/.rustc-build-sysroot-hash")
+            .exists()
+        {
+            eprintln!(
+                "Building AuToken's sysroot for target {target} in path {}. This only happens \
+                 once but may take a few minutes...",
+                sysroot.display(),
+            );
+        }
+
+        build_sysroot(&sysroot, &target)?;
+
+        let deps_dir = work_dir.join("deps");
+        fs::create_dir_all(&deps_dir)?;
+
+        let userland = Path::new(env!("CARGO_MANIFEST_DIR")).join("../userland/src/lib.rs");
+        let status = analyze_cmd(&sysroot, &deps_dir)
+            .arg(&userland)
+            .args([
+                "--crate-name",
+                "autoken",
+                "--crate-type",
+                "rlib",
+                "--edition",
+                "2021",
+            ])
+            .arg("--out-dir")
+            .arg(&deps_dir)
+            .status()
+            .context("failed to spawn the rustc wrapper")?;
+
+        anyhow::ensure!(status.success(), "failed to compile the `autoken` crate");
+
+        Ok(Self { sysroot, deps_dir })
+    }
+
+    /// Creates a command which analyzes `fixture` as a crate of type `crate_type` named `name`.
+    /// Callers still have to specify what to emit and where.
+    pub fn compile_cmd(&self, fixture: &Path, name: &str, crate_type: &str) -> Command {
+        let mut cmd = analyze_cmd(&self.sysroot, &self.deps_dir);
+        cmd.arg(fixture)
+            .args([
+                "--crate-name",
+                name,
+                "--crate-type",
+                crate_type,
+                "--edition",
+                "2021",
+            ])
+            .arg("--extern")
+            .arg(format!(
+                "autoken={}",
+                self.deps_dir.join("libautoken.rlib").display()
+            ));
+        cmd
+    }
+}
+
+fn analyze_cmd(sysroot: &Path, meta_dir: &Path) -> Command {
+    let mut cmd = rustc_cmd();
+    cmd.env("AUTOKEN_OVERRIDE_SYSROOT", sysroot)
+        .env("AUTOKEN_META_DIR", meta_dir)
+        .env_remove("AUTOKEN_SKIP_ANALYSIS");
+    cmd
+}
+
+fn rustc_cmd() -> Command {
+    let mut cmd = Command::new(RUSTC_WRAPPER);
+    cmd.env("AUTOKEN_SKIP_ANALYSIS", "yes");
+
+    // `autoken-rustc` links dynamically against the toolchain's `rustc_driver`.
+    if let Some(lib_dir) = toolchain_lib_dir() {
+        let var = if cfg!(windows) {
+            "PATH"
+        } else if cfg!(target_os = "macos") {
+            "DYLD_LIBRARY_PATH"
+        } else {
+            "LD_LIBRARY_PATH"
+        };
+
+        let paths = std::iter::once(lib_dir)
+            .chain(env::var_os(var).iter().flat_map(env::split_paths))
+            .collect::<Vec<_>>();
+
+        if let Ok(paths) = env::join_paths(paths) {
+            cmd.env(var, paths);
+        }
+    }
+
+    cmd
+}
+
+fn toolchain_lib_dir() -> Option<PathBuf> {
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = Command::new(rustc)
+        .args(["--print", "sysroot"])
+        .output()
+        .ok()?;
+
+    Some(PathBuf::from(String::from_utf8(output.stdout).ok()?.trim()).join("lib"))
+}
+
+fn host_target() -> anyhow::Result<String> {
+    let output = rustc_cmd()
+        .arg("-vV")
+        .output()
+        .context("failed to spawn the rustc wrapper")?;
+    let output = String::from_utf8(output.stdout)?;
+
+    Ok(output
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .context("failed to find `host: ` line")?
+        .to_string())
+}
+
+fn build_sysroot(store_path: &Path, target: &str) -> anyhow::Result<()> {
+    let sysroot_src_code = rustc_build_sysroot::rustc_sysroot_src(rustc_cmd())?;
+
+    if !sysroot_src_code.exists() {
+        anyhow::bail!("could not find rust-src for this current toolchain");
+    }
+
+    // N.B. like `cargo-autoken`, we only need the standard library's MIR so we skip its analysis.
+    let rustc = rustc_cmd();
+    let mut cargo = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    cargo
+        .env("RUSTC", rustc.get_program())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+
+    for (key, value) in rustc.get_envs() {
+        match value {
+            Some(value) => cargo.env(key, value),
+            None => cargo.env_remove(key),
+        };
+    }
+
+    SysrootBuilder::new(store_path, target)
+        .cargo(cargo)
+        .sysroot_config(SysrootConfig::WithStd {
+            std_features: vec!["panic_unwind".to_string(), "backtrace".to_string()],
+        })
+        .build_from_source(&sysroot_src_code)?;
+
+    Ok(())
+}
+
+fn parse_time_passes(stderr: &str) -> Vec<(String, Duration)> {
+    // Lines take the form `time:   0.012; rss:   45MB ->   46MB (   +1MB)	autoken_trace_facts`.
+    stderr
+        .lines()
+        .filter_map(|line| {
+            let seconds = line.strip_prefix("time:")?.split(';').next()?.trim();
+            let phase = line.split_whitespace().last()?;
+
+            if !phase.starts_with("autoken_") {
+                return None;
+            }
+
+            Some((
+                phase.to_string(),
+                Duration::from_secs_f64(seconds.parse().ok()?),
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,128 @@
+//! A regression-test harness for the defaults `cargo autoken check` reads from
+//! `[workspace.metadata.autoken]` and `[package.metadata.autoken]`.
+//!
+//! Each case checks one of the projects in the `fixtures/manifest` directory and expects the check
+//! to fail with an error mentioning the settings in effect. The checks never get as far as building
+//! anything: `RUSTC` points to a missing binary so the toolchain check fails unless it was disabled
+//! and, past that, the empty custom sysroot is rejected for whichever target the check resolved.
+
+use std::{env, fs, path::Path, process::Command};
+
+use anyhow::Context;
+
+use crate::test_filters;
+
+const CARGO_AUTOKEN: &str = env!("CARGO_BIN_FILE_CARGO_AUTOKEN");
+
+struct Case {
+    name: &'static str,
+    fixture: &'static str,
+    args: &'static [&'static str],
+    expected: &'static str,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "package_defaults",
+        fixture: "defaults",
+        args: &[],
+        expected: "does not contain a sysroot for target package-target",
+    },
+    Case {
+        name: "workspace_defaults",
+        fixture: "workspace_defaults",
+        args: &[],
+        expected: "does not contain a sysroot for target workspace-target",
+    },
+    Case {
+        name: "cli_target",
+        fixture: "defaults",
+        args: &["--target", "cli-target"],
+        expected: "does not contain a sysroot for target cli-target",
+    },
+    Case {
+        name: "cli_toolchain_check",
+        fixture: "defaults",
+        args: &["--toolchain-check", "error"],
+        expected: "Failed to determine version of the rustc binary",
+    },
+    Case {
+        name: "unknown_key",
+        fixture: "unknown_key",
+        args: &[],
+        expected: "Unknown key `old-artifact` in `[package.metadata.autoken]`",
+    },
+    Case {
+        name: "wrong_type",
+        fixture: "wrong_type",
+        args: &[],
+        expected: "`disable-toolchain-checks` in `[package.metadata.autoken]` must be a boolean",
+    },
+];
+
+// === Driver === //
+
+pub fn run(work_dir: &Path) -> anyhow::Result<()> {
+    let filters = test_filters();
+    let cases = CASES
+        .iter()
+        .filter(|case| filters.is_empty() || filters.iter().any(|f| case.name.contains(f.as_str())))
+        .collect::<Vec<_>>();
+
+    if cases.is_empty() {
+        eprintln!("No manifest tests matched the provided filters.");
+        return Ok(());
+    }
+
+    let fixture_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/manifest");
+    let sysroot_dir = work_dir.join("manifest-sysroot");
+    fs::create_dir_all(&sysroot_dir)
+        .with_context(|| format!("failed to create {}", sysroot_dir.display()))?;
+
+    let mut failed = Vec::new();
+
+    for case in &cases {
+        let output = Command::new(CARGO_AUTOKEN)
+            .args(["autoken", "check", "--no-sysroot-build"])
+            .arg("--manifest-path")
+            .arg(fixture_dir.join(case.fixture).join("Cargo.toml"))
+            .arg("--target-dir")
+            .arg(work_dir.join("manifest-target"))
+            .arg("--custom-rustc-sysroot")
+            .arg(&sysroot_dir)
+            .arg("--custom-rustc-wrapper")
+            .arg(work_dir.join("missing-rustc-wrapper"))
+            .args(case.args)
+            .env("RUSTC", work_dir.join("missing-rustc"))
+            .output()
+            .context("failed to spawn cargo-autoken")?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        if !output.status.success() && stderr.contains(case.expected) {
+            println!("test {} ... ok", case.name);
+        } else {
+            println!("test {} ... FAILED", case.name);
+            println!("    expected an error containing {:?}, got:", case.expected);
+            for line in stderr.lines() {
+                println!("    {line}");
+            }
+            failed.push(case.name);
+        }
+    }
+
+    println!();
+    println!(
+        "{} passed; {} failed",
+        cases.len() - failed.len(),
+        failed.len()
+    );
+
+    anyhow::ensure!(
+        failed.is_empty(),
+        "failing manifest tests: {}",
+        failed.join(", ")
+    );
+
+    Ok(())
+}
@@ -0,0 +1,408 @@
+//! A regression-test harness for AuToken's diagnostics.
+//!
+//! Each file in the `tests/ui` directory is analyzed as a standalone binary crate and the
+//! diagnostics reported for it are checked two ways:
+//!
+//! - Every error and warning pointing into the file must be matched by an annotation of the form
+//!   `//~ ERROR <message>` or `//~ WARN <message>` on the line it points to. `//~^` refers to the
+//!   line above the annotation—one line per caret—and `//~|` refers to the same line as the
+//!   previous annotation. `//~?` expects a diagnostic pointing outside of the file, such as into
+//!   the `autoken` crate, which are otherwise ignored. Messages are matched as substrings.
+//! - The full rendered output must match the `.stderr` file next to the test. The output is
+//!   rendered with `-Z ui-testing` and the paths of the test directory and of the `autoken` crate's
+//!   sources are replaced by `$DIR` and `$AUTOKEN`. Locations outside of the test, including those
+//!   of closures, are replaced by `LL:CC`. A missing `.stderr` file means that the test should
+//!   compile without any output.
+//!
+//! Tests are compiled as binary crates unless they specify another type with a
+//! `//@ crate-type: TYPE` line. They can also set environment variables for the analyzer with
+//! `//@ rustc-env: NAME=VALUE` lines. Running the harness with `AUTOKEN_BLESS` set overwrites the
+//! `.stderr` files with the current output instead of comparing against them.
+
+use std::{env, fmt, fs, path::Path, process::Stdio};
+
+use anyhow::Context;
+
+use crate::{collect_fixtures, fixture_name, Toolchain};
+
+// === Driver === //
+
+pub fn run(work_dir: &Path) -> anyhow::Result<()> {
+    let bless = env::var_os("AUTOKEN_BLESS").is_some();
+
+    let test_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ui");
+    let autoken_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../userland/src");
+    let tests = collect_fixtures(&test_dir)?;
+
+    if tests.is_empty() {
+        eprintln!("No UI tests matched the provided filters.");
+        return Ok(());
+    }
+
+    let toolchain = Toolchain::prepare(work_dir)?;
+    let mut failed = Vec::new();
+
+    for test in &tests {
+        let name = fixture_name(test);
+        let errors = run_test(
+            &toolchain,
+            work_dir,
+            &test_dir,
+            &autoken_dir,
+            test,
+            &name,
+            bless,
+        )?;
+
+        if errors.is_empty() {
+            println!("test {name} ... ok");
+        } else {
+            println!("test {name} ... FAILED");
+            for error in &errors {
+                println!("    {error}");
+            }
+            failed.push(name);
+        }
+    }
+
+    println!();
+    println!(
+        "{} passed; {} failed",
+        tests.len() - failed.len(),
+        failed.len()
+    );
+
+    anyhow::ensure!(
+        failed.is_empty(),
+        "failing UI tests: {}. If the new output is expected, re-run with `AUTOKEN_BLESS=1` to \
+         update the `.stderr` files.",
+        failed.join(", "),
+    );
+
+    Ok(())
+}
+
+fn run_test(
+    toolchain: &Toolchain,
+    work_dir: &Path,
+    test_dir: &Path,
+    autoken_dir: &Path,
+    test: &Path,
+    name: &str,
+    bless: bool,
+) -> anyhow::Result<Vec<String>> {
+    let source =
+        fs::read_to_string(test).with_context(|| format!("failed to read {}", test.display()))?;
+
+    let crate_type = parse_directives(&source, "crate-type")
+        .last()
+        .unwrap_or("bin");
+
+    let mut cmd = toolchain.compile_cmd(test, name, crate_type);
+    cmd.args(["--emit", "metadata", "--color", "never", "-Z", "ui-testing"])
+        .arg("--out-dir")
+        .arg(work_dir.join("ui-out"))
+        .env_remove("AUTOKEN_COLOR")
+        .stderr(Stdio::piped());
+
+    for var in parse_directives(&source, "rustc-env") {
+        if let Some((var, value)) = var.split_once('=') {
+            cmd.env(var, value);
+        }
+    }
+
+    let output = cmd.output().context("failed to spawn the rustc wrapper")?;
+    let stderr = String::from_utf8_lossy(&output.stderr)
+        .replace("\r\n", "\n")
+        .replace(&test_dir.display().to_string(), "$DIR")
+        .replace(&autoken_dir.display().to_string(), "$AUTOKEN");
+
+    let mut errors = Vec::new();
+
+    // Check annotations
+    let file_name = format!("$DIR/{}", test.file_name().unwrap().to_string_lossy());
+    let mut expected = parse_annotations(&source);
+    let should_fail = expected
+        .iter()
+        .any(|expected| expected.kind == DiagnosticKind::Error);
+
+    let mut unexpected = Vec::new();
+
+    for actual in parse_diagnostics(&stderr) {
+        let line = (actual.file == file_name).then_some(actual.line);
+        let matched = expected.iter().position(|expected| {
+            expected.line == line
+                && expected.kind == actual.kind
+                && actual.message.contains(&expected.message)
+        });
+
+        match matched {
+            Some(idx) => {
+                expected.remove(idx);
+            }
+            None if line.is_some() => unexpected.push(actual),
+            None => {}
+        }
+    }
+
+    for actual in unexpected {
+        errors.push(format!("unexpected {actual}"));
+    }
+
+    for expected in &expected {
+        errors.push(format!("expected {expected} but it was not reported"));
+    }
+
+    if output.status.success() == should_fail {
+        errors.push(format!(
+            "expected compilation to {} but it {}",
+            if should_fail { "fail" } else { "succeed" },
+            if output.status.success() {
+                "succeeded"
+            } else {
+                "failed"
+            },
+        ));
+    }
+
+    // Check the golden output
+    let stderr = normalize_foreign_locations(&stderr);
+    let golden_path = test.with_extension("stderr");
+    let golden = fs::read_to_string(&golden_path)
+        .unwrap_or_default()
+        .replace("\r\n", "\n");
+
+    if bless {
+        if stderr.is_empty() {
+            let _ = fs::remove_file(&golden_path);
+        } else {
+            fs::write(&golden_path, &stderr)
+                .with_context(|| format!("failed to write {}", golden_path.display()))?;
+        }
+    } else if stderr != golden {
+        errors.push(format!(
+            "stderr does not match {}:\n{}",
+            golden_path.display(),
+            diff_lines(&golden, &stderr),
+        ));
+    }
+
+    Ok(errors)
+}
+
+// === Annotations === //
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum DiagnosticKind {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DiagnosticKind::Error => "ERROR",
+            DiagnosticKind::Warning => "WARN",
+        })
+    }
+}
+
+#[derive(Debug)]
+struct ExpectedDiagnostic {
+    /// The line the diagnostic points to or `None` if it points outside of the test.
+    line: Option<usize>,
+    kind: DiagnosticKind,
+    message: String,
+}
+
+impl fmt::Display for ExpectedDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} on line {line}: {:?}", self.kind, self.message),
+            None => write!(f, "{} outside of the test: {:?}", self.kind, self.message),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ActualDiagnostic {
+    file: String,
+    line: usize,
+    kind: DiagnosticKind,
+    message: String,
+}
+
+impl fmt::Display for ActualDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on line {}: {:?}", self.kind, self.line, self.message)
+    }
+}
+
+fn parse_annotations(source: &str) -> Vec<ExpectedDiagnostic> {
+    let mut annotations = Vec::<ExpectedDiagnostic>::new();
+
+    for (idx, line) in source.lines().enumerate() {
+        let Some((_, annotation)) = line.split_once("//~") else {
+            continue;
+        };
+
+        let (line, annotation) = if let Some(rest) = annotation.strip_prefix('|') {
+            let Some(prev) = annotations.last() else {
+                continue;
+            };
+            (prev.line, rest)
+        } else if let Some(rest) = annotation.strip_prefix('?') {
+            (None, rest)
+        } else {
+            let rest = annotation.trim_start_matches('^');
+            let carets = annotation.len() - rest.len();
+            (Some((idx + 1).saturating_sub(carets)), rest)
+        };
+
+        let Some((kind, message)) = annotation.trim_start().split_once(' ') else {
+            continue;
+        };
+
+        let kind = match kind {
+            "ERROR" => DiagnosticKind::Error,
+            "WARN" | "WARNING" => DiagnosticKind::Warning,
+            _ => continue,
+        };
+
+        annotations.push(ExpectedDiagnostic {
+            line,
+            kind,
+            message: message.trim().to_string(),
+        });
+    }
+
+    annotations
+}
+
+/// Lists the values of the `//@ name: value` lines in `source`.
+fn parse_directives<'a>(source: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> {
+    source.lines().filter_map(move |line| {
+        let value = line.trim().strip_prefix("//@ ")?.strip_prefix(name)?;
+        Some(value.strip_prefix(':')?.trim())
+    })
+}
+
+// === Rendered Output === //
+
+fn parse_diagnostics(stderr: &str) -> Vec<ActualDiagnostic> {
+    let lines = stderr.lines().collect::<Vec<_>>();
+
+    // Diagnostics take the form `error[E0000]: message` followed by `  --> file:line:column`.
+    lines
+        .iter()
+        .zip(lines.iter().skip(1))
+        .filter_map(|(header, location)| {
+            let (kind, rest) = if let Some(rest) = header.strip_prefix("error") {
+                (DiagnosticKind::Error, rest)
+            } else if let Some(rest) = header.strip_prefix("warning") {
+                (DiagnosticKind::Warning, rest)
+            } else {
+                return None;
+            };
+
+            let rest = match rest.strip_prefix('[') {
+                Some(rest) => rest.split_once(']')?.1,
+                None => rest,
+            };
+            let message = rest.strip_prefix(": ")?;
+
+            let mut location = location.trim().strip_prefix("--> ")?.rsplitn(3, ':');
+            let _column = location.next()?;
+            let line = location.next()?.parse().ok()?;
+            let file = location.next()?;
+
+            Some(ActualDiagnostic {
+                file: file.to_string(),
+                line,
+                kind,
+                message: message.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Replaces the line and column of locations outside of the test (e.g.
+/// `--> $AUTOKEN/lib.rs:12:34` or `{closure@lib.rs:12:34}`) with placeholders so that editing the
+/// `autoken` crate doesn't change the output of every test going through it.
+fn normalize_foreign_locations(stderr: &str) -> String {
+    let stderr = stderr
+        .lines()
+        .map(|line| {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            let location = ["--> ", "::: "]
+                .into_iter()
+                .find_map(|marker| Some((marker, line.trim_start().strip_prefix(marker)?)));
+
+            match location {
+                Some((marker, location)) if !location.starts_with("$DIR") => {
+                    let file = location.rsplitn(3, ':').last().unwrap_or(location);
+                    format!("{indent}{marker}{file}:LL:CC\n")
+                }
+                _ => format!("{line}\n"),
+            }
+        })
+        .collect::<String>();
+
+    let mut normalized = String::with_capacity(stderr.len());
+    let mut rest = stderr.as_str();
+
+    while let Some(start) = rest.find("{closure@") {
+        let (before, closure) = rest.split_at(start);
+        normalized.push_str(before);
+
+        let Some(end) = closure.find('}') else {
+            rest = closure;
+            break;
+        };
+
+        let (name, after) = closure.split_at(end + 1);
+        let location = &name["{closure@".len()..name.len() - 1];
+
+        match location.split(':').next() {
+            Some(file) if !file.starts_with("$DIR") => {
+                normalized.push_str(&format!("{{closure@{file}:LL:CC}}"));
+            }
+            _ => normalized.push_str(name),
+        }
+
+        rest = after;
+    }
+
+    normalized.push_str(rest);
+    normalized
+}
+
+fn diff_lines(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+
+    // N.B. this is just meant to point at the first few differences, not to be a minimal diff.
+    let mut diff = Vec::new();
+
+    for i in 0..expected.len().max(actual.len()) {
+        let (expected, actual) = (expected.get(i), actual.get(i));
+        if expected == actual {
+            continue;
+        }
+
+        if let Some(expected) = expected {
+            diff.push(format!("        -{expected}"));
+        }
+
+        if let Some(actual) = actual {
+            diff.push(format!("        +{actual}"));
+        }
+
+        if diff.len() >= 20 {
+            diff.push("        ...".to_string());
+            break;
+        }
+    }
+
+    diff.join("\n")
+}
@@ -0,0 +1,3 @@
+fn main() -> anyhow::Result<()> {
+    autoken_bench::ui::run(env!("CARGO_TARGET_TMPDIR").as_ref())
+}
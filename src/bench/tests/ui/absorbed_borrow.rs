@@ -0,0 +1,38 @@
+//@ rustc-env: AUTOKEN_WARN_ABSORBED_BORROWS=yes
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+fn demo() {
+    let first_three = &autoken::cap!(ref MyCap)[0..3];
+
+    // This compiles because we don't see the mutable borrow of `MyCap` here!
+    unsafe {
+        autoken::absorb::<autoken::Mut<MyCap>, _>(|| {
+            //~^ WARN this `absorb` hides borrows of token MyCap while it is still borrowed immutably
+            autoken::cap!(mut MyCap).push(3);
+        });
+    }
+    eprintln!("The first three elements are: {first_three:?}");
+}
+
+fn unrelated() {
+    let len = autoken::cap!(ref MyCap).len();
+
+    // The borrow has already ended so there's nothing to hide.
+    unsafe {
+        autoken::absorb::<autoken::Mut<MyCap>, _>(|| {
+            autoken::cap!(mut MyCap).push(len as u32);
+        });
+    }
+}
+
+fn main() {
+    autoken::cap! {
+        MyCap: &mut vec![1, 2, 3]
+    =>
+        demo();
+        unrelated();
+    }
+}
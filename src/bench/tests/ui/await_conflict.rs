@@ -0,0 +1,50 @@
+// Borrows held across a suspension point conflict with whatever other code runs while the
+// coroutine is suspended. The report must point at the `.await` which actually holds the borrow.
+
+use std::{
+    future::Future,
+    pin::pin,
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+async fn yield_now() {}
+
+async fn read_values() {
+    yield_now().await;
+
+    let values = autoken::cap!(ref MyCap);
+    yield_now().await; //~ ERROR conflicting borrows on token MyCap
+    eprintln!("{values:?}");
+}
+
+fn main() {
+    let mut values = vec![1, 2, 3];
+
+    autoken::cap! {
+        MyCap: &mut values
+    =>
+        block_on(read_values());
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |_| RawWaker::new(std::ptr::null(), &VTABLE),
+        |_| {},
+        |_| {},
+        |_| {},
+    );
+
+    let waker = unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) };
+    let mut future = pin!(future);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            return output;
+        }
+    }
+}
@@ -0,0 +1,30 @@
+// A `Cell` provided by shared reference can be mutated by every `ref` fetch of it.
+
+use std::cell::Cell;
+
+autoken::cap! {
+    pub Hits = Cell<u32>;
+}
+
+fn hit() {
+    let hits = autoken::cap!(ref Hits);
+    hits.set(hits.get() + 1);
+}
+
+fn hit_around() {
+    let hits = autoken::cap!(ref Hits);
+    hits.set(hits.get() + 1);
+    hit();
+    hits.set(hits.get() + 1);
+}
+
+fn main() {
+    let hits = Cell::new(0);
+
+    autoken::cap! {
+        Hits: &hits
+    =>
+        hit();
+        hit_around();
+    }
+}
@@ -0,0 +1,28 @@
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+fn main() {
+    let mut my_vec = vec![1, 2, 3, 4];
+
+    autoken::cap! {
+        MyCap: &mut my_vec
+    =>
+        do_something();
+    }
+}
+
+fn do_something() {
+    with_indirection();
+}
+
+fn with_indirection() {
+    let my_vec = autoken::cap!(ref MyCap);
+    let first_three = &my_vec[0..3];
+    add_number(5); //~ ERROR conflicting borrows on token MyCap
+    eprintln!("The first three elements were {first_three:?}");
+}
+
+fn add_number(number: u32) {
+    autoken::cap!(mut MyCap).push(number);
+}
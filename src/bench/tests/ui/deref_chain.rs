@@ -0,0 +1,126 @@
+// Auto-deref through two user smart pointers must accumulate the borrows of both `deref` calls,
+// including when the outer call resolves through a generic `Deref` bound.
+
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+trait Pointee: Sized {
+    type Cap;
+
+    fn arena<'a>() -> &'a Vec<Self>;
+
+    fn arena_mut<'a>() -> &'a mut Vec<Self>;
+}
+
+struct Handle<T: Pointee> {
+    _ty: PhantomData<fn(T) -> T>,
+    index: usize,
+}
+
+impl<T: Pointee> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref<'a>(&'a self) -> &'a T {
+        autoken::tie!(unsafe 'a => ref T::Cap);
+        &T::arena()[self.index]
+    }
+}
+
+impl<T: Pointee> DerefMut for Handle<T> {
+    fn deref_mut<'a>(&'a mut self) -> &'a mut T {
+        autoken::tie!(unsafe 'a => mut T::Cap);
+        &mut T::arena_mut()[self.index]
+    }
+}
+
+autoken::cap! {
+    pub Values = Vec<Vec<u32>>;
+    pub Handles = Vec<Handle<Vec<u32>>>;
+}
+
+impl Pointee for Vec<u32> {
+    type Cap = Values;
+
+    fn arena<'a>() -> &'a Vec<Self> {
+        autoken::tie!('a => ref Values);
+        autoken::cap!(ref Values)
+    }
+
+    fn arena_mut<'a>() -> &'a mut Vec<Self> {
+        autoken::tie!('a => mut Values);
+        autoken::cap!(mut Values)
+    }
+}
+
+impl Pointee for Handle<Vec<u32>> {
+    type Cap = Handles;
+
+    fn arena<'a>() -> &'a Vec<Self> {
+        autoken::tie!('a => ref Handles);
+        autoken::cap!(ref Handles)
+    }
+
+    fn arena_mut<'a>() -> &'a mut Vec<Self> {
+        autoken::tie!('a => mut Handles);
+        autoken::cap!(mut Handles)
+    }
+}
+
+fn push_value() {
+    autoken::cap!(mut Values)[0].push(4);
+}
+
+fn push_handle() {
+    autoken::cap!(mut Handles).push(Handle {
+        _ty: PhantomData,
+        index: 0,
+    });
+}
+
+fn inner_borrow_conflicts(handle: &Handle<Handle<Vec<u32>>>) {
+    let values: &Vec<u32> = handle;
+    push_value(); //~ ERROR conflicting borrows on token Values
+    eprintln!("{values:?}");
+}
+
+fn outer_borrow_conflicts(handle: &Handle<Handle<Vec<u32>>>) {
+    let values: &Vec<u32> = handle;
+    push_handle(); //~ ERROR conflicting borrows on token Handles
+    eprintln!("{values:?}");
+}
+
+fn generic_borrow_conflicts<P: Deref<Target = Handle<Vec<u32>>>>(handle: &P) {
+    let values: &Vec<u32> = handle;
+    push_value(); //~ ERROR conflicting borrows on token Values
+    eprintln!("{values:?}");
+}
+
+fn chain_is_released(handle: &mut Handle<Handle<Vec<u32>>>) {
+    handle.push(5);
+    push_value();
+    push_handle();
+    eprintln!("{:?}", &***handle);
+}
+
+fn main() {
+    let mut values = vec![vec![1, 2, 3]];
+    let mut handles = Vec::new();
+
+    autoken::cap! {
+        Values: &mut values,
+        Handles: &mut handles,
+    =>
+        push_handle();
+
+        let mut handle = Handle {
+            _ty: PhantomData,
+            index: 0,
+        };
+        inner_borrow_conflicts(&handle);
+        outer_borrow_conflicts(&handle);
+        generic_borrow_conflicts(&handle);
+        chain_is_released(&mut handle);
+    }
+}
@@ -0,0 +1,18 @@
+// `Diff` only removes a borrow when the subtrahend borrows the token with at least the same
+// mutability.
+
+use autoken::{Diff, Mut, Ref};
+
+struct X;
+
+fn main() {
+    // Subtracting an immutable borrow leaves a mutable one in place...
+    autoken::assert_subset!(Mut<X>, Diff<Mut<X>, Ref<X>>);
+
+    // ...while subtracting a borrow of the same mutability removes it.
+    autoken::assert_subset!(Ref<X>, Diff<Mut<X>, Mut<X>>); //~ ERROR is not a subset of
+    autoken::assert_subset!(Ref<X>, Diff<Ref<X>, Ref<X>>); //~ ERROR is not a subset of
+
+    // Subtracting a mutable borrow removes an immutable one too.
+    autoken::assert_subset!(Ref<X>, Diff<Ref<X>, Mut<X>>); //~ ERROR is not a subset of
+}
@@ -0,0 +1,55 @@
+// Calls through `dyn Trait` reach their impls through vtable shims. The tracer must get through
+// those shims and blame the impl method which borrows the token, not the shim or the caller.
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+trait Logger {
+    fn log(&self, value: u32);
+}
+
+struct VecLogger;
+
+impl Logger for VecLogger {
+    fn log(&self, value: u32) {
+        autoken::cap!(mut MyCap).push(value);
+    }
+}
+
+struct StderrLogger;
+
+impl Logger for StderrLogger {
+    fn log(&self, value: u32) {
+        eprintln!("{value}");
+    }
+}
+
+fn main() {
+    let mut values = vec![1, 2, 3];
+
+    autoken::cap! {
+        MyCap: &mut values
+    =>
+        log_all(&[Box::new(StderrLogger), Box::new(VecLogger)]);
+        //~^ ERROR cannot unsize this function because it borrows unabsorbed tokens
+
+        let logger: Box<dyn Logger> = Box::new(StderrLogger);
+        log_all(&[logger]);
+
+        // By-value methods such as `FnOnce::call_once` go through a `VTableShim`.
+        run_boxed(Box::new(|| eprintln!("done")));
+        run_boxed(Box::new(|| autoken::cap!(mut MyCap).clear()));
+        //~^ ERROR cannot unsize this function because it borrows unabsorbed tokens
+    }
+}
+
+fn run_boxed(f: Box<dyn FnOnce()>) {
+    f();
+}
+
+fn log_all(loggers: &[Box<dyn Logger>]) {
+    for logger in loggers {
+        logger.log(4);
+    }
+}
@@ -0,0 +1,47 @@
+// Conflicts with a borrow held by a guard point at the implicit drop which keeps the guard alive.
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+struct Guard<'a> {
+    values: &'a Vec<u32>,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        eprintln!("releasing {:?}", self.values);
+    }
+}
+
+fn main() {
+    let mut values = vec![1, 2, 3];
+
+    autoken::cap! {
+        MyCap: &mut values
+    =>
+        hold_guard();
+        release_guard();
+    }
+}
+
+fn hold_guard() {
+    let other = Guard { values: &Vec::new() };
+    let _guard = Guard {
+        values: autoken::cap!(ref MyCap),
+    };
+    add_number(4); //~ ERROR conflicting borrows on token MyCap
+    drop(other);
+}
+
+fn release_guard() {
+    let guard = Guard {
+        values: autoken::cap!(ref MyCap),
+    };
+    drop(guard);
+    add_number(5);
+}
+
+fn add_number(number: u32) {
+    autoken::cap!(mut MyCap).push(number);
+}
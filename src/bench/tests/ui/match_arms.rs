@@ -0,0 +1,53 @@
+// Match arms which borrow different tokens rejoin without the borrows of one arm being charged to
+// the others. Borrows which escape an arm are still checked after the join.
+
+autoken::cap! {
+    pub Numbers = Vec<u32>;
+    pub Names = Vec<&'static str>;
+}
+
+fn main() {
+    let mut numbers = vec![1, 2, 3];
+    let mut names = vec!["one", "two", "three"];
+
+    autoken::cap! {
+        Numbers: &mut numbers,
+        Names: &mut names,
+    =>
+        for choice in 0..3 {
+            log_choice(choice);
+            add_number(choice);
+        }
+        count_choice(1);
+    }
+}
+
+fn log_choice(choice: u32) {
+    match choice {
+        0 => eprintln!("{:?}", autoken::cap!(ref Numbers)),
+        1 => autoken::cap!(mut Names).push("four"),
+        _ => {}
+    }
+
+    add_number(choice);
+    autoken::cap!(mut Names).push("five");
+}
+
+fn count_choice(choice: u32) {
+    let count = match choice {
+        0 => autoken::cap!(ref Numbers).len(),
+        _ => autoken::cap!(ref Names).len(),
+    };
+    add_number(count as u32);
+
+    let first = match choice {
+        0 => &autoken::cap!(ref Numbers)[0],
+        _ => &0,
+    };
+    add_number(1); //~ ERROR conflicting borrows on token Numbers
+    eprintln!("{first}");
+}
+
+fn add_number(number: u32) {
+    autoken::cap!(mut Numbers).push(number);
+}
@@ -0,0 +1,41 @@
+//@ rustc-env: AUTOKEN_MAX_ERRORS=2
+
+// Errors reported while building templates count towards the limit along with conflicts, and
+// going over the limit says that errors were suppressed.
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+fn input_tie<'a>(values: &'a Vec<u32>) -> &'a u32 {
+    autoken::tie!('a => ref MyCap); //~ ERROR ties to lifetimes appearing in input parameter types are currently rejected due to soundness issues
+    &values[0]
+}
+
+fn first_conflict() {
+    let values = autoken::cap!(ref MyCap);
+    add_number(1); //~ ERROR conflicting borrows on token MyCap
+    eprintln!("{values:?}");
+}
+
+fn second_conflict() {
+    let values = autoken::cap!(ref MyCap);
+    add_number(2);
+    eprintln!("{values:?}");
+}
+
+fn add_number(number: u32) {
+    autoken::cap!(mut MyCap).push(number);
+}
+
+fn main() {
+    let mut values = vec![1, 2, 3];
+
+    autoken::cap! {
+        MyCap: &mut values
+    =>
+        first_conflict();
+        second_conflict();
+        eprintln!("{}", input_tie(&vec![4]));
+    }
+}
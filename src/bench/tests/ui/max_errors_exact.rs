@@ -0,0 +1,34 @@
+//@ rustc-env: AUTOKEN_MAX_ERRORS=2
+
+// Reaching the error limit without going over it must not claim that errors were suppressed.
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+fn first_conflict() {
+    let values = autoken::cap!(ref MyCap);
+    add_number(1); //~ ERROR conflicting borrows on token MyCap
+    eprintln!("{values:?}");
+}
+
+fn second_conflict() {
+    let values = autoken::cap!(ref MyCap);
+    add_number(2); //~ ERROR conflicting borrows on token MyCap
+    eprintln!("{values:?}");
+}
+
+fn add_number(number: u32) {
+    autoken::cap!(mut MyCap).push(number);
+}
+
+fn main() {
+    let mut values = vec![1, 2, 3];
+
+    autoken::cap! {
+        MyCap: &mut values
+    =>
+        first_conflict();
+        second_conflict();
+    }
+}
@@ -0,0 +1,38 @@
+// Validating a call whose signature can't be normalized because of a type error elsewhere in the
+// crate must leave reporting that error to rustc rather than crashing the analyzer.
+
+trait Provider {
+    type Out;
+}
+
+trait Other {
+    type Value;
+}
+
+struct Cap;
+
+impl Provider for Cap {
+    type Out = <Cap as Other>::Value; //~ ERROR the trait bound `Cap: Other` is not satisfied
+}
+
+fn fetch<'a, T: Provider>() -> Option<&'a T::Out> {
+    autoken::tie!('a => ref MyCap);
+    let _ = autoken::cap!(ref MyCap);
+    None
+}
+
+fn run<T: Provider>() {
+    let _ = fetch::<T>();
+}
+
+autoken::cap! {
+    MyCap = u32;
+}
+
+fn main() {
+    autoken::cap! {
+        MyCap: &0
+    =>
+        run::<Cap>();
+    }
+}
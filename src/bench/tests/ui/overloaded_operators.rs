@@ -0,0 +1,68 @@
+// Overloaded operators are calls to their trait's methods so the borrows made by their impls must
+// be reported at the operator expression which invoked them.
+
+use std::ops::{Add, Deref, Index};
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+fn main() {
+    let mut values = vec![1, 2, 3];
+
+    autoken::cap! {
+        MyCap: &mut values
+    =>
+        add_values();
+        index_values();
+        deref_values();
+    }
+}
+
+fn add_values() {
+    let values = autoken::cap!(ref MyCap);
+    let sum = Logged(1) + Logged(2); //~ ERROR conflicting borrows on token MyCap
+    eprintln!("{values:?} {}", sum.0);
+}
+
+fn index_values() {
+    let values = autoken::cap!(ref MyCap);
+    let value = Logged(1)[2]; //~ ERROR conflicting borrows on token MyCap
+    eprintln!("{values:?} {value}");
+}
+
+fn deref_values() {
+    let values = autoken::cap!(ref MyCap);
+    let value = *Logged(1); //~ ERROR conflicting borrows on token MyCap
+    eprintln!("{values:?} {value}");
+}
+
+/// A number which logs every use of its operators.
+struct Logged(u32);
+
+impl Add for Logged {
+    type Output = Logged;
+
+    fn add(self, rhs: Logged) -> Logged {
+        autoken::cap!(mut MyCap).push(self.0);
+        Logged(self.0 + rhs.0)
+    }
+}
+
+impl Index<u32> for Logged {
+    type Output = u32;
+
+    fn index(&self, index: u32) -> &u32 {
+        autoken::cap!(mut MyCap).push(index);
+        &self.0
+    }
+}
+
+impl Deref for Logged {
+    type Target = u32;
+
+    fn deref(&self) -> &u32 {
+        autoken::cap!(mut MyCap).push(self.0);
+        &self.0
+    }
+}
@@ -0,0 +1,31 @@
+//@ crate-type: proc-macro
+
+// Proc-macro crates are analyzed even though they have no `main` and only export macros.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+
+autoken::cap! {
+    Counter = u32;
+}
+
+fn bump() {
+    *autoken::cap!(mut Counter) += 1;
+}
+
+fn expand(input: TokenStream) -> TokenStream {
+    let count = autoken::cap!(ref Counter);
+    bump(); //~ ERROR conflicting borrows on token Counter
+    eprintln!("Expanded {count} macros");
+    input
+}
+
+#[proc_macro]
+pub fn counted(input: TokenStream) -> TokenStream {
+    autoken::cap! {
+        Counter: &mut 0
+    =>
+        expand(input)
+    }
+}
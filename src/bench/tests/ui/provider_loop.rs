@@ -0,0 +1,74 @@
+// Providers whose values don't change between loop iterations should be hoisted out of the loop
+// while providers of values depending on the loop can't be.
+
+autoken::cap! {
+    pub Counter = u32;
+}
+
+fn bump() {
+    *autoken::cap!(mut Counter) += 1;
+}
+
+fn invariant_provider() {
+    let mut counter = 0;
+
+    for _ in 0..3 {
+        autoken::cap! { //~ WARN `cap!` provider is re-entered on every iteration of a loop
+            Counter: &mut counter
+        =>
+            bump();
+        }
+    }
+}
+
+fn varying_provider() {
+    let mut counters = [0, 0, 0];
+
+    for counter in &mut counters {
+        autoken::cap! {
+            Counter: counter
+        =>
+            bump();
+        }
+    }
+}
+
+fn mutated_provider() {
+    let mut counters = [0, 0, 0];
+    let mut index = 0;
+
+    for _ in 0..3 {
+        autoken::cap! {
+            Counter: &mut counters[index]
+        =>
+            bump();
+        }
+        advance(&mut index);
+    }
+}
+
+fn mutated_through_deref_provider() {
+    let mut counters = [0, 0, 0];
+    let mut index = 0;
+
+    for _ in 0..3 {
+        autoken::cap! {
+            Counter: &mut counters[index]
+        =>
+            bump();
+        }
+        let index_ref = &mut index;
+        *index_ref += 1;
+    }
+}
+
+fn advance(index: &mut usize) {
+    *index += 1;
+}
+
+fn main() {
+    invariant_provider();
+    varying_provider();
+    mutated_provider();
+    mutated_through_deref_provider();
+}
@@ -0,0 +1,41 @@
+// Raw pointer accesses in functions borrowing a token mutably are pointed out once per function,
+// no matter how many times it is instantiated.
+
+use autoken::BorrowsOne;
+
+autoken::cap! {
+    pub Arena = Vec<u32>;
+    pub OtherArena = Vec<u32>;
+}
+
+fn poke<T>(slot: *mut u32) {
+    let _guard = BorrowsOne::<T>::acquire_mut();
+    unsafe { std::ptr::write(slot, 2) }; //~ WARN raw pointer access in a function which borrows token
+}
+
+fn push_and_poke(slot: *mut u32) {
+    autoken::cap!(mut Arena).push(1);
+    unsafe { slot.write(2) }; //~ WARN raw pointer access in a function which borrows token Arena
+}
+
+// Reading the token leaves nothing for the raw pointer to alias with a mutable borrow.
+fn read_and_poke(slot: *mut u32) {
+    eprintln!("{:?}", autoken::cap!(ref Arena));
+    unsafe { std::ptr::write(slot, 2) };
+}
+
+fn main() {
+    let mut arena = vec![1, 2, 3];
+    let mut other_arena = vec![4, 5, 6];
+    let mut slot = 0;
+
+    autoken::cap! {
+        Arena: &mut arena,
+        OtherArena: &mut other_arena,
+    =>
+        poke::<Arena>(&mut slot);
+        poke::<OtherArena>(&mut slot);
+        push_and_poke(&mut slot);
+        read_and_poke(&mut slot);
+    }
+}
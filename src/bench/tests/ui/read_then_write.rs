@@ -0,0 +1,43 @@
+// Shared borrows only conflict with later mutable borrows of the same token while they're live.
+// Letting the reference go out of scope before the mutable call is fine but using it afterwards
+// is not.
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+fn main() {
+    let mut values = vec![1, 2, 3];
+
+    autoken::cap! {
+        MyCap: &mut values
+    =>
+        read_then_write();
+        scope_then_write();
+        write_while_reading();
+    }
+}
+
+fn read_then_write() {
+    let values = autoken::cap!(ref MyCap);
+    eprintln!("{values:?}");
+    add_number(4);
+}
+
+fn scope_then_write() {
+    {
+        let values = autoken::cap!(ref MyCap);
+        eprintln!("{}", values[0]);
+    }
+    add_number(5);
+}
+
+fn write_while_reading() {
+    let values = autoken::cap!(ref MyCap);
+    add_number(6); //~ ERROR conflicting borrows on token MyCap
+    eprintln!("{values:?}");
+}
+
+fn add_number(number: u32) {
+    autoken::cap!(mut MyCap).push(number);
+}
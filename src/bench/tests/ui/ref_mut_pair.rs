@@ -0,0 +1,45 @@
+// `RefMutPair::split` hands out an immutable and a mutable borrow at once. Both are borrowed from
+// the surrounding context for as long as the pair lives and conflict when both halves of the pair
+// are the same token.
+
+use autoken::{Borrows, Mut, Ref};
+
+autoken::cap! {
+    pub Config = u32;
+    pub Log = Vec<u32>;
+}
+
+fn record() {
+    let pair = Borrows::<(Ref<Config>, Mut<Log>)>::acquire_ref_mut_pair();
+    let (config, log) = pair.split();
+    let config = Config::get(config, |v| v);
+    Log::get_mut(log, |v| v).push(*config);
+}
+
+fn record_while_configuring() {
+    let pair = Borrows::<(Ref<Config>, Mut<Log>)>::acquire_ref_mut_pair();
+    let (config, log) = pair.split();
+    *autoken::cap!(mut Config) += 1; //~ ERROR conflicting borrows on token Config
+    Log::get_mut(log, |v| v).push(*Config::get(config, |v| v));
+}
+
+fn record_same_token() {
+    let pair = Borrows::<(Ref<Log>, Mut<Log>)>::acquire_ref_mut_pair();
+    let (first, log) = pair.split();
+    let first = Log::get(first, |v| v);
+    Log::get_mut(log, |v| v).push(first[0]);
+    //~? ERROR conflicting borrows on token Log
+}
+
+fn main() {
+    let mut log = vec![1];
+
+    autoken::cap! {
+        Config: &mut 3,
+        Log: &mut log,
+    =>
+        record();
+        record_while_configuring();
+        record_same_token();
+    }
+}
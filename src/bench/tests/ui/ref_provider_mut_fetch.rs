@@ -0,0 +1,21 @@
+// Capabilities provided by shared reference can't be fetched mutably.
+
+use std::cell::Cell;
+
+autoken::cap! { //~ ERROR conflicting borrows on token Hits
+    pub Hits = Cell<u32>;
+}
+
+fn hit() {
+    autoken::cap!(mut Hits).set(1);
+}
+
+fn main() {
+    let hits = Cell::new(0);
+
+    autoken::cap! {
+        Hits: &hits
+    =>
+        hit();
+    }
+}
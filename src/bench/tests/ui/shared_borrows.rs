@@ -0,0 +1,21 @@
+// Immutable borrows of the same token never conflict with one another.
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+fn main() {
+    let mut my_vec = vec![1, 2, 3, 4];
+
+    autoken::cap! {
+        MyCap: &mut my_vec
+    =>
+        let first = autoken::cap!(ref MyCap);
+        let len = count();
+        eprintln!("{first:?} has {len} elements");
+    }
+}
+
+fn count() -> usize {
+    autoken::cap!(ref MyCap).len()
+}
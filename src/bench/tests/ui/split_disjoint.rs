@@ -0,0 +1,33 @@
+// `Borrows::split_disjoint` hands out both halves of a token set at once as long as they don't
+// overlap.
+
+use autoken::{Borrows, Mut};
+
+autoken::cap! {
+    pub Source = Vec<u32>;
+    pub Sink = Vec<u32>;
+}
+
+fn transfer(borrows: &mut Borrows<(Mut<Source>, Mut<Sink>)>) {
+    let (source, sink) = borrows.split_disjoint();
+    let source = Source::get_mut(source, |v| v);
+    Sink::get_mut(sink, |v| v).append(source);
+}
+
+fn overlapping_halves(borrows: &mut Borrows<(Mut<Source>, Mut<Source>)>) {
+    let _ = borrows.split_disjoint();
+    //~? ERROR conflicting borrows on token Source
+}
+
+fn main() {
+    let mut source = vec![1, 2];
+    let mut sink = Vec::new();
+
+    autoken::cap! {
+        Source: &mut source,
+        Sink: &mut sink,
+    =>
+        transfer(Borrows::acquire_mut());
+        overlapping_halves(Borrows::acquire_mut());
+    }
+}
@@ -0,0 +1,46 @@
+//@ rustc-env: AUTOKEN_WARN_TOKEN_SWAPS=yes
+
+// With `--warn-token-swaps`, exchanging two values which borrow the same token is pointed out
+// while exchanging values borrowing different tokens isn't.
+
+autoken::cap! {
+    pub Arena = Vec<u32>;
+    pub OtherArena = Vec<u32>;
+}
+
+fn handle<'a>(index: usize) -> &'a u32 {
+    autoken::tie!('a => ref Arena);
+    &autoken::cap!(ref Arena)[index]
+}
+
+fn other_handle<'a>(index: usize) -> &'a u32 {
+    autoken::tie!('a => ref OtherArena);
+    &autoken::cap!(ref OtherArena)[index]
+}
+
+fn same_token() {
+    let mut first = handle(0);
+    let mut second = handle(1);
+    std::mem::swap(&mut first, &mut second);
+    eprintln!("{first} {second}");
+}
+
+fn different_tokens() {
+    let mut first = handle(0);
+    let mut second = other_handle(1);
+    std::mem::swap(&mut first, &mut second);
+    eprintln!("{first} {second}");
+}
+
+fn main() {
+    let mut arena = vec![1, 2, 3];
+    let mut other_arena = vec![4, 5, 6];
+
+    autoken::cap! {
+        Arena: &mut arena,
+        OtherArena: &mut other_arena,
+    =>
+        same_token();
+        different_tokens();
+    }
+}
@@ -0,0 +1,61 @@
+// `?` desugars to calls to `Try::branch` and `FromResidual::from_residual` so borrows made by
+// user-defined `Try` impls must be reported at the `?` which invoked them.
+
+#![feature(try_trait_v2)]
+
+use std::{
+    convert::Infallible,
+    ops::{ControlFlow, FromResidual, Try},
+};
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+fn main() {
+    let mut values = vec![1, 2, 3];
+
+    autoken::cap! {
+        MyCap: &mut values
+    =>
+        let _ = read_values();
+    }
+}
+
+fn read_values() -> Logged<()> {
+    let values = autoken::cap!(ref MyCap);
+    let value = Logged::Ok(4)?; //~ ERROR conflicting borrows on token MyCap
+    eprintln!("{values:?} {value}");
+    Logged::Ok(())
+}
+
+/// A `Result`-like type which logs the values it unwraps.
+enum Logged<T> {
+    Ok(T),
+    Err,
+}
+
+impl Try for Logged<u32> {
+    type Output = u32;
+    type Residual = Logged<Infallible>;
+
+    fn from_output(output: u32) -> Self {
+        Logged::Ok(output)
+    }
+
+    fn branch(self) -> ControlFlow<Self::Residual, u32> {
+        match self {
+            Logged::Ok(value) => {
+                autoken::cap!(mut MyCap).push(value);
+                ControlFlow::Continue(value)
+            }
+            Logged::Err => ControlFlow::Break(Logged::Err),
+        }
+    }
+}
+
+impl<T> FromResidual<Logged<Infallible>> for Logged<T> {
+    fn from_residual(_residual: Logged<Infallible>) -> Self {
+        Logged::Err
+    }
+}
@@ -0,0 +1,49 @@
+// Ties which can be skipped on the way to a return are reported unless the function's diagnostics
+// were deselected.
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+fn maybe_tied<'a>(tie: bool) -> &'a Vec<u32> {
+    if tie {
+        autoken::tie!('a => ref MyCap);
+        //~^ WARN this tie of 'a does not run on every path to a return
+    }
+
+    autoken::cap!(ref MyCap)
+}
+
+fn always_tied<'a>(tie: bool) -> &'a Vec<u32> {
+    autoken::tie!('a => ref MyCap);
+
+    if tie {
+        eprintln!("tied");
+    }
+
+    autoken::cap!(ref MyCap)
+}
+
+mod legacy {
+    autoken::skip_module!();
+
+    pub fn maybe_tied<'a>(tie: bool) -> &'a Vec<u32> {
+        if tie {
+            autoken::tie!('a => ref super::MyCap);
+        }
+
+        autoken::cap!(ref super::MyCap)
+    }
+}
+
+fn main() {
+    let mut values = vec![1, 2, 3];
+
+    autoken::cap! {
+        MyCap: &mut values
+    =>
+        eprintln!("{:?}", maybe_tied(true));
+        eprintln!("{:?}", always_tied(true));
+        eprintln!("{:?}", legacy::maybe_tied(true));
+    }
+}
@@ -0,0 +1,43 @@
+//@ rustc-env: AUTOKEN_WARN_UNUSED_ACQUIRES=yes
+
+// Acquiring a `Borrows` object without using it still restricts callers.
+
+autoken::cap! {
+    pub MyCap = Vec<u32>;
+}
+
+fn never_used() {
+    let _ = autoken::BorrowsOne::<MyCap>::acquire_mut();
+    //~^ WARN this `Borrows` object is never used but still borrows token MyCap mutably
+    eprintln!("Nothing to see here!");
+}
+
+fn dropped_immediately() {
+    autoken::BorrowsOne::<MyCap>::acquire_ref(); //~ WARN still borrows token MyCap immutably
+}
+
+fn borrowed_elsewhere() {
+    let _ = autoken::BorrowsOne::<MyCap>::acquire_ref();
+    autoken::cap!(mut MyCap).push(1);
+}
+
+fn used() {
+    let borrows = autoken::BorrowsOne::<MyCap>::acquire_mut();
+    MyCap::get_mut(borrows, |v| v).push(2);
+}
+
+fn declared() {
+    autoken::tie!(mut MyCap);
+}
+
+fn main() {
+    autoken::cap! {
+        MyCap: &mut Vec::new()
+    =>
+        never_used();
+        dropped_immediately();
+        borrowed_elsewhere();
+        used();
+        declared();
+    }
+}
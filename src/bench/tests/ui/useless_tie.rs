@@ -0,0 +1,19 @@
+fn main() {
+    eprintln!("{}", unrelated());
+    eprintln!("{}", legacy::unrelated());
+}
+
+fn unrelated() -> u32 {
+    autoken::tie!(set ());
+    //~^ WARN this `tie!` acquires no tokens and has no effect
+    4
+}
+
+mod legacy {
+    autoken::skip_module!();
+
+    pub fn unrelated() -> u32 {
+        autoken::tie!(set ());
+        4
+    }
+}
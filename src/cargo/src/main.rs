@@ -1,13 +1,14 @@
 use std::{
+    cell::Cell,
     env,
     fs::{self, File},
-    io::Write,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Stdio},
 };
 
 use anyhow::Context;
-use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use directories::ProjectDirs;
 use rustc_build_sysroot::{SysrootBuilder, SysrootConfig};
 
@@ -27,14 +28,46 @@ include!("../INTERFACE_VERSION.in");
     no_binary_name = true,
 )]
 struct Cli {
+    #[arg(
+        long = "color",
+        global = true,
+        help = "Determines whether diagnostics and cargo's output are colored. Defaults to `auto`, \
+                which only colors output written to a terminal.",
+        default_value = None
+    )]
+    color: Option<CliColorMode>,
+
+    #[arg(
+        long = "print",
+        value_name = "FIELD",
+        help = "Print a single value describing this installation to stdout, without a label, and \
+                exit. This is meant for scripts which would otherwise have to parse the output of \
+                `metadata`.",
+        default_value = None
+    )]
+    print: Option<CliPrintField>,
+
     #[command(subcommand)]
-    cmd: CliCmd,
+    cmd: Option<CliCmd>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+enum CliPrintField {
+    Sysroot,
+    CacheDir,
+    RustcWrapper,
+    RustcHash,
 }
 
 #[derive(Debug, Subcommand)]
 enum CliCmd {
     #[command(about = "Analyze the specified program.")]
     Check(CliCmdCheck),
+    #[command(
+        about = "Analyze the specified program and list every `unsafe` tie along with its \
+                 justification."
+    )]
+    Audit(CliCmdCheck),
     #[command(about = "Run autoken's version of rustc.")]
     Rustc {
         #[command(flatten)]
@@ -47,7 +80,24 @@ enum CliCmd {
         args: CliRustcArgs,
     },
     #[command(about = "Print metadata about this cargo-autoken installation.")]
-    Metadata,
+    Metadata {
+        #[arg(
+            long = "json",
+            help = "Print the metadata as a single JSON object instead of as `key: value` lines.",
+            default_value_t = false
+        )]
+        json: bool,
+    },
+    #[command(about = "Print a detailed explanation of an analyzer diagnostic.")]
+    Explain {
+        #[arg(
+            help = "The slug of the diagnostic to explain (e.g. `conflicting-borrows`). Lists all \
+                    known slugs if omitted."
+        )]
+        slug: Option<String>,
+    },
+    #[command(about = "Check the environment for common setup problems and suggest fixes.")]
+    Doctor,
     #[command(about = "Clean cargo-autoken's global cache directory.")]
     ClearCache,
     #[command(about = "Emit the embedded rustc wrapper binary into the target path.")]
@@ -87,19 +137,112 @@ struct CliCmdCheck {
     #[arg(
         short = 'O',
         long = "target-dir",
-        help = "Specify a custom cargo target directory into which the project will be compiled and analyzed.",
+        help = "Specify a custom cargo target directory into which the project will be compiled and analyzed. \
+                Using a directory other than the one used by regular cargo builds keeps the two from \
+                blocking each other or invalidating each other's artifacts.",
         default_value = None,
     )]
     target_dir: Option<PathBuf>,
 
+    #[arg(
+        long = "tmp",
+        help = "Compile and analyze the project in a fresh temporary target directory which is removed \
+                once the check completes. This leaves the project's own target directory untouched.",
+        default_value_t = false,
+        conflicts_with = "target_dir"
+    )]
+    tmp: bool,
+
     #[arg(
         short = 'W',
         long = "old-artifacts",
         help = "Specifies how we should handle cargo target directories generated by other cargo-autoken \
-                versions.",
-        default_value = "warn"
+                versions. Defaults to `warn`.",
+        default_value = None
+    )]
+    old_artifact_mode: Option<CliOldArtifactMode>,
+
+    #[arg(
+        long = "keep-going",
+        help = "Continue analyzing the remaining crates after one of them fails and summarize which \
+                packages failed once cargo exits.",
+        default_value_t = false
+    )]
+    keep_going: bool,
+
+    #[arg(
+        long = "warn-dynamic-boundaries",
+        help = "Emit a note at every dynamically dispatched call through which tokens cannot flow.",
+        default_value_t = false
+    )]
+    warn_dynamic_boundaries: bool,
+
+    #[arg(
+        long = "warn-token-swaps",
+        help = "Emit a note at every `mem::swap` or `mem::replace` which exchanges values borrowing \
+                the same token.",
+        default_value_t = false
+    )]
+    warn_token_swaps: bool,
+
+    #[arg(
+        long = "warn-absorbed-borrows",
+        help = "Emit a warning at every `absorb` which hides borrows of a token that its caller \
+                still borrows in a conflicting way.",
+        default_value_t = false
+    )]
+    warn_absorbed_borrows: bool,
+
+    #[arg(
+        long = "warn-unused-acquires",
+        help = "Emit a warning at every call acquiring a `Borrows` object which is never used but \
+                is the only reason its function borrows some token.",
+        default_value_t = false
+    )]
+    warn_unused_acquires: bool,
+
+    #[arg(
+        long = "only",
+        value_name = "PATH",
+        help = "Only report diagnostics for functions whose path (e.g. `my_crate::module::func`) \
+                is or is nested within PATH. Every function is still analyzed. Can be specified \
+                several times."
     )]
-    old_artifact_mode: CliOldArtifactMode,
+    only: Vec<String>,
+
+    #[arg(
+        long = "since",
+        value_name = "REV",
+        help = "Only report diagnostics for functions defined in files which changed since the git \
+                revision REV, including uncommitted and untracked files. Every function is still \
+                analyzed. This is mostly useful for speeding up CI checks."
+    )]
+    since: Option<String>,
+
+    #[arg(
+        long = "max-errors",
+        value_name = "N",
+        help = "Stop analyzing a crate once N errors have been reported for it. By default, every \
+                error is reported."
+    )]
+    max_errors: Option<usize>,
+
+    #[arg(
+        long = "analyze-deps",
+        help = "Re-analyze the project's path and workspace dependencies rather than relying on the \
+                analysis metadata left behind by previous checks.",
+        default_value_t = false
+    )]
+    analyze_deps: bool,
+
+    #[arg(
+        long = "emit-metadata-only",
+        help = "Only generate the analysis metadata which dependents of each crate rely upon \
+                without checking the crates themselves. This is useful for precomputing the \
+                metadata of trusted dependencies.",
+        default_value_t = false
+    )]
+    emit_metadata_only: bool,
 
     // Cargo options
     #[command(flatten)]
@@ -113,16 +256,52 @@ enum CliOldArtifactMode {
     Ignore,
 }
 
-#[derive(Debug, Args)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+enum CliColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl CliColorMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            CliColorMode::Auto => "auto",
+            CliColorMode::Always => "always",
+            CliColorMode::Never => "never",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+enum CliToolchainCheckMode {
+    Error,
+    Warn,
+    Off,
+}
+
+#[derive(Debug, Default, Args)]
 struct CliBinaryOverrides {
     #[arg(
         short = 'I',
         long = "disable-toolchain-checks",
-        help = "Disable calling cargo version integrity checks.",
-        default_value_t = false
+        help = "Disable calling cargo version integrity checks. This is equivalent to \
+                `--toolchain-check=off`.",
+        default_value_t = false,
+        conflicts_with = "toolchain_check"
     )]
     disable_toolchain_checks: bool,
 
+    #[arg(
+        long = "toolchain-check",
+        help = "Determines how a mismatch between the calling cargo's toolchain and the toolchain \
+                against which AuToken was built is reported. `warn` reports the mismatch but \
+                proceeds anyways, which can be useful for experimenting with nearby nightlies. \
+                Defaults to `error`.",
+        default_value = None
+    )]
+    toolchain_check: Option<CliToolchainCheckMode>,
+
     #[arg(
         short = 'C',
         long = "custom-cargo",
@@ -134,7 +313,8 @@ struct CliBinaryOverrides {
     #[arg(
         short = 'R',
         long = "custom-rustc-wrapper",
-        help = "Use a custom rustc wrapper executable to check this project.",
+        help = "Use a custom rustc wrapper executable to check this project. This can also be set \
+                through the `AUTOKEN_RUSTC_WRAPPER` environment variable.",
         default_value = None,
     )]
     custom_rustc_wrapper: Option<PathBuf>,
@@ -146,9 +326,73 @@ struct CliBinaryOverrides {
         default_value_t = false
     )]
     disable_interface_checks: bool,
+
+    #[arg(
+        long = "offline",
+        help = "Run every cargo invocation, including those building the sysroot, without accessing \
+                the network. This is implied by setting `CARGO_NET_OFFLINE=true`.",
+        default_value_t = false
+    )]
+    offline: bool,
+
+    #[arg(
+        long = "locked",
+        help = "Require every cargo invocation, including those building the sysroot, to leave its \
+                `Cargo.lock` unchanged.",
+        default_value_t = false
+    )]
+    locked: bool,
+
+    #[arg(
+        long = "frozen",
+        help = "Equivalent to specifying both `--locked` and `--offline`.",
+        default_value_t = false
+    )]
+    frozen: bool,
+
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        help = "Suppress cargo's progress output, including that of the sysroot build which is \
+                otherwise shown while it runs.",
+        default_value_t = false
+    )]
+    quiet: bool,
 }
 
-#[derive(Debug, Args)]
+impl CliBinaryOverrides {
+    fn toolchain_check_mode(&self) -> CliToolchainCheckMode {
+        if self.disable_toolchain_checks {
+            CliToolchainCheckMode::Off
+        } else {
+            self.toolchain_check.unwrap_or(CliToolchainCheckMode::Error)
+        }
+    }
+
+    fn is_offline(&self) -> bool {
+        self.offline || self.frozen || env::var("CARGO_NET_OFFLINE").is_ok_and(|v| v == "true")
+    }
+
+    fn is_locked(&self) -> bool {
+        self.locked || self.frozen
+    }
+
+    fn cargo_global_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        if self.is_locked() {
+            args.push("--locked".to_string());
+        }
+
+        if self.is_offline() {
+            args.push("--offline".to_string());
+        }
+
+        args
+    }
+}
+
+#[derive(Debug, Default, Args)]
 struct CliRustcOverrides {
     #[arg(
         short = 'S',
@@ -165,6 +409,14 @@ struct CliRustcOverrides {
         default_value = None,
     )]
     target_triple: Option<String>,
+
+    #[arg(
+        long = "no-sysroot-build",
+        help = "Fail instead of building AuToken's sysroot if `custom-rustc-sysroot` is not specified \
+                or does not contain a sysroot for the target.",
+        default_value_t = false
+    )]
+    no_sysroot_build: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -177,7 +429,14 @@ enum CliRustcArgs {
     Metadata,
     #[command(name = "with", about = "Run rustc with the specified arguments")]
     With {
-        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        // N.B. we intentionally forward `@argfile` arguments verbatim. The rustc driver expands
+        // them itself, which keeps the wrapper's own command line short.
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            help = "The arguments to pass to rustc. Arguments of the form `@path` are expanded by rustc \
+                    into the newline-delimited arguments contained in the file at `path`."
+        )]
         rustc_args: Vec<String>,
     },
 }
@@ -207,83 +466,33 @@ fn main() -> anyhow::Result<()> {
     });
 
     // Handle CLI
-    match cli.cmd {
-        CliCmd::Check(args) => {
-            // Get the binary collection.
-            let bin = BinaryCollection::new(&mut app_dir, &args.binary_overrides)?;
-
-            let (target_triple, rustc_sysroot_path) =
-                prepare_rust_wrapper(&mut app_dir, &bin, &args.rustc_overrides)?;
-
-            // Determine the target artifact directory for our compilation.
-            let target_dir = match args.target_dir {
-                Some(path) => path,
-                None => {
-                    let meta = args.manifest.metadata().exec().context(
-                        "Failed to get cargo metadata. This was performed in order to customize \
-                         the cargo target directory and can be skipped by setting it manually \
-                         by setting the `target-dir` parameter.",
-                    )?;
-                    let mut target_dir = PathBuf::from(meta.target_directory);
-                    target_dir.push("autoken");
-
-                    // Try to remove the all autoken directories which don't belong to us.
-                    if args.old_artifact_mode != CliOldArtifactMode::Ignore {
-                        if let Ok(item_list) = fs::read_dir(&target_dir) {
-                            for item in item_list.flatten() {
-                                if item.file_name() != rustc_wrapper_hash() {
-                                    let path = item.path();
-
-                                    if args.old_artifact_mode == CliOldArtifactMode::Warn {
-                                        eprintln!(
-                                            "The target artifact directory {} was created by a \
-                                            different version of cargo-autoken and is likely wasting \
-                                            space. If you wish to have these directories automatically \
-                                            removed, set the `old-artifacts` parameter to `delete`. \
-                                            If you wish to suppress this warning, set the parameter \
-                                            to `ignore`.",
-                                            path.to_string_lossy(),
-                                        );
-                                    } else {
-                                        let _ = fs::remove_dir_all(path);
-                                    }
-                                }
-                            }
-                        }
-                    }
-
-                    target_dir.push(rustc_wrapper_hash());
-                    target_dir
-                }
-            };
-
-            // Call out to cargo to do the actual work!
-            let mut cmd = bin.cargo_cmd(bin.rustc_cmd(false, Some(rustc_sysroot_path)));
-            cmd.arg("check")
-                .arg("--target")
-                .arg(target_triple)
-                .env("CARGO_TARGET_DIR", target_dir);
-
-            if let Some(path) = args.manifest.manifest_path {
-                cmd.arg("--path").arg(path);
-            }
+    let cmd = match (cli.print, cli.cmd) {
+        (Some(field), None) => return run_print(&mut app_dir, field, cli.color),
+        (None, Some(cmd)) => cmd,
+        (Some(_), Some(_)) => Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "`--print` cannot be combined with a subcommand",
+            )
+            .exit(),
+        (None, None) => Cli::command()
+            .error(
+                ErrorKind::MissingSubcommand,
+                "either a subcommand or `--print` must be specified",
+            )
+            .exit(),
+    };
 
-            std::process::exit(
-                cmd.spawn()
-                    .context("failed to spawn cargo")?
-                    .wait_with_output()?
-                    .status
-                    .code()
-                    .unwrap_or(1),
-            );
-        }
+    match cmd {
+        CliCmd::Check(args) => run_check(&mut app_dir, args, cli.color, false),
+        CliCmd::Audit(args) => run_check(&mut app_dir, args, cli.color, true),
         CliCmd::Rustc {
             binary_overrides,
             rustc_overrides,
             args,
         } => {
             // Get the binary collection.
-            let bin = BinaryCollection::new(&mut app_dir, &binary_overrides)?;
+            let bin = BinaryCollection::new(&mut app_dir, &binary_overrides, cli.color)?;
 
             let (target_triple, rustc_sysroot_path) =
                 prepare_rust_wrapper(&mut app_dir, &bin, &rustc_overrides)?;
@@ -321,7 +530,32 @@ fn main() -> anyhow::Result<()> {
                 ),
             }
         }
-        CliCmd::Metadata => {
+        CliCmd::Metadata { json: true } => {
+            let json_path = |path: anyhow::Result<PathBuf>| match path {
+                Ok(path) => json_str(&path.to_string_lossy()),
+                Err(_) => "null".to_string(),
+            };
+
+            let fields = [
+                ("cargo_autoken_version", json_str(env!("CARGO_PKG_VERSION"))),
+                ("rustc_wrapper_version", json_str(rustc_wrapper_version())),
+                ("rustc_wrapper_hash", json_str(rustc_wrapper_hash())),
+                ("cache_dir", json_path(get_cache_dir())),
+                ("calling_cargo_path", json_path(get_calling_cargo())),
+                ("interface_supported_range", json_str(SUPPORTED_RANGE)),
+                ("interface_deprecated_range", json_str(DEPRECATED_RANGE)),
+            ];
+
+            let fields = fields
+                .iter()
+                .map(|(key, value)| format!("{}: {value}", json_str(key)))
+                .collect::<Vec<_>>();
+
+            println!("{{ {} }}", fields.join(", "));
+
+            Ok(())
+        }
+        CliCmd::Metadata { json: false } => {
             println!("cargo-autoken-version: {}", env!("CARGO_PKG_VERSION"));
             println!("rustc-wrapper-version: {}", rustc_wrapper_version());
             println!("rustc-wrapper-hash: {}", rustc_wrapper_hash());
@@ -338,6 +572,31 @@ fn main() -> anyhow::Result<()> {
 
             Ok(())
         }
+        CliCmd::Explain { slug: None } => {
+            for (slug, _) in EXPLANATIONS {
+                println!("{slug}");
+            }
+
+            Ok(())
+        }
+        CliCmd::Explain { slug: Some(slug) } => {
+            let Some((_, explanation)) = EXPLANATIONS.iter().find(|(other, _)| *other == slug)
+            else {
+                anyhow::bail!(
+                    "no explanation exists for the diagnostic `{slug}`. The known diagnostics are: {}",
+                    EXPLANATIONS
+                        .iter()
+                        .map(|(slug, _)| format!("`{slug}`"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                );
+            };
+
+            print!("{explanation}");
+
+            Ok(())
+        }
+        CliCmd::Doctor => run_doctor(),
         CliCmd::ClearCache => {
             let cache_dir = get_cache_dir().context("failed to get cache directory")?;
             eprintln!("Deleting {}", cache_dir.to_string_lossy());
@@ -356,7 +615,7 @@ fn main() -> anyhow::Result<()> {
             path,
         } => {
             // Get the binary collection.
-            let bin = BinaryCollection::new(&mut app_dir, &binary_overrides)?;
+            let bin = BinaryCollection::new(&mut app_dir, &binary_overrides, cli.color)?;
 
             // Get the target.
             let target = match target {
@@ -366,16 +625,18 @@ fn main() -> anyhow::Result<()> {
             };
 
             // Build the requested sysroot.
-            eprintln!(
-                "Building sysroot for target {target} in path {}...",
-                path.to_string_lossy()
-            );
+            if !bin.quiet {
+                eprintln!(
+                    "Building sysroot for target {target} in path {}...",
+                    path.to_string_lossy()
+                );
+            }
 
             build_sysroot(
                 &path,
                 &target,
                 bin.rustc_cmd(true, None),
-                bin.cargo_cmd(bin.rustc_cmd(true, None)),
+                bin.sysroot_cargo_cmd(bin.rustc_cmd(true, None)),
             )?;
 
             Ok(())
@@ -388,12 +649,18 @@ struct BinaryCollection {
     cargo_exe: PathBuf,
     rustc_wrapper_path: PathBuf,
     disable_interface_checks: bool,
+    locked: bool,
+    offline: bool,
+    quiet: bool,
+    color: Option<CliColorMode>,
+    warned_about_wrappers: Cell<bool>,
 }
 
 impl BinaryCollection {
     pub fn new(
         app_dir: &mut LazilyComputed<'_, ProjectDirs>,
         args: &CliBinaryOverrides,
+        color: Option<CliColorMode>,
     ) -> anyhow::Result<Self> {
         // Get a path to cargo.
         let cargo_exe = match &args.custom_cargo {
@@ -406,41 +673,26 @@ impl BinaryCollection {
 
         // Ensure that cargo's `rustc` version string against which `cargo`'s linker path is
         // provided is appropriate for our rustc binary.
-        if !args.disable_toolchain_checks {
-            let mut cargo_rustc_exe = cargo_exe.clone();
-            if cfg!(windows) {
-                cargo_rustc_exe.set_file_name("rustc.exe");
-            } else {
-                cargo_rustc_exe.set_file_name("rustc");
-            }
-
-            let cargo_rustc_version =
-                get_rustc_version_str(&cargo_rustc_exe).with_context(|| {
-                    format!(
-                        "Failed to determine version of the rustc binary with which the invoking \
-                     cargo command was distributed (expected path: {}). This is for an integrity \
-                     check so, if this cannot be satisfied, you can bypass this check entirely \
-                     by setting the `disable-toolchain-checks` flag.",
-                        cargo_rustc_exe.to_string_lossy(),
-                    )
-                })?;
-
-            if cargo_rustc_version.lines().next() != Some(rustc_wrapper_version()) {
-                anyhow::bail!(
-                    "The version of rustc (path: {}) with which cargo was bundled was {:?} but \
-                     autoken's rustc version was {:?}. Make sure to call cargo with the appropriate \
-                     toolchain parameter to avoid dynamic linker errors. If this is a false positive, \
-                     you can bypass this check by setting the `disable-toolchain-checks` flag.",
-                    cargo_rustc_exe.to_string_lossy(),
-                    cargo_rustc_version,
-                    env!("AUTOKEN_EXPECTED_RUSTC_VERSION"),
-                );
+        match args.toolchain_check_mode() {
+            CliToolchainCheckMode::Error => check_toolchain(&cargo_exe)?,
+            CliToolchainCheckMode::Warn => {
+                if let Err(err) = check_toolchain(&cargo_exe) {
+                    eprintln!("cargo-autoken: warning: {err:#}");
+                }
             }
+            CliToolchainCheckMode::Off => {}
         }
 
-        // Get our rustc wrapper.
-        let rustc_wrapper_path = match &args.custom_rustc_wrapper {
-            Some(path) => path.clone(),
+        // Get our rustc wrapper. The environment variable lets scripts iterating on the wrapper
+        // itself skip extracting the embedded one.
+        let custom_rustc_wrapper = args.custom_rustc_wrapper.clone().or_else(|| {
+            env::var_os("AUTOKEN_RUSTC_WRAPPER")
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        });
+
+        let rustc_wrapper_path = match custom_rustc_wrapper {
+            Some(path) => path,
             None => {
                 // Determine its path
                 let mut path = app_dir
@@ -455,11 +707,7 @@ impl BinaryCollection {
 
                 let file_name = format!("autoken_rustc_wrapper_{}", rustc_wrapper_hash());
 
-                if cfg!(windows) {
-                    path.push(&format!("{file_name}.exe"));
-                } else {
-                    path.push(&file_name);
-                }
+                path.push(format!("{file_name}{}", env::consts::EXE_SUFFIX));
 
                 // Extract it
                 write_rustc_wrapper_exe(&path).with_context(|| {
@@ -479,13 +727,72 @@ impl BinaryCollection {
             cargo_exe,
             rustc_wrapper_path,
             disable_interface_checks: args.disable_interface_checks,
+            locked: args.is_locked(),
+            offline: args.is_offline(),
+            quiet: args.quiet,
+            color,
+            warned_about_wrappers: Cell::new(false),
         })
     }
 
     pub fn cargo_cmd(&self, rustc: Command) -> Command {
+        let mut cmd = self.sysroot_cargo_cmd(rustc);
+
+        if self.locked {
+            cmd.arg("--locked");
+        }
+
+        cmd
+    }
+
+    /// Like `cargo_cmd` but without `--locked`. The sysroot builder generates its own workspace
+    /// around a copy of `rust-src`'s lockfile which cargo always has to amend. The versions of the
+    /// standard library's dependencies are still pinned by that lockfile.
+    pub fn sysroot_cargo_cmd(&self, rustc: Command) -> Command {
         let mut cmd = Command::new(&self.cargo_exe);
+
+        // N.B. this is a global cargo flag so it can precede whichever subcommand the caller picks.
+        if self.offline {
+            cmd.arg("--offline");
+        }
+
+        if self.quiet {
+            cmd.arg("--quiet");
+        }
+
+        if let Some(color) = self.color {
+            cmd.arg("--color").arg(color.as_str());
+        }
+
         cmd.env("RUSTC", rustc.get_program());
-        cmd.envs(rustc.get_envs().filter_map(|(a, b)| Some((a, b?))));
+
+        // Wrappers such as `sccache` can replay a cached compilation without ever running our
+        // analyzer so we disable them for the crates we compile. N.B. an empty value also overrides
+        // any `build.rustc-wrapper` set in the user's cargo config.
+        for var in ["RUSTC_WRAPPER", "RUSTC_WORKSPACE_WRAPPER"] {
+            if let Some(wrapper) = env::var_os(var).filter(|v| !v.is_empty()) {
+                if !self.warned_about_wrappers.get() {
+                    eprintln!(
+                        "cargo-autoken: ignoring `{var}={}` since rustc wrappers may skip \
+                         AuToken's analysis by replaying cached compilations",
+                        wrapper.to_string_lossy(),
+                    );
+                }
+            }
+
+            cmd.env(var, "");
+        }
+        self.warned_about_wrappers.set(true);
+
+        // N.B. removals matter too since cargo would otherwise forward our own environment to the
+        // wrapper (e.g. an `AUTOKEN_SKIP_ANALYSIS` inherited from the user's shell).
+        for (key, value) in rustc.get_envs() {
+            match value {
+                Some(value) => cmd.env(key, value),
+                None => cmd.env_remove(key),
+            };
+        }
+
         if !self.disable_interface_checks {
             cmd.env("AUTOKEN_ANALYZER_VERSION", env!("CARGO_PKG_VERSION"));
             cmd.env("AUTOKEN_ANALYZER_SUPPORTED_RANGE", SUPPORTED_RANGE);
@@ -517,10 +824,326 @@ impl BinaryCollection {
             cmd.env_remove("AUTOKEN_OVERRIDE_SYSROOT");
         }
 
+        match self.color {
+            Some(color) => cmd.env("AUTOKEN_COLOR", color.as_str()),
+            None => cmd.env_remove("AUTOKEN_COLOR"),
+        };
+
         cmd
     }
 }
 
+fn run_print(
+    app_dir: &mut LazilyComputed<'_, ProjectDirs>,
+    field: CliPrintField,
+    color: Option<CliColorMode>,
+) -> anyhow::Result<()> {
+    let value = match field {
+        CliPrintField::Sysroot => {
+            let bin = BinaryCollection::new(app_dir, &CliBinaryOverrides::default(), color)?;
+            let rustc_overrides = CliRustcOverrides::default();
+            let (_, sysroot) = prepare_rust_wrapper(app_dir, &bin, &rustc_overrides)?;
+            sysroot.to_string_lossy().into_owned()
+        }
+        CliPrintField::CacheDir => get_cache_dir()
+            .context("failed to get cache directory")?
+            .to_string_lossy()
+            .into_owned(),
+        CliPrintField::RustcWrapper => {
+            BinaryCollection::new(app_dir, &CliBinaryOverrides::default(), color)?
+                .rustc_wrapper_path
+                .to_string_lossy()
+                .into_owned()
+        }
+        CliPrintField::RustcHash => rustc_wrapper_hash().to_string(),
+    };
+
+    println!("{value}");
+
+    Ok(())
+}
+
+fn run_check(
+    app_dir: &mut LazilyComputed<'_, ProjectDirs>,
+    mut args: CliCmdCheck,
+    color: Option<CliColorMode>,
+    audit: bool,
+) -> anyhow::Result<()> {
+    // Apply the defaults specified by the project's manifest.
+    let meta = {
+        let mut cmd = args.manifest.metadata();
+        cmd.no_deps();
+
+        cmd.other_options(args.binary_overrides.cargo_global_args());
+
+        cmd.exec()
+    };
+
+    match &meta {
+        Ok(meta) => apply_manifest_defaults(&mut args, meta)?,
+        Err(_) if args.target_dir.is_some() || args.tmp => {}
+        Err(err) => anyhow::bail!(
+            "Failed to get cargo metadata: {err}. This was performed in order to read the \
+             project's `[package.metadata.autoken]` defaults and to customize the cargo \
+             target directory and can be skipped by setting the `target-dir` parameter \
+             manually or by passing `--tmp`."
+        ),
+    }
+
+    let old_artifact_mode = args.old_artifact_mode.unwrap_or(CliOldArtifactMode::Warn);
+
+    // Get the binary collection.
+    let bin = BinaryCollection::new(app_dir, &args.binary_overrides, color)?;
+
+    let (target_triple, rustc_sysroot_path) =
+        prepare_rust_wrapper(app_dir, &bin, &args.rustc_overrides)?;
+
+    // Determine the target artifact directory for our compilation.
+    let tmp_dir = args
+        .tmp
+        .then(|| tempfile::Builder::new().prefix("autoken-").tempdir())
+        .transpose()
+        .context("failed to create a temporary target directory")?;
+
+    let target_dir = match (args.target_dir, &tmp_dir) {
+        (Some(path), _) => path,
+        (None, Some(tmp_dir)) => tmp_dir.path().to_path_buf(),
+        (None, None) => {
+            // N.B. we already bailed if we failed to fetch metadata without a `target-dir`.
+            let mut target_dir = PathBuf::from(&meta.unwrap().target_directory);
+            target_dir.push("autoken");
+
+            // Try to remove the all autoken directories which don't belong to us.
+            if old_artifact_mode != CliOldArtifactMode::Ignore {
+                if let Ok(item_list) = fs::read_dir(&target_dir) {
+                    for item in item_list.flatten() {
+                        if item.file_name() != rustc_wrapper_hash() {
+                            let path = item.path();
+
+                            if old_artifact_mode == CliOldArtifactMode::Warn {
+                                eprintln!(
+                                    "The target artifact directory {} was created by a \
+                                    different version of cargo-autoken and is likely wasting \
+                                    space. If you wish to have these directories automatically \
+                                    removed, set the `old-artifacts` parameter to `delete`. \
+                                    If you wish to suppress this warning, set the parameter \
+                                    to `ignore`.",
+                                    path.to_string_lossy(),
+                                );
+                            } else {
+                                let _ = fs::remove_dir_all(path);
+                            }
+                        }
+                    }
+                }
+            }
+
+            target_dir.push(rustc_wrapper_hash());
+            target_dir
+        }
+    };
+
+    // Force cargo to recompile our local packages so that their metadata is regenerated.
+    if args.analyze_deps {
+        clean_path_packages(
+            &bin,
+            &args.manifest,
+            bin.rustc_cmd(false, Some(rustc_sysroot_path)),
+            &target_triple,
+            &target_dir,
+        )?;
+    }
+
+    // Call out to cargo to do the actual work!
+    let mut cmd = bin.cargo_cmd(bin.rustc_cmd(false, Some(rustc_sysroot_path)));
+    cmd.arg("check")
+        .arg("--target")
+        .arg(target_triple)
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .env("AUTOKEN_META_DIR", &target_dir);
+
+    if args.warn_dynamic_boundaries {
+        cmd.env("AUTOKEN_WARN_DYNAMIC_BOUNDARIES", "yes");
+    } else {
+        cmd.env_remove("AUTOKEN_WARN_DYNAMIC_BOUNDARIES");
+    }
+
+    if args.warn_token_swaps {
+        cmd.env("AUTOKEN_WARN_TOKEN_SWAPS", "yes");
+    } else {
+        cmd.env_remove("AUTOKEN_WARN_TOKEN_SWAPS");
+    }
+
+    if args.warn_absorbed_borrows {
+        cmd.env("AUTOKEN_WARN_ABSORBED_BORROWS", "yes");
+    } else {
+        cmd.env_remove("AUTOKEN_WARN_ABSORBED_BORROWS");
+    }
+
+    if args.warn_unused_acquires {
+        cmd.env("AUTOKEN_WARN_UNUSED_ACQUIRES", "yes");
+    } else {
+        cmd.env_remove("AUTOKEN_WARN_UNUSED_ACQUIRES");
+    }
+
+    if audit {
+        cmd.env("AUTOKEN_AUDIT", "yes");
+    } else {
+        cmd.env_remove("AUTOKEN_AUDIT");
+    }
+
+    if args.emit_metadata_only {
+        cmd.env("AUTOKEN_METADATA_ONLY", "yes");
+    } else {
+        cmd.env_remove("AUTOKEN_METADATA_ONLY");
+    }
+
+    if args.only.is_empty() {
+        cmd.env_remove("AUTOKEN_ONLY");
+    } else {
+        cmd.env("AUTOKEN_ONLY", args.only.join(","));
+    }
+
+    match &args.since {
+        Some(rev) => {
+            // The project being checked isn't necessarily in the repository we were invoked from.
+            let project_dir = args
+                .manifest
+                .manifest_path
+                .as_deref()
+                .and_then(Path::parent)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+
+            let changed = get_changed_files(rev, project_dir)
+                .with_context(|| format!("failed to determine the files changed since `{rev}`"))?;
+
+            cmd.env(
+                "AUTOKEN_CHANGED_FILES",
+                env::join_paths(changed).context("failed to encode the list of changed files")?,
+            )
+        }
+        None => cmd.env_remove("AUTOKEN_CHANGED_FILES"),
+    };
+
+    match args.max_errors {
+        Some(max_errors) => cmd.env("AUTOKEN_MAX_ERRORS", max_errors.to_string()),
+        None => cmd.env_remove("AUTOKEN_MAX_ERRORS"),
+    };
+
+    // Rustc runs in whichever directory cargo picks so we resolve relative dump paths against the
+    // directory the user invoked us from.
+    if let Some(path) = env::var_os("AUTOKEN_DUMP_CALLGRAPH").filter(|v| !v.is_empty()) {
+        cmd.env(
+            "AUTOKEN_DUMP_CALLGRAPH",
+            env::current_dir()
+                .context("failed to resolve the `AUTOKEN_DUMP_CALLGRAPH` path")?
+                .join(path),
+        );
+    }
+
+    if let Some(path) = args.manifest.manifest_path {
+        cmd.arg("--manifest-path").arg(path);
+    }
+
+    let exit_code = if args.keep_going {
+        cmd.arg("--keep-going");
+        run_cargo_keep_going(cmd)?
+    } else {
+        cmd.spawn()
+            .context("failed to spawn cargo")?
+            .wait_with_output()?
+            .status
+            .code()
+            .unwrap_or(1)
+    };
+
+    // N.B. `process::exit` doesn't run destructors so we have to remove the temporary directory
+    // ourselves.
+    if let Some(tmp_dir) = tmp_dir {
+        tmp_dir
+            .close()
+            .context("failed to remove the temporary target directory")?;
+    }
+
+    std::process::exit(exit_code);
+}
+
+fn run_doctor() -> anyhow::Result<()> {
+    let mut failures = 0;
+    let mut check = |name: &str, fix: &str, f: &dyn Fn() -> anyhow::Result<String>| match f() {
+        Ok(detail) => println!("ok: {name} ({detail})"),
+        Err(err) => {
+            failures += 1;
+            println!("error: {name}: {err:#}");
+            println!("  fix: {fix}");
+        }
+    };
+
+    check(
+        "the active toolchain matches AuToken's",
+        &format!(
+            "run cargo-autoken through the toolchain for {:?}, e.g. by pinning that nightly in a \
+             `rust-toolchain.toml` file at the root of your project",
+            rustc_wrapper_version(),
+        ),
+        &|| {
+            check_toolchain(&get_calling_cargo()?)?;
+            Ok(rustc_wrapper_version().to_string())
+        },
+    );
+
+    check(
+        "the `rust-src` component is installed",
+        "run `rustup component add rust-src` for the toolchain AuToken uses",
+        &|| {
+            let rustc_exe = get_cargo_rustc(&get_calling_cargo()?);
+            let src = rustc_build_sysroot::rustc_sysroot_src(Command::new(rustc_exe))?;
+            anyhow::ensure!(src.exists(), "{} does not exist", src.to_string_lossy());
+
+            Ok(src.to_string_lossy().into_owned())
+        },
+    );
+
+    check(
+        "the cache directory is writable",
+        "make sure the directory can be created and written to, or pass `--custom-rustc-wrapper` \
+         and `--custom-rustc-sysroot` to avoid using it",
+        &|| {
+            let cache_dir = get_cache_dir()?;
+            fs::create_dir_all(&cache_dir)?;
+            tempfile::tempfile_in(&cache_dir)?;
+
+            Ok(cache_dir.to_string_lossy().into_owned())
+        },
+    );
+
+    check(
+        "a sysroot has been built for the host target",
+        "run `cargo autoken check` once to build it. This takes a few minutes but only happens \
+         once per toolchain",
+        &|| {
+            let cache_dir = get_cache_dir()?;
+            let rustc_exe = get_cargo_rustc(&get_calling_cargo()?);
+            let target = get_host_target(Command::new(rustc_exe))?;
+
+            anyhow::ensure!(
+                is_sysroot_built(&cache_dir, &target),
+                "no sysroot for {target} in {}",
+                cache_dir.to_string_lossy(),
+            );
+
+            Ok(target)
+        },
+    );
+
+    if failures > 0 {
+        anyhow::bail!("{failures} check(s) failed");
+    }
+
+    Ok(())
+}
+
 // === Helpers === //
 
 fn get_cache_dir() -> anyhow::Result<PathBuf> {
@@ -530,6 +1153,163 @@ fn get_cache_dir() -> anyhow::Result<PathBuf> {
     Ok(app_dir.cache_dir().to_path_buf())
 }
 
+fn apply_manifest_defaults(
+    args: &mut CliCmdCheck,
+    meta: &cargo_metadata::Metadata,
+) -> anyhow::Result<()> {
+    // Package-level settings take precedence over workspace-level settings.
+    let mut tables = vec![("[workspace.metadata.autoken]", &meta.workspace_metadata)];
+
+    if let Some(package) = meta.root_package() {
+        tables.push(("[package.metadata.autoken]", &package.metadata));
+    }
+
+    let mut target = None;
+    let mut old_artifact_mode = None;
+    let mut disable_toolchain_checks = None;
+    let mut toolchain_check = None;
+
+    for (table_name, table) in tables {
+        let Some(table) = table.get("autoken") else {
+            continue;
+        };
+
+        let table = table
+            .as_object()
+            .with_context(|| format!("`{table_name}` must be a table"))?;
+
+        for (key, value) in table {
+            let expected_ty = match key.as_str() {
+                "target" => {
+                    target = value.as_str().map(str::to_string);
+                    target.is_none().then_some("a string")
+                }
+                "old-artifacts" => {
+                    old_artifact_mode = value
+                        .as_str()
+                        .and_then(|v| CliOldArtifactMode::from_str(v, false).ok());
+
+                    old_artifact_mode
+                        .is_none()
+                        .then_some("one of `warn`, `delete`, or `ignore`")
+                }
+                "disable-toolchain-checks" => {
+                    disable_toolchain_checks = value.as_bool();
+                    disable_toolchain_checks.is_none().then_some("a boolean")
+                }
+                "toolchain-check" => {
+                    toolchain_check = value
+                        .as_str()
+                        .and_then(|v| CliToolchainCheckMode::from_str(v, false).ok());
+
+                    toolchain_check
+                        .is_none()
+                        .then_some("one of `error`, `warn`, or `off`")
+                }
+                _ => anyhow::bail!(
+                    "Unknown key `{key}` in `{table_name}`. The supported keys are `target`, \
+                     `old-artifacts`, `disable-toolchain-checks`, and `toolchain-check`."
+                ),
+            };
+
+            if let Some(expected_ty) = expected_ty {
+                anyhow::bail!("`{key}` in `{table_name}` must be {expected_ty}");
+            }
+        }
+    }
+
+    // Arguments passed on the command line take precedence over the manifest.
+    if args.rustc_overrides.target_triple.is_none() {
+        args.rustc_overrides.target_triple = target;
+    }
+
+    if args.old_artifact_mode.is_none() {
+        args.old_artifact_mode = old_artifact_mode;
+    }
+
+    let overrides = &mut args.binary_overrides;
+    if !overrides.disable_toolchain_checks && overrides.toolchain_check.is_none() {
+        overrides.disable_toolchain_checks = disable_toolchain_checks.unwrap_or(false);
+        overrides.toolchain_check = toolchain_check;
+    }
+
+    Ok(())
+}
+
+fn clean_path_packages(
+    bin: &BinaryCollection,
+    manifest: &clap_cargo::Manifest,
+    rustc: Command,
+    target_triple: &str,
+    target_dir: &Path,
+) -> anyhow::Result<()> {
+    let mut meta = manifest.metadata();
+    meta.other_options(
+        [(bin.locked, "--locked"), (bin.offline, "--offline")]
+            .into_iter()
+            .filter(|&(enabled, _)| enabled)
+            .map(|(_, flag)| flag.to_string())
+            .collect::<Vec<_>>(),
+    );
+
+    let meta = meta
+        .exec()
+        .context("failed to get cargo metadata to determine the project's path dependencies")?;
+
+    // Packages without a source are either workspace members or path dependencies.
+    let packages = meta
+        .packages
+        .iter()
+        .filter(|pkg| pkg.source.is_none())
+        .collect::<Vec<_>>();
+
+    if packages.is_empty() {
+        return Ok(());
+    }
+
+    let mut cmd = bin.cargo_cmd(rustc);
+    cmd.arg("clean")
+        .arg("--target")
+        .arg(target_triple)
+        .env("CARGO_TARGET_DIR", target_dir);
+
+    for pkg in packages {
+        cmd.arg("-p").arg(format!("{}@{}", pkg.name, pkg.version));
+    }
+
+    if let Some(path) = &manifest.manifest_path {
+        cmd.arg("--manifest-path").arg(path);
+    }
+
+    let status = cmd.status().context("failed to spawn cargo")?;
+    anyhow::ensure!(
+        status.success(),
+        "failed to clean the project's path dependencies ({status})"
+    );
+
+    Ok(())
+}
+
+fn json_str(str: &str) -> String {
+    let mut out = String::with_capacity(str.len() + 2);
+    out.push('"');
+
+    for char in str.chars() {
+        match char {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            char if char.is_control() => out.push_str(&format!("\\u{:04x}", char as u32)),
+            char => out.push(char),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
 fn get_calling_cargo() -> anyhow::Result<PathBuf> {
     Ok(PathBuf::from(
         env::var("CARGO").context("`CARGO` environment variable was not set")?,
@@ -565,6 +1345,130 @@ fn write_rustc_wrapper_exe(path: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn run_cargo_keep_going(mut cmd: Command) -> anyhow::Result<i32> {
+    // We intercept cargo's diagnostics to figure out which packages failed so we have to tell cargo
+    // to keep coloring its output even though it's no longer writing to a terminal.
+    if io::stderr().is_terminal() && env::var_os("CARGO_TERM_COLOR").is_none() {
+        cmd.env("CARGO_TERM_COLOR", "always");
+    }
+
+    let mut child = cmd
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to spawn cargo")?;
+
+    // Forward cargo's output verbatim while collecting the names of the packages which failed.
+    let mut failed_packages = Vec::new();
+
+    for line in BufReader::new(child.stderr.take().unwrap()).lines() {
+        let line = line.context("failed to read cargo's output")?;
+        eprintln!("{line}");
+
+        if let Some(package) = line
+            .split_once("could not compile `")
+            .and_then(|(_, rest)| rest.split_once('`'))
+            .map(|(package, _)| package.to_string())
+        {
+            if !failed_packages.contains(&package) {
+                failed_packages.push(package);
+            }
+        }
+    }
+
+    let code = child.wait()?.code().unwrap_or(1);
+
+    if !failed_packages.is_empty() {
+        eprintln!(
+            "cargo-autoken: {} package(s) failed to check: {}",
+            failed_packages.len(),
+            failed_packages.join(", "),
+        );
+    } else if code != 0 {
+        eprintln!("cargo-autoken: cargo exited with code {code}");
+    }
+
+    Ok(code)
+}
+
+fn get_cargo_rustc(cargo_exe: &Path) -> PathBuf {
+    // Like cargo itself, we honor an explicitly configured rustc.
+    if let Some(rustc) = env::var_os("RUSTC").filter(|v| !v.is_empty()) {
+        return PathBuf::from(rustc);
+    }
+
+    // Toolchains normally ship rustc right beside cargo. If it isn't there, cargo is likely being run
+    // from an unusual layout so we look for whichever rustc is on the `PATH`.
+    let rustc_name = format!("rustc{}", env::consts::EXE_SUFFIX);
+    let sibling = cargo_exe.with_file_name(&rustc_name);
+
+    if sibling.is_file() {
+        return sibling;
+    }
+
+    env::var_os("PATH")
+        .and_then(|paths| {
+            env::split_paths(&paths)
+                .map(|dir| dir.join(&rustc_name))
+                .find(|path| path.is_file())
+        })
+        .unwrap_or(sibling)
+}
+
+fn check_toolchain(cargo_exe: &Path) -> anyhow::Result<()> {
+    let cargo_rustc_exe = get_cargo_rustc(cargo_exe);
+
+    let cargo_rustc_version = get_rustc_version_str(&cargo_rustc_exe).with_context(|| {
+        format!(
+            "Failed to determine version of the rustc binary with which the invoking cargo \
+             command was distributed (expected path: {}). This is for an integrity check so, if \
+             this cannot be satisfied, you can bypass this check by setting the `toolchain-check` \
+             parameter to `warn` or `off`.",
+            cargo_rustc_exe.to_string_lossy(),
+        )
+    })?;
+
+    if cargo_rustc_version.lines().next() != Some(rustc_wrapper_version()) {
+        anyhow::bail!(
+            "The version of rustc (path: {}) with which cargo was bundled was {:?} but autoken's \
+             rustc version was {:?}. Make sure to call cargo with the appropriate toolchain \
+             parameter to avoid dynamic linker errors. If this is a false positive, you can bypass \
+             this check by setting the `toolchain-check` parameter to `warn` or `off`.",
+            cargo_rustc_exe.to_string_lossy(),
+            cargo_rustc_version,
+            env!("AUTOKEN_EXPECTED_RUSTC_VERSION"),
+        );
+    }
+
+    Ok(())
+}
+
+/// Lists the canonicalized paths of the files which changed since the git revision `rev` in the
+/// working tree of the repository containing `dir`. Deleted files are omitted.
+fn get_changed_files(rev: &str, dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let git = |args: &[&str]| -> anyhow::Result<String> {
+        let output = Command::new("git").args(args).current_dir(dir).output()?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "`git {}` failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let root = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim());
+    let diffed = git(&["diff", "--name-only", rev, "--"])?;
+    let untracked = git(&["ls-files", "--others", "--exclude-standard", "--full-name"])?;
+
+    Ok(diffed
+        .lines()
+        .chain(untracked.lines())
+        .filter_map(|path| root.join(path).canonicalize().ok())
+        .collect())
+}
+
 fn get_host_target(mut rust_cmd: Command) -> anyhow::Result<String> {
     Ok(String::from_utf8(rust_cmd.arg("-vV").output()?.stdout)?
         .lines()
@@ -589,15 +1493,42 @@ fn prepare_rust_wrapper<'a>(
 
     // Get a sysroot for our wrapper.
     let rustc_sysroot_path = match &args.custom_rustc_sysroot {
-        Some(path) => path,
+        Some(path) => {
+            if args.no_sysroot_build && !path.join("lib/rustlib").join(&target_triple).is_dir() {
+                anyhow::bail!(
+                    "The custom sysroot at {} does not contain a sysroot for target \
+                     {target_triple} and `no-sysroot-build` forbids building one.",
+                    path.to_string_lossy(),
+                );
+            }
+
+            path
+        }
+        None if args.no_sysroot_build => {
+            anyhow::bail!(
+                "No sysroot was provided and `no-sysroot-build` forbids building one. Build a \
+                 sysroot ahead of time with `cargo autoken build-sysroot` and specify it with the \
+                 `custom-rustc-sysroot` parameter."
+            );
+        }
         None => {
             let sysroot_dir = app_dir.get()?.cache_dir();
 
+            // The first build of the sysroot takes a few minutes so let the user know why we're
+            // stalling. Cargo's own progress output follows unless `--quiet` was specified.
+            if !bin.quiet && !is_sysroot_built(sysroot_dir, &target_triple) {
+                eprintln!(
+                    "Building AuToken's sysroot for target {target_triple} in path {}. This only \
+                     happens once per toolchain but may take a few minutes...",
+                    sysroot_dir.to_string_lossy(),
+                );
+            }
+
             build_sysroot(
                 sysroot_dir,
                 &target_triple,
                 bin.rustc_cmd(true, None),
-                bin.cargo_cmd(bin.rustc_cmd(true, None)),
+                bin.sysroot_cargo_cmd(bin.rustc_cmd(true, None)),
             ).context(
                 "Failed to build sysroot. This can be skipped by specifying a sysroot explicitly with \
                  the `custom-rustc-sysroot` parameter."
@@ -614,7 +1545,7 @@ fn build_sysroot(
     store_path: &Path,
     target: &str,
     rust_cmd: Command,
-    cargo_cmd: Command,
+    mut cargo_cmd: Command,
 ) -> anyhow::Result<()> {
     let sysroot_src_code = rustc_build_sysroot::rustc_sysroot_src(rust_cmd)?;
 
@@ -622,6 +1553,10 @@ fn build_sysroot(
         anyhow::bail!("could not find rust-src for this current toolchain");
     }
 
+    // `rustc-build-sysroot` waits on the command's status so we ensure that cargo's progress output is
+    // forwarded to the user rather than captured.
+    cargo_cmd.stdout(Stdio::inherit()).stderr(Stdio::inherit());
+
     SysrootBuilder::new(store_path, target)
         .cargo(cargo_cmd)
         .sysroot_config(SysrootConfig::WithStd {
@@ -632,9 +1567,20 @@ fn build_sysroot(
     Ok(())
 }
 
+fn is_sysroot_built(store_path: &Path, target: &str) -> bool {
+    // N.B. this mirrors the stamp file `rustc-build-sysroot` writes once a build has succeeded. It
+    // says nothing about whether that build is stale but that's fine for deciding whether to warn.
+    store_path
+        .join("lib/rustlib")
+        .join(target)
+        .join("lib/.rustc-build-sysroot-hash")
+        .exists()
+}
+
 enum LazilyComputed<'f, V> {
     Ok(V),
     Pending(Option<Box<dyn FnOnce() -> anyhow::Result<V> + 'f>>),
+    Failed(String),
 }
 
 impl<'f, V> LazilyComputed<'f, V> {
@@ -649,10 +1595,15 @@ impl<'f, V> LazilyComputed<'f, V> {
         match self {
             LazilyComputed::Ok(ok) => Ok(ok),
             LazilyComputed::Pending(value) => {
-                #[rustfmt::skip]
-                let value = value
-                    .take()
-                    .expect("LazilyComputed::get() called after an error was already yielded")()?;
+                let value = match value.take().unwrap()() {
+                    Ok(value) => value,
+                    Err(err) => {
+                        // `anyhow::Error` isn't `Clone` so we cache its rendered form to re-report
+                        // it to later callers.
+                        *self = LazilyComputed::Failed(format!("{err:#}"));
+                        return Err(err);
+                    }
+                };
 
                 *self = LazilyComputed::Ok(value);
 
@@ -662,12 +1613,57 @@ impl<'f, V> LazilyComputed<'f, V> {
 
                 Ok(value)
             }
+            LazilyComputed::Failed(err) => Err(anyhow::anyhow!("{err}")),
         }
     }
 }
 
 // === Embedded Data === //
 
+// N.B. these slugs are referenced by the analyzer's diagnostics and should therefore be kept
+// stable.
+const EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "conflicting-borrows",
+        include_str!("../explain/conflicting-borrows.md"),
+    ),
+    (
+        "unabsorbed-tokens",
+        include_str!("../explain/unabsorbed-tokens.md"),
+    ),
+    ("cannot-unsize", include_str!("../explain/cannot-unsize.md")),
+    ("leaked-borrow", include_str!("../explain/leaked-borrow.md")),
+    (
+        "tie-lifetime-not-found",
+        include_str!("../explain/tie-lifetime-not-found.md"),
+    ),
+    ("tie-soundness", include_str!("../explain/tie-soundness.md")),
+    (
+        "fabricated-borrow",
+        include_str!("../explain/fabricated-borrow.md"),
+    ),
+    (
+        "transmuted-borrow",
+        include_str!("../explain/transmuted-borrow.md"),
+    ),
+    (
+        "absorbed-borrow",
+        include_str!("../explain/absorbed-borrow.md"),
+    ),
+    (
+        "unused-acquire",
+        include_str!("../explain/unused-acquire.md"),
+    ),
+    (
+        "subset-assertion",
+        include_str!("../explain/subset-assertion.md"),
+    ),
+    (
+        "raw-pointer-access",
+        include_str!("../explain/raw-pointer-access.md"),
+    ),
+];
+
 fn rustc_wrapper_version() -> &'static str {
     env!("AUTOKEN_EXPECTED_RUSTC_VERSION")
         .lines()
@@ -0,0 +1,130 @@
+use std::{fmt::Write, fs, path::Path};
+
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_middle::ty::{print::with_no_trimmed_paths, Instance, Mutability, TyCtxt};
+
+use crate::util::hash::FxHashMap;
+
+use super::trace::TraceFacts;
+
+// === Driver === //
+
+/// Writes the call graph traced while analyzing the local crate to `path` as a Graphviz document.
+///
+/// Each node is a concrete function instance annotated with the tokens it borrows. Solid edges are
+/// static calls through which tokens are inherited while dashed edges mark functions which were
+/// unsized by the source node and therefore must not borrow anything.
+///
+/// If `path` contains the string `{crate}`, it is replaced with the name of the crate being analyzed
+/// so that several crates can dump their graphs in the same build. Otherwise, the last crate to be
+/// analyzed wins.
+pub fn save_call_graph<'tcx>(tcx: TyCtxt<'tcx>, path: &Path, trace: &TraceFacts<'tcx>) {
+    let path = path
+        .to_string_lossy()
+        .replace("{crate}", tcx.crate_name(LOCAL_CRATE).as_str());
+
+    // N.B. trimmed paths are only meant for diagnostics and would make the output ambiguous.
+    let out = with_no_trimmed_paths!(render_dot(tcx, trace));
+
+    if let Err(err) = fs::write(&path, out) {
+        tcx.dcx().fatal(format!(
+            "failed to write AuToken call graph to {path}: {err}"
+        ));
+    }
+}
+
+fn render_dot<'tcx>(tcx: TyCtxt<'tcx>, trace: &TraceFacts<'tcx>) -> String {
+    // Sort everything by name to keep the output stable between runs.
+    let mut instances = trace
+        .facts
+        .keys()
+        .map(|&instance| (instance.to_string(), instance))
+        .collect::<Vec<_>>();
+
+    instances.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let ids = instances
+        .iter()
+        .enumerate()
+        .map(|(i, &(_, instance))| (instance, i))
+        .collect::<FxHashMap<Instance<'tcx>, usize>>();
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "digraph {} {{",
+        dot_str(tcx.crate_name(LOCAL_CRATE).as_str())
+    )
+    .unwrap();
+    out.push_str("  node [shape=box, fontname=monospace];\n");
+
+    // Write nodes
+    for (i, (name, instance)) in instances.iter().enumerate() {
+        let facts = trace.facts(*instance).unwrap();
+        let mut borrows = facts
+            .borrows
+            .iter()
+            .map(|(ty, (mutability, _))| match mutability {
+                Mutability::Mut => format!("mut {ty}"),
+                Mutability::Not => format!("ref {ty}"),
+            })
+            .collect::<Vec<_>>();
+
+        borrows.sort_unstable();
+
+        let label = if borrows.is_empty() {
+            name.clone()
+        } else {
+            format!("{name}\n{}", borrows.join(", "))
+        };
+
+        writeln!(out, "  n{i} [label={}];", dot_str(&label)).unwrap();
+    }
+
+    // Write edges
+    for (i, (_, instance)) in instances.iter().enumerate() {
+        let facts = trace.facts(*instance).unwrap();
+        let mut edges = facts
+            .calls
+            .iter()
+            .map(|callee| (callee, false))
+            .chain(facts.unsizes.iter().map(|callee| (callee, true)))
+            .filter_map(|(callee, is_unsize)| Some((*ids.get(callee)?, is_unsize)))
+            .collect::<Vec<_>>();
+
+        edges.sort_unstable();
+        edges.dedup();
+
+        for (target, is_unsize) in edges {
+            if is_unsize {
+                writeln!(
+                    out,
+                    "  n{i} -> n{target} [style=dashed, label=\"unsizes\"];"
+                )
+                .unwrap();
+            } else {
+                writeln!(out, "  n{i} -> n{target};").unwrap();
+            }
+        }
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+fn dot_str(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
@@ -176,6 +176,13 @@ impl<'tcx, 'body> TokenMirBuilder<'tcx, 'body> {
 
     // === Calls === //
 
+    /// Creates a fresh token and borrows it immediately before the terminator of `bb`.
+    ///
+    /// The borrow is placed after every other statement in the block so that it models the point
+    /// at which the callee runs rather than the point at which the block starts. Borrowck then only
+    /// reports an overlap if some other borrow of the token is *live* at that point. A reference
+    /// whose last use, explicit `drop`, or end of scope comes before the call is therefore dead by
+    /// then and doesn't conflict, even if its storage is only released later.
     pub fn ensure_not_borrowed_at(&mut self, bb: BasicBlock) -> Local {
         let (local, local_initializer) = self.create_token(DUMMY_SP);
 
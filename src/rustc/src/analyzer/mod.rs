@@ -1,21 +1,36 @@
 use rustc_ast::Mutability;
+use rustc_errors::{Diag, EmissionGuarantee};
 use rustc_hir::{
     def::DefKind,
-    def_id::{DefId, LOCAL_CRATE},
+    def_id::{DefId, LocalDefId, LOCAL_CRATE},
     Constness, LangItem,
 };
 
-use rustc_middle::ty::{Instance, ParamEnv, TyCtxt};
-use rustc_session::config::CrateType;
-use rustc_span::Span;
+use rustc_middle::ty::{print::with_no_trimmed_paths, Instance, ParamEnv, TyCtxt};
+use rustc_session::{config::CrateType, lint::Level};
+use rustc_span::{FileName, Span};
 
-use std::fmt::Write;
+use std::{
+    fmt::Write,
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tracing::{info, info_span};
 
 use crate::{
     analyzer::overlap::BodyOverlapFacts,
+    diagnostic::EXPLAIN_SLUG_ARG,
+    entry::{
+        call_graph_dump_path, changed_file_filter, diagnostic_path_filter, max_error_count,
+        should_audit_unsafe_ties, should_emit_json_sidecar, should_emit_metadata_only,
+        should_warn_absorbed_borrows, should_warn_dynamic_boundaries, should_warn_token_swaps,
+        should_warn_unused_acquires,
+    },
     util::{
         feeder::{feeders::MirBuiltStasher, read_feed},
-        hash::FxHashMap,
+        hash::{FxHashMap, FxHashSet},
         meta::{get_crate_cache_path, save_to_file, try_load_from_file},
         mir::{
             for_each_concrete_unsized_func, has_optimized_mir, iter_all_local_def_ids,
@@ -24,13 +39,20 @@ use crate::{
     },
 };
 
-use self::{template::BodyTemplateFacts, trace::TraceFacts};
+use self::{
+    callgraph::save_call_graph,
+    sidecar::save_json_sidecar,
+    template::{validate_const_context_ties, BodyTemplateFacts, TemplateLints, ValidationLints},
+    trace::TraceFacts,
+};
 
 // === Modules === //
 
+mod callgraph;
 mod mir;
 mod overlap;
 mod sets;
+mod sidecar;
 mod sym;
 mod template;
 mod trace;
@@ -40,29 +62,249 @@ mod trace;
 type SerializedCrateData<'tcx> =
     FxHashMap<DefId, (BodyTemplateFacts<'tcx>, BodyOverlapFacts<'tcx>)>;
 
+type TemplateMap<'tcx> = FxHashMap<
+    DefId,
+    (
+        BodyTemplateFacts<'tcx>,
+        Option<LocalDefId>,
+        Option<BodyOverlapFacts<'tcx>>,
+    ),
+>;
+
 pub fn analyze(tcx: TyCtxt<'_>) {
+    let _span = info_span!("analyze", krate = %tcx.crate_name(LOCAL_CRATE)).entered();
+
+    // Crates which don't link against `autoken` can't borrow any tokens so there is nothing to check
+    // and nothing worth telling their dependents about. We treat their generic functions the same
+    // way we treat those of the standard library.
+    if !links_autoken(tcx) {
+        info!("skipping crate which doesn't link against `autoken`");
+
+        if tcx.needs_metadata() && !tcx.crate_types().contains(&CrateType::ProcMacro) {
+            // Don't let a stale file from a previous version of this crate stick around.
+            let path = get_crate_cache_path(tcx, LOCAL_CRATE);
+
+            if let Err(err) = fs::remove_file(&path) {
+                if err.kind() != ErrorKind::NotFound {
+                    tcx.dcx().fatal(format!(
+                        "failed to remove stale AuToken metadata at {}: {err}",
+                        path.display(),
+                    ));
+                }
+            }
+        }
+
+        return;
+    }
+
+    // In metadata-only mode, we only generate the facts our dependents need and trust that the
+    // crate itself has been (or will be) checked separately.
+    let metadata_only = should_emit_metadata_only(tcx);
+
+    // `--only` restricts the diagnostics we emit to a subset of functions. We still analyze
+    // everything since the facts of the selected functions depend on those of their callees.
+    // Metadata-only runs select nothing so that they don't report any lints. Modules which opted out
+    // using `skip_module!` and, with `--since`, files which haven't changed are deselected in the
+    // same way.
+    let only = diagnostic_path_filter(tcx);
+    let changed_files = changed_file_filter(tcx);
+    let is_selected = |did: DefId| {
+        !metadata_only
+            && is_selected_by_filter(tcx, only.as_deref(), did)
+            && is_in_changed_file(tcx, changed_files.as_deref(), did)
+            && !is_in_skipped_module(tcx, did)
+    };
+
+    // Generate the facts describing each local function
+    let mut templates = build_templates(tcx, &is_selected);
+
+    // Our dependents still need our borrowck facts but we can skip validating this crate's
+    // functions against them.
+    if metadata_only {
+        let timer = tcx.sess.timer("autoken_borrowck_templates");
+        borrowck_templates(tcx, &mut templates);
+
+        drop(timer);
+
+        save_crate_facts(tcx, &templates);
+        return;
+    }
+
+    // Generate trace facts
+    let timer = tcx.sess.timer("autoken_trace_facts");
+    let trace = TraceFacts::compute(tcx);
+
+    drop(timer);
+
+    // Check for undeclared unsizing in trace
+    let timer = tcx.sess.timer("autoken_check_unsizing");
+    check_unsizing(tcx, &trace, &is_selected);
+
+    drop(timer);
+
+    // Borrow-check each template fact
+    let timer = tcx.sess.timer("autoken_borrowck_templates");
+    borrowck_templates(tcx, &mut templates);
+
+    drop(timer);
+
+    // Load other crates' facts
+    let timer = tcx.sess.timer("autoken_load_dependency_facts");
+    for &krate in tcx.crates(()) {
+        let path = get_crate_cache_path(tcx, krate);
+
+        let Some(map) =
+            try_load_from_file::<SerializedCrateData<'_>>(tcx, "AuToken metadata", &path)
+        else {
+            info!(krate = %tcx.crate_name(krate), path = %path.display(), "no facts to load");
+            continue;
+        };
+
+        info!(
+            krate = %tcx.crate_name(krate),
+            path = %path.display(),
+            templates = map.len(),
+            "loaded dependency facts",
+        );
+
+        for (did, (template, overlap)) in map {
+            assert!(!templates.contains_key(&did));
+            templates.insert(did, (template, None, Some(overlap)));
+        }
+    }
+
+    drop(timer);
+
+    // Validate each traced function using their template
+    let timer = tcx.sess.timer("autoken_validate");
+    let lints_allowed = tcx.sess.opts.lint_cap == Some(Level::Allow);
+    let lints = ValidationLints {
+        raw_ptr_accesses: !lints_allowed,
+        token_swaps: should_warn_token_swaps(tcx) && !lints_allowed,
+        absorbed_borrows: should_warn_absorbed_borrows(tcx) && !lints_allowed,
+        unused_acquires: should_warn_unused_acquires(tcx) && !lints_allowed,
+    };
+    let mut raw_ptr_warned = FxHashSet::default();
+
+    for instance in trace.sorted_instances(tcx) {
+        if errors_suppressed() {
+            break;
+        }
+
+        if !is_selected(instance.def_id()) {
+            continue;
+        }
+
+        let Some((template, _, overlaps)) = templates.get(&instance.def_id()) else {
+            continue;
+        };
+
+        let _span = info_span!("validate", %instance).entered();
+        template.validate(
+            tcx,
+            &trace,
+            overlaps.as_ref().unwrap(),
+            instance,
+            lints,
+            &mut raw_ptr_warned,
+        );
+    }
+
+    drop(timer);
+
+    if errors_suppressed() {
+        tcx.dcx().note(format!(
+            "further errors suppressed (--max-errors {})",
+            max_error_count(tcx).unwrap(),
+        ));
+    }
+
+    // Save my crate's facts
+    save_crate_facts(tcx, &templates);
+
+    // Describe my crate's facts for external tools if requested
+    if should_emit_json_sidecar(tcx) && !tcx.crate_types().contains(&CrateType::ProcMacro) {
+        let path = get_crate_cache_path(tcx, LOCAL_CRATE).with_extension("autoken.json");
+
+        let local_templates = templates
+            .iter()
+            .filter(|(did, _)| did.is_local())
+            .map(|(&did, (template, _, _))| (did, template))
+            .collect();
+
+        save_json_sidecar(tcx, &path, &local_templates, &trace);
+    }
+
+    // Dump the traced call graph for debugging if requested. Like with auditing, we skip crates
+    // outside of the user's workspace since they would otherwise clobber the user's graph.
+    if let Some(path) = call_graph_dump_path(tcx) {
+        if tcx.sess.opts.lint_cap != Some(Level::Allow) {
+            save_call_graph(tcx, &path, &trace);
+        }
+    }
+}
+
+/// Generates borrow-checking templates for each local function. Functions rejected by `is_selected`
+/// still get a template but don't have their lints reported.
+fn build_templates<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    is_selected: &impl Fn(DefId) -> bool,
+) -> TemplateMap<'tcx> {
     // Fetch the MIR for each local definition to populate the `MirBuiltStasher`
+    let timer = tcx.sess.timer("autoken_collect_mir");
     for local_def in iter_all_local_def_ids(tcx) {
         if try_grab_base_mir_of_def_id(tcx, local_def).is_some() {
             assert!(read_feed::<MirBuiltStasher>(tcx, local_def).is_some());
         }
     }
 
+    drop(timer);
+
     // Generate borrow-checking templates for each local function
+    let timer = tcx.sess.timer("autoken_build_templates");
     assert!(!tcx.untracked().definitions.is_frozen());
 
     let mut templates = FxHashMap::default();
 
+    // N.B. cargo caps lints for dependencies outside of the user's workspace. We use this to avoid
+    // auditing crates which the user isn't responsible for.
+    let lints_allowed = tcx.sess.opts.lint_cap == Some(Level::Allow);
+    let lints = TemplateLints {
+        dynamic_boundaries: should_warn_dynamic_boundaries(tcx),
+        unsafe_tie_audit: should_audit_unsafe_ties(tcx) && !lints_allowed,
+        provider_loops: !lints_allowed,
+        shared_tokens: !lints_allowed,
+        undominated_ties: !lints_allowed,
+        empty_ties: !lints_allowed,
+    };
+
     for did in iter_all_local_def_ids(tcx) {
-        if read_feed::<MirBuiltStasher>(tcx, did).is_none()
-            || !has_optimized_mir(tcx, did.to_def_id())
-            || tcx.constness(did) == Constness::Const
-        {
+        if read_feed::<MirBuiltStasher>(tcx, did).is_none() {
+            continue;
+        }
+
+        if !has_optimized_mir(tcx, did.to_def_id()) || tcx.constness(did) == Constness::Const {
+            validate_const_context_ties(tcx, did);
             continue;
         }
 
+        let _span = info_span!("build_template", ?did).entered();
+
+        // N.B. errors about malformed `tie!` directives are still reported for every function
+        // since they invalidate the analysis of their callers.
         let param_env_user = tcx.param_env(did);
-        let (template, shadow_did) = BodyTemplateFacts::new(tcx, param_env_user, did);
+        let (template, shadow_did) = BodyTemplateFacts::new(
+            tcx,
+            param_env_user,
+            did,
+            lints.only_if(is_selected(did.to_def_id())),
+        );
+
+        info!(
+            calls = template.calls.len(),
+            leaks = ?template.permitted_leaks.iter().map(|(_, _, set)| set).collect::<Vec<_>>(),
+            "built template",
+        );
 
         templates.insert(
             did.to_def_id(),
@@ -70,20 +312,64 @@ pub fn analyze(tcx: TyCtxt<'_>) {
         );
     }
 
-    // Generate trace facts
-    let trace = TraceFacts::compute(tcx);
+    drop(timer);
+
+    templates
+}
+
+fn borrowck_templates<'tcx>(tcx: TyCtxt<'tcx>, templates: &mut TemplateMap<'tcx>) {
+    for (orig_did, (_, shadow_did, overlaps)) in templates {
+        *overlaps = Some(BodyOverlapFacts::new(tcx, *orig_did, shadow_did.unwrap()));
+    }
+}
+
+fn check_unsizing<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    trace: &TraceFacts<'tcx>,
+    is_selected: &impl Fn(DefId) -> bool,
+) {
+    // The exported functions of libraries meant to be linked into foreign code are called by that
+    // code so, like `main`, they have no caller from which to take tokens.
+    let exports_c_symbols = tcx
+        .crate_types()
+        .iter()
+        .any(|ty| matches!(ty, CrateType::Cdylib | CrateType::Staticlib));
+
+    for instance in trace.sorted_instances(tcx) {
+        if errors_suppressed() {
+            break;
+        }
+
+        if !is_selected(instance.def_id()) {
+            continue;
+        }
 
-    // Check for undeclared unsizing in trace
-    for &instance in trace.facts.keys() {
         let body = try_grab_optimized_mir_of_instance(tcx, instance.def).unwrap();
 
+        if exports_c_symbols
+            && instance.def_id().is_local()
+            && tcx
+                .codegen_fn_attrs(instance.def_id())
+                .contains_extern_indicator()
+        {
+            ensure_no_borrow(
+                tcx,
+                trace,
+                instance,
+                tcx.def_span(instance.def_id()),
+                "export this function",
+                None,
+            );
+        }
+
         if tcx.entry_fn(()).map(|(did, _)| did) == Some(instance.def_id()) {
             ensure_no_borrow(
                 tcx,
-                &trace,
+                trace,
                 instance,
                 tcx.def_span(instance.def_id()),
                 "use this main function",
+                None,
             );
         }
 
@@ -96,10 +382,11 @@ pub fn analyze(tcx: TyCtxt<'_>) {
         {
             ensure_no_borrow(
                 tcx,
-                &trace,
+                trace,
                 instance,
                 tcx.def_span(instance.def_id()),
                 "use this method as a destructor",
+                None,
             );
         }
 
@@ -108,41 +395,26 @@ pub fn analyze(tcx: TyCtxt<'_>) {
             ParamEnv::reveal_all(),
             instance.into(),
             body,
-            |span, instance| ensure_no_borrow(tcx, &trace, instance, span, "unsize this function"),
+            |span, unsized_instance| {
+                info!(caller = %instance, unsized_fn = %unsized_instance, "checking unsizing");
+                ensure_no_borrow(
+                    tcx,
+                    trace,
+                    unsized_instance,
+                    span,
+                    "unsize this function",
+                    Some(instance),
+                )
+            },
         );
     }
+}
 
-    // Borrow-check each template fact
-    for (orig_did, (_, shadow_did, overlaps)) in &mut templates {
-        *overlaps = Some(BodyOverlapFacts::new(tcx, *orig_did, shadow_did.unwrap()));
-    }
-
-    // Load other crates' facts
-    for &krate in tcx.crates(()) {
-        let path = get_crate_cache_path(tcx, krate);
-
-        let Some(map) =
-            try_load_from_file::<SerializedCrateData<'_>>(tcx, "AuToken metadata", &path)
-        else {
-            continue;
-        };
-
-        for (did, (template, overlap)) in map {
-            assert!(!templates.contains_key(&did));
-            templates.insert(did, (template, None, Some(overlap)));
-        }
-    }
-
-    // Validate each traced function using their template
-    for &instance in trace.facts.keys() {
-        let Some((template, _, overlaps)) = templates.get(&instance.def_id()) else {
-            continue;
-        };
-
-        template.validate(tcx, &trace, overlaps.as_ref().unwrap(), instance.args);
-    }
+fn save_crate_facts<'tcx>(tcx: TyCtxt<'tcx>, templates: &TemplateMap<'tcx>) {
+    let timer = tcx.sess.timer("autoken_save_facts");
 
-    // Save my crate's facts
+    // N.B. proc-macro crates are still validated like any other crate—every function with a body
+    // is a root of the trace—but they can only export macros so dependents never need their facts.
     if tcx.needs_metadata() && !tcx.crate_types().contains(&CrateType::ProcMacro) {
         let path = get_crate_cache_path(tcx, LOCAL_CRATE);
 
@@ -156,6 +428,121 @@ pub fn analyze(tcx: TyCtxt<'_>) {
 
         save_to_file(tcx, "AuToken metadata", &path, &serialized);
     }
+
+    drop(timer);
+}
+
+fn links_autoken(tcx: TyCtxt<'_>) -> bool {
+    std::iter::once(LOCAL_CRATE)
+        .chain(tcx.crates(()).iter().copied())
+        .any(|krate| tcx.crate_name(krate) == sym::autoken.get())
+}
+
+fn is_selected_by_filter(tcx: TyCtxt<'_>, filter: Option<&[String]>, did: DefId) -> bool {
+    let Some(filter) = filter else {
+        return true;
+    };
+
+    // N.B. the path of a closure is nested within that of its parent function so selecting a
+    // function also selects its closures.
+    let mut path = with_no_trimmed_paths!(tcx.def_path_str(did));
+
+    if did.is_local() {
+        path = format!("{}::{path}", tcx.crate_name(LOCAL_CRATE));
+    }
+
+    filter.iter().any(|filter| {
+        path.strip_prefix(filter.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    })
+}
+
+fn is_in_changed_file(tcx: TyCtxt<'_>, changed: Option<&[PathBuf]>, did: DefId) -> bool {
+    let Some(changed) = changed else {
+        return true;
+    };
+
+    // N.B. cargo hands rustc paths relative to its working directory, which is also ours, while
+    // the changed files are canonicalized.
+    let FileName::Real(name) = tcx.sess.source_map().span_to_filename(tcx.def_span(did)) else {
+        return false;
+    };
+
+    name.local_path()
+        .and_then(|path| path.canonicalize().ok())
+        .is_some_and(|path| changed.contains(&path))
+}
+
+fn is_in_skipped_module(tcx: TyCtxt<'_>, did: DefId) -> bool {
+    let mut curr = Some(did);
+
+    while let Some(did) = curr {
+        if tcx.def_kind(did) == DefKind::Mod {
+            let children = match did.as_local() {
+                Some(did) => tcx.module_children_local(did),
+                None => tcx.module_children(did),
+            };
+
+            if children
+                .iter()
+                .any(|child| child.ident.name == sym::__autoken_skip_module.get())
+            {
+                return true;
+            }
+        }
+
+        curr = tcx.opt_parent(did);
+    }
+
+    false
+}
+
+/// Set once an error has been dropped because `--max-errors` errors had already been reported.
+static ERRORS_SUPPRESSED: AtomicBool = AtomicBool::new(false);
+
+/// Emits errors unless `--max-errors` errors have already been reported, in which case they are
+/// dropped. Every error reported by the analysis must go through this.
+pub(crate) trait EmitCapped {
+    fn emit_capped(self, tcx: TyCtxt<'_>);
+}
+
+impl EmitCapped for Diag<'_> {
+    fn emit_capped(self, tcx: TyCtxt<'_>) {
+        if max_error_count(tcx).is_some_and(|max| tcx.dcx().err_count() >= max) {
+            ERRORS_SUPPRESSED.store(true, Ordering::Relaxed);
+            self.cancel();
+        } else {
+            self.emit();
+        }
+    }
+}
+
+/// Determines whether an error has been dropped because of `--max-errors`, in which case there is
+/// no point in looking for more.
+fn errors_suppressed() -> bool {
+    ERRORS_SUPPRESSED.load(Ordering::Relaxed)
+}
+
+/// Points users to the `cargo autoken explain` writeup for a diagnostic slug. These slugs must be
+/// kept in sync with the table in `cargo-autoken`.
+pub(crate) trait ExplainNote: Sized {
+    fn explain_note(&mut self, slug: &'static str) -> &mut Self;
+
+    fn with_explain_note(mut self, slug: &'static str) -> Self {
+        self.explain_note(slug);
+        self
+    }
+}
+
+impl<G: EmissionGuarantee> ExplainNote for Diag<'_, G> {
+    fn explain_note(&mut self, slug: &'static str) -> &mut Self {
+        // N.B. the slug is also attached as an argument so that diagnostic sinks can report it
+        // without having to parse it back out of the note.
+        self.arg(EXPLAIN_SLUG_ARG, slug);
+        self.note(format!(
+            "for more information about this diagnostic, try `cargo autoken explain {slug}`"
+        ))
+    }
 }
 
 fn ensure_no_borrow<'tcx>(
@@ -164,6 +551,7 @@ fn ensure_no_borrow<'tcx>(
     instance: Instance<'tcx>,
     span: Span,
     action: &str,
+    unsized_by: Option<Instance<'tcx>>,
 ) {
     let Some(facts) = trace.facts(instance) else {
         return;
@@ -177,6 +565,12 @@ fn ensure_no_borrow<'tcx>(
 
             diag.span(span);
 
+            // The span of an unsizing is that of the coercion rather than that of the function so
+            // let's make it clear which function is being unsized there.
+            if unsized_by.is_some() {
+                diag.span_label(span, format!("{instance} is unsized here"));
+            }
+
             let mut borrow_list = String::new();
             let mut borrow_strings = Vec::new();
 
@@ -211,9 +605,37 @@ fn ensure_no_borrow<'tcx>(
 
             diag.note(borrow_list);
 
-            diag.span_note(tcx.def_span(instance.def_id()), format!("{instance} was unsized"));
+            // If a closure was unsized by some function other than the one which defined it, it was
+            // likely handed off to an adaptor. Name that adaptor since the closure's span alone
+            // doesn't explain why the unsizing happened.
+            if let Some(unsized_by) = unsized_by {
+                if tcx.is_closure_like(instance.def_id())
+                    && tcx.typeck_root_def_id(instance.def_id())
+                        != tcx.typeck_root_def_id(unsized_by.def_id())
+                {
+                    diag.note(format!(
+                        "closure passed to {} was required to borrow no tokens",
+                        tcx.def_path_str(unsized_by.def_id()),
+                    ));
+                }
+            }
+
+            diag.explain_note(if unsized_by.is_some() {
+                "cannot-unsize"
+            } else {
+                "unabsorbed-tokens"
+            });
+
+            diag.span_note(
+                tcx.def_span(instance.def_id()),
+                if unsized_by.is_some() {
+                    format!("{instance} is defined here")
+                } else {
+                    format!("{instance} was unsized")
+                },
+            );
 
-            diag.emit();
+            diag.emit_capped(tcx);
         }
     }
 }
@@ -1,16 +1,32 @@
-use petgraph::{visit::Dfs, Graph};
+use petgraph::{
+    algo::has_path_connecting,
+    graph::NodeIndex,
+    graphmap::DiGraphMap,
+    visit::{Dfs, EdgeFiltered},
+    Graph,
+};
 use rustc_borrowck::consumers::{BodyWithBorrowckFacts, BorrowIndex, Borrows, ConsumerOptions};
 
 use rustc_hir::def_id::{DefId, LocalDefId};
 use rustc_index::bit_set::BitSet;
 use rustc_macros::{TyDecodable, TyEncodable};
 use rustc_middle::{
-    mir::{traversal::reverse_postorder, Local, Location, Statement, Terminator},
-    ty::{GenericArgs, Mutability, Region, TyCtxt},
+    lint::in_external_macro,
+    mir::{
+        traversal::reverse_postorder, Local, Location, Statement, Terminator, TerminatorKind,
+        RETURN_PLACE,
+    },
+    ty::{GenericArgs, Mutability, Region, RegionVid, Ty, TyCtxt},
 };
 use rustc_mir_dataflow::{Analysis, ResultsVisitor};
 use rustc_span::Span;
 
+use super::{
+    errors_suppressed,
+    sets::{get_absorb_kind, is_swap_func, is_transmute_func, AbsorbKind},
+    EmitCapped, ExplainNote,
+};
+
 use crate::util::{
     hash::{FxHashMap, FxHashSet},
     mir::get_body_with_borrowck_facts_but_sinful,
@@ -27,12 +43,28 @@ rustc_index::newtype_index! {
     pub struct SerBorrowIndex {}
 }
 
+rustc_index::newtype_index! {
+    #[orderable]
+    #[debug_format = "'?{}"]
+    #[encodable]
+    pub struct SerRegionVid {}
+}
+
 #[derive(Debug, Clone, TyEncodable, TyDecodable)]
 pub struct BodyOverlapFacts<'tcx> {
     borrows: FxHashMap<SerBorrowIndex, (Local, Span)>,
     overlaps: FxHashMap<SerBorrowIndex, BitSet<SerBorrowIndex>>,
     leaked_locals: FxHashMap<Region<'tcx>, Vec<Local>>,
     leaked_local_def_spans: FxHashMap<Local, Span>,
+    leak_containers: FxHashMap<Local, Local>,
+    escaped_locals: Vec<Local>,
+    static_locals: Vec<Local>,
+    drops: Vec<(Span, Vec<SerRegionVid>, BitSet<SerBorrowIndex>)>,
+    drop_outlives: Vec<(SerRegionVid, SerRegionVid)>,
+    borrow_regions: FxHashMap<SerBorrowIndex, SerRegionVid>,
+    transmutes: Vec<(Span, Vec<SerBorrowIndex>)>,
+    swaps: Vec<(Span, Vec<Vec<SerBorrowIndex>>)>,
+    absorbs: Vec<(Span, AbsorbKind, Ty<'tcx>, Vec<SerBorrowIndex>)>,
 }
 
 impl<'tcx> BodyOverlapFacts<'tcx> {
@@ -57,6 +89,27 @@ impl<'tcx> BodyOverlapFacts<'tcx> {
             })
             .collect();
 
+        // Build the region constraint graph. Edges point from each region to the regions outliving it
+        // and remember the location of the statement introducing them, if any.
+        let mut cst_graph = Graph::new();
+        let mut cst_nodes = FxHashMap::default();
+
+        for cst in facts.region_inference_context.outlives_constraints() {
+            // Left outlives right.
+            let left = cst.sup;
+            let right = cst.sub;
+
+            let left = *cst_nodes
+                .entry(left)
+                .or_insert_with(|| cst_graph.add_node(left));
+
+            let right = *cst_nodes
+                .entry(right)
+                .or_insert_with(|| cst_graph.add_node(right));
+
+            cst_graph.add_edge(right, left, cst.locations.from_location());
+        }
+
         // Run fix-point analysis to figure out which sections of code have which borrows.
         let mut results = Borrows::new(
             tcx,
@@ -68,8 +121,15 @@ impl<'tcx> BodyOverlapFacts<'tcx> {
         .iterate_to_fixpoint();
 
         let mut visitor = BorrowckVisitor {
+            tcx,
             facts: &facts,
+            cst_graph: &cst_graph,
+            cst_nodes: &cst_nodes,
             overlaps: FxHashMap::default(),
+            drops: Vec::new(),
+            transmutes: Vec::new(),
+            swaps: Vec::new(),
+            absorbs: Vec::new(),
         };
 
         rustc_mir_dataflow::visit_results(
@@ -93,6 +153,102 @@ impl<'tcx> BodyOverlapFacts<'tcx> {
             })
             .collect();
 
+        let drops = visitor
+            .drops
+            .into_iter()
+            .map(|(span, res, live)| {
+                let mut live2 = BitSet::new_empty(live.domain_size());
+                for bw in live.iter() {
+                    live2.insert(SerBorrowIndex::from_u32(bw.as_u32()));
+                }
+                (
+                    span,
+                    res.into_iter()
+                        .map(|re| SerRegionVid::from_u32(re.as_u32()))
+                        .collect(),
+                    live2,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Figuring out which of the borrows live at a drop are held by the dropped value is only
+        // worth doing for the borrows involved in a conflict so we keep the constraints around to
+        // do so lazily.
+        let (drop_outlives, borrow_regions) = if drops.is_empty() {
+            (Vec::new(), FxHashMap::default())
+        } else {
+            let drop_outlives = facts
+                .region_inference_context
+                .outlives_constraints()
+                .map(|cst| {
+                    (
+                        SerRegionVid::from_u32(cst.sub.as_u32()),
+                        SerRegionVid::from_u32(cst.sup.as_u32()),
+                    )
+                })
+                .collect();
+
+            let borrow_regions = facts
+                .borrow_set
+                .location_map
+                .values()
+                .enumerate()
+                .map(|(bw, info)| {
+                    (
+                        SerBorrowIndex::from_usize(bw),
+                        SerRegionVid::from_u32(info.region.as_u32()),
+                    )
+                })
+                .collect();
+
+            (drop_outlives, borrow_regions)
+        };
+
+        let transmutes = visitor
+            .transmutes
+            .into_iter()
+            .map(|(span, held)| {
+                (
+                    span,
+                    held.into_iter()
+                        .map(|bw| SerBorrowIndex::from_u32(bw.as_u32()))
+                        .collect(),
+                )
+            })
+            .collect();
+
+        let swaps = visitor
+            .swaps
+            .into_iter()
+            .map(|(span, args)| {
+                (
+                    span,
+                    args.into_iter()
+                        .map(|held| {
+                            held.into_iter()
+                                .map(|bw| SerBorrowIndex::from_u32(bw.as_u32()))
+                                .collect()
+                        })
+                        .collect(),
+                )
+            })
+            .collect();
+
+        let absorbs = visitor
+            .absorbs
+            .into_iter()
+            .map(|(span, kind, set, held)| {
+                (
+                    span,
+                    kind,
+                    set,
+                    held.into_iter()
+                        .map(|bw| SerBorrowIndex::from_u32(bw.as_u32()))
+                        .collect(),
+                )
+            })
+            .collect();
+
         // Determine the bijection between universal regions in signature-land and inference-land.
         let mut universal_to_vid = FxHashMap::default();
         for arg in GenericArgs::identity_for_item(tcx, tcx.typeck_root_def_id(orig_did)) {
@@ -112,105 +268,153 @@ impl<'tcx> BodyOverlapFacts<'tcx> {
         // Now, use the region information to determine which locals are leaked
         let mut leaked_locals = FxHashMap::default();
         let mut leaked_local_def_spans = FxHashMap::default();
-        {
-            let mut cst_graph = Graph::new();
-            let mut cst_nodes = FxHashMap::default();
 
-            // Build constraint graph
-            for cst in facts.region_inference_context.outlives_constraints() {
-                // Left outlives right.
-                let left = cst.sup;
-                let right = cst.sub;
+        for (&origin_real, &origin_vid) in &universal_to_vid {
+            leaked_locals.insert(
+                origin_real,
+                find_leaked_locals(
+                    tcx,
+                    &facts,
+                    &cst_graph,
+                    &cst_nodes,
+                    origin_vid,
+                    &mut leaked_local_def_spans,
+                ),
+            );
+        }
 
-                let left = *cst_nodes
-                    .entry(left)
-                    .or_insert_with(|| cst_graph.add_node(left));
+        // Values stored in a collection which leaks are leaked alongside it. Remember which collection
+        // each leaked local was stored in so that we can point users at it.
+        let mut leak_containers = FxHashMap::default();
 
-                let right = *cst_nodes
-                    .entry(right)
-                    .or_insert_with(|| cst_graph.add_node(right));
+        for locals in leaked_locals.values() {
+            find_leak_containers(
+                tcx,
+                &facts,
+                &cst_graph,
+                &cst_nodes,
+                locals,
+                &mut leak_containers,
+            );
+        }
 
-                cst_graph.add_edge(right, left, ());
-            }
+        // Closures cannot declare ties so values escaping them through their return value or
+        // through their captures cannot borrow tokens either. We treat the free regions of the
+        // closure's return type and environment as additional leak origins.
+        let mut escaped_locals = Vec::new();
 
-            // Determine which nodes are reachable from our universal regions.
-            for (&origin_real, &origin_vid) in &universal_to_vid {
-                let mut leaked_res = FxHashSet::default();
+        if tcx.is_closure_like(orig_did) {
+            let mut escape_vids = FxHashSet::default();
 
-                let Some(&origin) = cst_nodes.get(&origin_vid) else {
+            for local in [RETURN_PLACE, Local::from_u32(1)] {
+                let Some(info) = facts.body.local_decls.get(local) else {
                     continue;
                 };
 
-                let mut dfs = Dfs::new(&cst_graph, origin);
-
-                while let Some(reachable) = dfs.next(&cst_graph) {
-                    leaked_res.insert(reachable);
-                }
-
-                // Finally, let's go through each local to see if it has any regions linked to the
-                // return type.
-                let leaked_locals: &mut Vec<_> = leaked_locals.entry(origin_real).or_default();
-
-                for (local, info) in facts.body.local_decls.iter_enumerated() {
-                    let mut was_used = false;
-
-                    for used in extract_free_region_list(tcx, info.ty, re_as_vid) {
-                        let Some(used) = cst_nodes.get(&used) else {
-                            continue;
-                        };
-
-                        if leaked_res.contains(used) {
-                            leaked_locals.push(local);
-                            was_used = true;
-                        }
-                    }
+                escape_vids.extend(extract_free_region_list(tcx, info.ty, re_as_vid));
+            }
 
-                    if was_used {
-                        leaked_local_def_spans.insert(local, info.source_info.span);
+            for origin_vid in escape_vids {
+                for local in find_leaked_locals(
+                    tcx,
+                    &facts,
+                    &cst_graph,
+                    &cst_nodes,
+                    origin_vid,
+                    &mut leaked_local_def_spans,
+                ) {
+                    if !escaped_locals.contains(&local) {
+                        escaped_locals.push(local);
                     }
                 }
             }
         }
 
+        // Values whose regions must outlive `'static` hold onto their borrows for the remainder of
+        // the program. We find these regardless of whether `'static` is one of the leak origins
+        // above since they are worth warning about even when they don't leak out of the function.
+        let static_locals = find_leaked_locals(
+            tcx,
+            &facts,
+            &cst_graph,
+            &cst_nodes,
+            facts
+                .region_inference_context
+                .to_region_vid(tcx.lifetimes.re_static),
+            &mut leaked_local_def_spans,
+        );
+
         Self {
             borrows,
             overlaps,
             leaked_locals,
             leaked_local_def_spans,
+            leak_containers,
+            escaped_locals,
+            static_locals,
+            drops,
+            drop_outlives,
+            borrow_regions,
+            transmutes,
+            swaps,
+            absorbs,
+        }
+    }
+
+    fn for_each_overlap(&self, mut f: impl FnMut(Pair<SerBorrowIndex>)) {
+        for (&new_bw, conflicts) in &self.overlaps {
+            for old_bw in conflicts.iter() {
+                if old_bw == new_bw {
+                    continue;
+                }
+
+                f(Pair::new(old_bw, new_bw));
+            }
         }
     }
 
     pub fn validate_overlaps(
         &self,
         tcx: TyCtxt<'tcx>,
-        mut are_conflicting: impl FnMut(Pair<Local>) -> Option<(String, Pair<(Mutability, String)>)>,
+        mut are_conflicting: impl FnMut(
+            Pair<Local>,
+        )
+            -> Option<(String, Pair<(Mutability, String)>, Option<String>)>,
     ) {
         let dcx = tcx.dcx();
+        let mut drop_graph = None;
 
-        for (&new_bw, conflicts) in &self.overlaps {
-            for old_bw in conflicts.iter() {
-                if old_bw == new_bw {
-                    continue;
-                }
+        self.for_each_overlap(|bws| {
+            if errors_suppressed() {
+                return;
+            }
 
-                let (old_bw, old_bw_span) = self.borrows[&old_bw];
-                let (new_bw, new_bw_span) = self.borrows[&new_bw];
+            let (old_bw, old_bw_span) = self.borrows[&bws.left];
+            let (new_bw, new_bw_span) = self.borrows[&bws.right];
 
-                let Some((conflict, borrows)) = (are_conflicting)(Pair::new(old_bw, new_bw)) else {
-                    continue;
-                };
+            let Some((conflict, borrows, note)) = (are_conflicting)(Pair::new(old_bw, new_bw))
+            else {
+                return;
+            };
 
-                let borrows = borrows.nat();
-                let (old_bw_mut, old_reason) = borrows.left;
-                let (new_bw_mut, new_reason) = borrows.right;
+            let borrows = borrows.nat();
+            let (old_bw_mut, old_reason) = borrows.left;
+            let (new_bw_mut, new_reason) = borrows.right;
 
-                assert!(!old_bw_mut.is_compatible_with(new_bw_mut));
+            assert!(!old_bw_mut.is_compatible_with(new_bw_mut));
 
-                // Report the conflict
-                dcx.struct_span_err(
-                    new_bw_span,
-                    format!("conflicting borrows on token {conflict}"),
-                )
+            // Report the conflict
+            let mut diag = dcx.struct_span_err(
+                new_bw_span,
+                format!("conflicting borrows on token {conflict}"),
+            );
+
+            if let Some(drop_span) = self.find_drop_span(bws.left, &mut drop_graph) {
+                diag = diag
+                    .with_span_label(drop_span, "first borrow is held until it is dropped here");
+            }
+
+            diag = diag
                 .with_span_label(
                     old_bw_span,
                     format!(
@@ -232,8 +436,206 @@ impl<'tcx> BodyOverlapFacts<'tcx> {
                     ),
                 )
                 .with_help(format!("first borrow originates from {old_reason}"))
-                .with_help(format!("later borrow originates from {new_reason}"))
+                .with_help(format!("later borrow originates from {new_reason}"));
+
+            if let Some(note) = note {
+                diag = diag.with_note(note);
+            }
+
+            diag.with_explain_note("conflicting-borrows")
+                .emit_capped(tcx);
+        });
+    }
+
+    /// Finds the first user-variable drop which keeps `bw` alive. This lets us point at the implicit
+    /// drop of a guard (e.g. a value whose `Drop` impl absorbs tokens) when reporting a conflict
+    /// against the borrow it holds. `graph` caches the constraint graph between calls.
+    fn find_drop_span(
+        &self,
+        bw: SerBorrowIndex,
+        graph: &mut Option<DiGraphMap<SerRegionVid, ()>>,
+    ) -> Option<Span> {
+        let bw_re = *self.borrow_regions.get(&bw)?;
+        let graph =
+            graph.get_or_insert_with(|| DiGraphMap::from_edges(self.drop_outlives.iter().copied()));
+
+        self.drops
+            .iter()
+            .find(|(_, res, live)| {
+                live.contains(bw)
+                    && res.iter().any(|&re| {
+                        graph.contains_node(re) && has_path_connecting(&*graph, re, bw_re, None)
+                    })
+            })
+            .map(|&(span, _, _)| span)
+    }
+
+    /// Warns about `Borrows::new_unchecked` calls which fabricate tokens while a conflicting borrow
+    /// of them is live. `is_unsound` is given the local standing in for the fabrication and the
+    /// local it overlaps with and returns the conflicting token and the origin of the other borrow.
+    pub fn validate_fabrications(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        mut is_unsound: impl FnMut(Local, Local) -> Option<(String, String)>,
+    ) {
+        self.for_each_overlap(|bws| {
+            for bws in bws.orders() {
+                let (fab_bw, fab_bw_span) = self.borrows[bws.left];
+                let (other_bw, other_bw_span) = self.borrows[bws.right];
+
+                let Some((token, other_reason)) = (is_unsound)(fab_bw, other_bw) else {
+                    continue;
+                };
+
+                tcx.dcx()
+                    .struct_span_warn(
+                        fab_bw_span,
+                        format!(
+                            "`Borrows::new_unchecked` fabricates token {token} while it is \
+                             borrowed elsewhere"
+                        ),
+                    )
+                    .with_span_label(fab_bw_span, "token fabricated here")
+                    .with_span_label(other_bw_span, "conflicting borrow is live here")
+                    .with_help(format!("conflicting borrow originates from {other_reason}"))
+                    .with_note(
+                        "absorbing borrows with a fabricated token while it is borrowed elsewhere \
+                         may be unsound",
+                    )
+                    .with_explain_note("fabricated-borrow")
+                    .emit();
+            }
+        });
+    }
+
+    /// Warns about `transmute`s whose input keeps a tied borrow alive. `is_tied` is given the local
+    /// borrowed by the input and returns the token it borrows and the origin of that borrow.
+    pub fn validate_transmutes(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        mut is_tied: impl FnMut(Local) -> Option<(String, String)>,
+    ) {
+        for (span, held) in &self.transmutes {
+            for bw in held {
+                let (bw_local, bw_span) = self.borrows[bw];
+
+                let Some((token, reason)) = (is_tied)(bw_local) else {
+                    continue;
+                };
+
+                tcx.dcx()
+                    .struct_span_warn(
+                        *span,
+                        "transmuting a token-tied reference may bypass AuToken's analysis",
+                    )
+                    .with_span_label(*span, "value transmuted here")
+                    .with_span_label(bw_span, format!("value borrows token {token} here"))
+                    .with_help(format!("borrow originates from {reason}"))
+                    .with_note(
+                        "the transmuted value may outlive the borrow of the token without AuToken \
+                         noticing",
+                    )
+                    .with_explain_note("transmuted-borrow")
+                    .emit();
+
+                // One warning per transmute is plenty.
+                break;
+            }
+        }
+    }
+
+    /// Notes `mem::swap` and `mem::replace` calls where more than one argument keeps a borrow of the
+    /// same token alive. `tokens` is given the local borrowed by an argument and returns the tokens
+    /// it borrows.
+    pub fn validate_swaps(&self, tcx: TyCtxt<'tcx>, mut tokens: impl FnMut(Local) -> Vec<String>) {
+        for (span, args) in &self.swaps {
+            let arg_tokens = args
+                .iter()
+                .map(|held| {
+                    held.iter()
+                        .flat_map(|bw| (tokens)(self.borrows[bw].0))
+                        .collect::<FxHashSet<_>>()
+                })
+                .collect::<Vec<_>>();
+
+            let Some(token) = arg_tokens
+                .iter()
+                .enumerate()
+                .flat_map(|(i, left)| {
+                    arg_tokens[i + 1..]
+                        .iter()
+                        .flat_map(move |right| left.intersection(right))
+                })
+                .min()
+            else {
+                continue;
+            };
+
+            tcx.dcx()
+                .struct_span_note(
+                    *span,
+                    format!("this call exchanges values which both borrow token {token}"),
+                )
+                .with_note(
+                    "AuToken only tracks borrows of the token itself so invariants relating values \
+                     guarded by the same token (e.g. handles into a shared arena) are not checked \
+                     across this exchange",
+                )
                 .emit();
+        }
+    }
+
+    /// Warns about calls to `absorb` which hide borrows conflicting with a borrow their caller
+    /// still holds. `conflict` is given the kind of absorb, the set it absorbs, and the local
+    /// borrowed by a held borrow and returns the conflicting token, the mutability with which it is
+    /// held, and the origin of that borrow.
+    pub fn validate_absorbs(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        mut conflict: impl FnMut(
+            Span,
+            AbsorbKind,
+            Ty<'tcx>,
+            Local,
+        ) -> Option<(String, Mutability, String)>,
+    ) {
+        for &(span, kind, set, ref held) in &self.absorbs {
+            if errors_suppressed() {
+                return;
+            }
+
+            for bw in held {
+                let (bw_local, bw_span) = self.borrows[bw];
+
+                let Some((token, held_mut, reason)) = (conflict)(span, kind, set, bw_local) else {
+                    continue;
+                };
+
+                let held_mut = match held_mut {
+                    Mutability::Not => "immutably",
+                    Mutability::Mut => "mutably",
+                };
+
+                tcx.dcx()
+                    .struct_span_warn(
+                        span,
+                        format!(
+                            "this `absorb` hides borrows of token {token} while it is still \
+                             borrowed {held_mut}"
+                        ),
+                    )
+                    .with_span_label(span, "borrows made in here are hidden from the caller")
+                    .with_span_label(bw_span, format!("token {token} is borrowed {held_mut} here"))
+                    .with_help(format!("borrow originates from {reason}"))
+                    .with_note(
+                        "AuToken will not report conflicts between this borrow and the ones hidden \
+                         by `absorb`",
+                    )
+                    .with_explain_note("absorbed-borrow")
+                    .emit();
+
+                // One warning per absorb is plenty.
+                break;
             }
         }
     }
@@ -249,18 +651,213 @@ impl<'tcx> BodyOverlapFacts<'tcx> {
                     continue;
                 };
 
-                tcx.dcx().span_err(
+                if errors_suppressed() {
+                    return;
+                }
+
+                let mut diag = tcx.dcx().struct_span_err(
                     self.leaked_local_def_spans[&local],
                     format!("cannot leak local variable {deny_reason}"),
                 );
+
+                if let Some(container) = self.leak_containers.get(&local) {
+                    diag = diag
+                        .with_span_label(
+                            self.leaked_local_def_spans[container],
+                            "the borrow is stored in this collection, which outlives the function",
+                        )
+                        .with_note(
+                            "values stored in a collection keep their tokens borrowed for as long \
+                             as the collection is alive",
+                        );
+                }
+
+                diag.with_explain_note("leaked-borrow").emit_capped(tcx);
+            }
+        }
+    }
+
+    /// Reports locals which escape a closure through its return value or its captures. `borrows`
+    /// is given each such local and returns the token it borrows, if any.
+    pub fn validate_escapes(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        mut borrows: impl FnMut(Local) -> Option<String>,
+    ) {
+        for &local in &self.escaped_locals {
+            let Some(token) = (borrows)(local) else {
+                continue;
+            };
+
+            if errors_suppressed() {
+                return;
+            }
+
+            let span = self.leaked_local_def_spans[&local];
+
+            tcx.dcx()
+                .struct_span_err(
+                    span,
+                    format!(
+                        "cannot leak local variable out of its closure since it borrows the \
+                         token {token}"
+                    ),
+                )
+                .with_span_label(span, "this value escapes the closure")
+                .with_note(
+                    "closures cannot tie tokens to their return value or captures so the borrow \
+                     may outlive the `cap!` block providing it",
+                )
+                .with_explain_note("leaked-borrow")
+                .emit_capped(tcx);
+        }
+    }
+
+    /// Warns about locals which must live for `'static` while mutably borrowing a token. `borrows`
+    /// is given each such local and returns the token it borrows mutably, if any.
+    pub fn validate_static_borrows(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        mut borrows: impl FnMut(Local) -> Option<String>,
+    ) {
+        let already_leaked = self
+            .leaked_locals
+            .get(&tcx.lifetimes.re_static)
+            .map_or(&[][..], |locals| &locals[..]);
+
+        for &local in &self.static_locals {
+            // These are already reported as leaks.
+            if already_leaked.contains(&local) {
+                continue;
+            }
+
+            let Some(token) = (borrows)(local) else {
+                continue;
+            };
+
+            let span = self.leaked_local_def_spans[&local];
+
+            tcx.dcx()
+                .struct_span_warn(
+                    span,
+                    format!("token {token} is borrowed mutably for the `'static` lifetime"),
+                )
+                .with_span_label(
+                    span,
+                    "this value's tied lifetime is inferred to be `'static`",
+                )
+                .with_note(format!(
+                    "the borrow is never released so every later borrow of {token} will conflict \
+                     with it"
+                ))
+                .with_explain_note("leaked-borrow")
+                .emit();
+        }
+    }
+}
+
+/// Collects the locals whose types mention a region outliving `origin_vid`.
+fn find_leaked_locals<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    facts: &BodyWithBorrowckFacts<'tcx>,
+    cst_graph: &Graph<RegionVid, Option<Location>>,
+    cst_nodes: &FxHashMap<RegionVid, NodeIndex>,
+    origin_vid: RegionVid,
+    def_spans: &mut FxHashMap<Local, Span>,
+) -> Vec<Local> {
+    let mut leaked_locals = Vec::new();
+
+    let Some(&origin) = cst_nodes.get(&origin_vid) else {
+        return leaked_locals;
+    };
+
+    // Determine which nodes are reachable from our origin region.
+    let mut leaked_res = FxHashSet::default();
+    let mut dfs = Dfs::new(cst_graph, origin);
+
+    while let Some(reachable) = dfs.next(cst_graph) {
+        leaked_res.insert(reachable);
+    }
+
+    // Finally, let's go through each local to see if it has any regions linked to the origin.
+    for (local, info) in facts.body.local_decls.iter_enumerated() {
+        let was_used = extract_free_region_list(tcx, info.ty, re_as_vid)
+            .into_iter()
+            .any(|used| {
+                cst_nodes
+                    .get(&used)
+                    .is_some_and(|used| leaked_res.contains(used))
+            });
+
+        if was_used {
+            leaked_locals.push(local);
+            def_spans.insert(local, info.source_info.span);
+        }
+    }
+
+    leaked_locals
+}
+
+/// Determines which of the `leaked` locals were stored in a collection which is itself among the
+/// `leaked` locals. A value stored in a collection must outlive it so the regions of the value are
+/// reachable from those of the collection.
+fn find_leak_containers<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    facts: &BodyWithBorrowckFacts<'tcx>,
+    cst_graph: &Graph<RegionVid, Option<Location>>,
+    cst_nodes: &FxHashMap<RegionVid, NodeIndex>,
+    leaked: &[Local],
+    containers: &mut FxHashMap<Local, Local>,
+) {
+    let region_nodes_of = |local: Local| {
+        extract_free_region_list(tcx, facts.body.local_decls[local].ty, re_as_vid)
+            .into_iter()
+            .filter_map(|re| cst_nodes.get(&re).copied())
+            .collect::<Vec<_>>()
+    };
+
+    // We only consider the user's own variables of ADT type (e.g. `Vec<&T>` or `HashMap<K, &T>`)
+    // since references and compiler temporaries aren't what users think of as storage.
+    let is_container = |local: Local| {
+        let decl = &facts.body.local_decls[local];
+        decl.is_user_variable() && decl.ty.is_adt()
+    };
+
+    for &container in leaked.iter().filter(|&&local| is_container(local)) {
+        let mut reachable = FxHashSet::default();
+
+        for start in region_nodes_of(container) {
+            let mut dfs = Dfs::new(cst_graph, start);
+            while let Some(node) = dfs.next(cst_graph) {
+                reachable.insert(node);
+            }
+        }
+
+        for &local in leaked {
+            if local == container || is_container(local) || containers.contains_key(&local) {
+                continue;
+            }
+
+            if region_nodes_of(local)
+                .iter()
+                .any(|node| reachable.contains(node))
+            {
+                containers.insert(local, container);
             }
         }
     }
 }
 
 struct BorrowckVisitor<'mir, 'tcx> {
+    tcx: TyCtxt<'tcx>,
     facts: &'mir BodyWithBorrowckFacts<'tcx>,
+    cst_graph: &'mir Graph<RegionVid, Option<Location>>,
+    cst_nodes: &'mir FxHashMap<RegionVid, NodeIndex>,
     overlaps: FxHashMap<BorrowIndex, BitSet<BorrowIndex>>,
+    drops: Vec<(Span, Vec<RegionVid>, BitSet<BorrowIndex>)>,
+    transmutes: Vec<(Span, Vec<BorrowIndex>)>,
+    swaps: Vec<(Span, Vec<Vec<BorrowIndex>>)>,
+    absorbs: Vec<(Span, AbsorbKind, Ty<'tcx>, Vec<BorrowIndex>)>,
 }
 
 impl<'mir, 'tcx> BorrowckVisitor<'mir, 'tcx> {
@@ -271,6 +868,160 @@ impl<'mir, 'tcx> BorrowckVisitor<'mir, 'tcx> {
         self.overlaps
             .insert(BorrowIndex::from_usize(started), set.clone());
     }
+
+    // Records the regions of the values dropped by `Drop` terminators alongside the borrows live at
+    // them so that conflicts can later point at the drop keeping their first borrow alive.
+    fn push_drop(
+        &mut self,
+        location: Location,
+        terminator: &Terminator<'tcx>,
+        set: &BitSet<BorrowIndex>,
+    ) {
+        let TerminatorKind::Drop { place, .. } = &terminator.kind else {
+            return;
+        };
+
+        // Drops of compiler temporaries and drops which only happen while unwinding are rarely the
+        // ones the user is thinking of.
+        if self.facts.body.basic_blocks[location.block].is_cleanup
+            || !self.facts.body.local_decls[place.local].is_user_variable()
+        {
+            return;
+        }
+
+        let dropped_ty = place.ty(&self.facts.body.local_decls, self.tcx).ty;
+        let dropped_res = extract_free_region_list(self.tcx, dropped_ty, re_as_vid);
+
+        if set.is_empty() || dropped_res.is_empty() {
+            return;
+        }
+
+        self.drops
+            .push((terminator.source_info.span, dropped_res, set.clone()));
+    }
+
+    // Records the live borrows which flow into the input of each `transmute` so that we can warn
+    // about transmutes which could be used to extend the lifetime of a tied borrow.
+    fn push_transmute(&mut self, terminator: &Terminator<'tcx>, set: &BitSet<BorrowIndex>) {
+        let TerminatorKind::Call { func, args, .. } = &terminator.kind else {
+            return;
+        };
+
+        let Some((callee, _)) = func.const_fn_def() else {
+            return;
+        };
+
+        if !is_transmute_func(self.tcx, callee) {
+            return;
+        }
+
+        let Some(input) = args.first() else {
+            return;
+        };
+
+        let input_ty = input.node.ty(&self.facts.body.local_decls, self.tcx);
+        let held = self.borrows_held_by(input_ty, set, None);
+
+        if !held.is_empty() {
+            self.transmutes.push((terminator.source_info.span, held));
+        }
+    }
+
+    // Records the live borrows which flow into each argument of a `mem::swap` or `mem::replace` so
+    // that we can point out exchanges between values guarded by the same token.
+    fn push_swap(
+        &mut self,
+        location: Location,
+        terminator: &Terminator<'tcx>,
+        set: &BitSet<BorrowIndex>,
+    ) {
+        let TerminatorKind::Call { func, args, .. } = &terminator.kind else {
+            return;
+        };
+
+        let Some((callee, _)) = func.const_fn_def() else {
+            return;
+        };
+
+        if !is_swap_func(self.tcx, callee) {
+            return;
+        }
+
+        // `swap` and `replace` take both of their arguments at the same type so the call itself
+        // unifies their regions. We ignore the constraints it introduces to see what each argument
+        // held going into the call.
+        let held = args
+            .iter()
+            .map(|arg| {
+                let arg_ty = arg.node.ty(&self.facts.body.local_decls, self.tcx);
+                self.borrows_held_by(arg_ty, set, Some(location))
+            })
+            .collect::<Vec<_>>();
+
+        if held.iter().filter(|held| !held.is_empty()).count() >= 2 {
+            self.swaps.push((terminator.source_info.span, held));
+        }
+    }
+
+    // Records the borrows which are live across each call to `absorb` so that we can warn about
+    // absorbs hiding borrows which conflict with them. Absorbs written by macros from other crates
+    // (e.g. the providers generated by `cap!`) are trusted to know what they are doing.
+    fn push_absorb(&mut self, terminator: &Terminator<'tcx>, set: &BitSet<BorrowIndex>) {
+        let TerminatorKind::Call { func, .. } = &terminator.kind else {
+            return;
+        };
+
+        let Some((callee, args)) = func.const_fn_def() else {
+            return;
+        };
+
+        let Some(kind) = get_absorb_kind(self.tcx, callee) else {
+            return;
+        };
+
+        let span = terminator.source_info.span;
+
+        if in_external_macro(self.tcx.sess, span) || set.is_empty() {
+            return;
+        }
+
+        self.absorbs
+            .push((span, kind, args[0].as_type().unwrap(), set.iter().collect()));
+    }
+
+    // Determines which borrows in `set` are kept alive by the regions of a value of type `ty`.
+    // Constraints introduced at `ignored_location` are disregarded.
+    fn borrows_held_by(
+        &self,
+        ty: Ty<'tcx>,
+        set: &BitSet<BorrowIndex>,
+        ignored_location: Option<Location>,
+    ) -> Vec<BorrowIndex> {
+        let ty_res = extract_free_region_list(self.tcx, ty, re_as_vid)
+            .into_iter()
+            .filter_map(|re| self.cst_nodes.get(&re).copied())
+            .collect::<Vec<_>>();
+
+        if ty_res.is_empty() {
+            return Vec::new();
+        }
+
+        let cst_graph = EdgeFiltered::from_fn(self.cst_graph, |edge| {
+            ignored_location.map_or(true, |ignored| *edge.weight() != Some(ignored))
+        });
+
+        set.iter()
+            .filter(|&bw| {
+                let Some(&bw_re) = self.cst_nodes.get(&self.facts.borrow_set[bw].region) else {
+                    return false;
+                };
+
+                ty_res
+                    .iter()
+                    .any(|&re| has_path_connecting(&cst_graph, re, bw_re, None))
+            })
+            .collect()
+    }
 }
 
 impl<'mir, 'tcx, R> ResultsVisitor<'mir, 'tcx, R> for BorrowckVisitor<'mir, 'tcx> {
@@ -300,10 +1051,14 @@ impl<'mir, 'tcx, R> ResultsVisitor<'mir, 'tcx, R> for BorrowckVisitor<'mir, 'tcx
         &mut self,
         _results: &mut R,
         state: &Self::FlowState,
-        _terminator: &'mir Terminator<'tcx>,
+        terminator: &'mir Terminator<'tcx>,
         location: Location,
     ) {
         self.push_overlap_set(location, state);
+        self.push_drop(location, terminator, state);
+        self.push_transmute(terminator, state);
+        self.push_swap(location, terminator, state);
+        self.push_absorb(terminator, state);
     }
 
     fn visit_terminator_after_primary_effect(
@@ -1,21 +1,131 @@
 use std::collections::hash_map;
 
 use rustc_hir::def_id::DefId;
+use rustc_macros::{TyDecodable, TyEncodable};
 use rustc_middle::ty::{Instance, Mutability, Ty, TyCtxt, TyKind};
-use rustc_span::Symbol;
+use rustc_span::{Span, Symbol};
 
 use crate::util::{hash::FxHashMap, ty::is_annotated_ty};
 
-use super::sym;
+use super::{sym, EmitCapped};
 
 pub fn is_tie_func(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
     tcx.opt_item_name(def_id) == Some(sym::__autoken_declare_tied.get())
 }
 
+pub fn is_tie_justification_func(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    tcx.opt_item_name(def_id) == Some(sym::__autoken_justify_tie.get())
+}
+
 pub fn is_absorb_func(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
     tcx.opt_item_name(def_id) == Some(sym::__autoken_absorb_only.get())
 }
 
+/// The user-facing functions which hide borrows from their caller.
+#[derive(Debug, Copy, Clone, TyEncodable, TyDecodable)]
+pub enum AbsorbKind {
+    /// `absorb::<T, R>`, which hides the token set `T`.
+    Set,
+    /// `absorb_mut::<T, R>`, which hides every borrow of the token `T`.
+    Mut,
+    /// `absorb_ref::<T, R>`, which hides immutable borrows of the token `T`.
+    Ref,
+}
+
+pub fn get_absorb_kind(tcx: TyCtxt<'_>, def_id: DefId) -> Option<AbsorbKind> {
+    if tcx.crate_name(def_id.krate) != sym::autoken.get() {
+        return None;
+    }
+
+    let name = tcx.opt_item_name(def_id)?;
+
+    if name == sym::absorb.get() {
+        Some(AbsorbKind::Set)
+    } else if name == sym::absorb_mut.get() {
+        Some(AbsorbKind::Mut)
+    } else if name == sym::absorb_ref.get() {
+        Some(AbsorbKind::Ref)
+    } else {
+        None
+    }
+}
+
+pub fn is_assert_subset_func(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    tcx.opt_item_name(def_id) == Some(sym::__autoken_assert_subset.get())
+}
+
+pub fn is_new_unchecked_func(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    tcx.opt_item_name(def_id) == Some(sym::new_unchecked.get()) && is_borrows_method(tcx, def_id)
+}
+
+pub fn is_acquire_func(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    tcx.opt_item_name(def_id)
+        .is_some_and(|name| name == sym::acquire_ref.get() || name == sym::acquire_mut.get())
+        && is_borrows_method(tcx, def_id)
+}
+
+fn is_borrows_method(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    tcx.impl_of_method(def_id).is_some_and(|impl_did| {
+        matches!(
+            tcx.type_of(impl_did).skip_binder().kind(),
+            TyKind::Adt(def, _) if is_annotated_ty(def, sym::__autoken_borrows_ty_marker.get()),
+        )
+    })
+}
+
+pub fn is_cap_provide_func(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    let trait_did = tcx.trait_of_item(def_id).or_else(|| {
+        tcx.impl_of_method(def_id)
+            .and_then(|impl_did| tcx.trait_id_of_impl(impl_did))
+    });
+
+    tcx.opt_item_name(def_id) == Some(sym::provide.get())
+        && trait_did.is_some_and(|trait_did| {
+            tcx.item_name(trait_did) == sym::CapTarget.get()
+                && tcx.crate_name(trait_did.krate) == sym::autoken.get()
+        })
+}
+
+pub fn is_transmute_func(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    tcx.is_intrinsic(def_id, rustc_span::sym::transmute)
+        || tcx.is_intrinsic(def_id, rustc_span::sym::transmute_unchecked)
+        || (tcx.crate_name(def_id.krate) == rustc_span::sym::core
+            && tcx.opt_item_name(def_id) == Some(sym::transmute_copy.get()))
+}
+
+pub fn is_swap_func(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    tcx.is_diagnostic_item(rustc_span::sym::mem_swap, def_id)
+        || tcx.is_diagnostic_item(rustc_span::sym::mem_replace, def_id)
+}
+
+pub fn is_raw_ptr_access_func(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    const ACCESSES: [&str; 6] = [
+        "read",
+        "read_volatile",
+        "read_unaligned",
+        "write",
+        "write_volatile",
+        "write_unaligned",
+    ];
+
+    // `ptr::read` and friends
+    if let Some(name) = tcx.get_diagnostic_name(def_id) {
+        if let Some(access) = name.as_str().strip_prefix("ptr_") {
+            return ACCESSES.contains(&access);
+        }
+    }
+
+    // `<*const T>::read` and friends
+    tcx.opt_item_name(def_id)
+        .is_some_and(|name| ACCESSES.contains(&name.as_str()))
+        && tcx.impl_of_method(def_id).is_some_and(|impl_did| {
+            matches!(
+                tcx.type_of(impl_did).skip_binder().kind(),
+                TyKind::RawPtr(_)
+            )
+        })
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct ParsedTieCall<'tcx> {
     pub acquired_set: Ty<'tcx>,
@@ -60,11 +170,12 @@ pub fn parse_tie_func<'tcx>(
 
 pub fn instantiate_set<'tcx>(
     tcx: TyCtxt<'tcx>,
+    span: Span,
     ty: Ty<'tcx>,
 ) -> FxHashMap<Ty<'tcx>, (Mutability, Option<Symbol>)> {
     let mut set = FxHashMap::<Ty<'tcx>, (Mutability, Option<Symbol>)>::default();
 
-    instantiate_set_proc(tcx, ty, &mut |ty, mutability| match set.entry(ty) {
+    instantiate_set_proc(tcx, span, ty, &mut |ty, mutability| match set.entry(ty) {
         hash_map::Entry::Occupied(entry) => {
             if mutability.is_mut() {
                 entry.into_mut().0 = Mutability::Mut;
@@ -78,8 +189,27 @@ pub fn instantiate_set<'tcx>(
     set
 }
 
+/// Determines the tokens hidden by a call to one of the absorbing functions described by `kind`
+/// whose first generic parameter is `ty`.
+pub fn instantiate_absorbed_set<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    span: Span,
+    kind: AbsorbKind,
+    ty: Ty<'tcx>,
+) -> FxHashMap<Ty<'tcx>, Mutability> {
+    match kind {
+        AbsorbKind::Set => instantiate_set(tcx, span, ty)
+            .into_iter()
+            .map(|(ty, (mutability, _))| (ty, mutability))
+            .collect(),
+        AbsorbKind::Mut => [(ty, Mutability::Mut)].into_iter().collect(),
+        AbsorbKind::Ref => [(ty, Mutability::Not)].into_iter().collect(),
+    }
+}
+
 pub fn instantiate_set_proc<'tcx>(
     tcx: TyCtxt<'tcx>,
+    span: Span,
     ty: Ty<'tcx>,
     add: &mut impl FnMut(Ty<'tcx>, Mutability),
 ) {
@@ -87,7 +217,7 @@ pub fn instantiate_set_proc<'tcx>(
         // Union
         TyKind::Tuple(fields) => {
             for field in fields.iter() {
-                instantiate_set_proc(tcx, field, add);
+                instantiate_set_proc(tcx, span, field, add);
             }
         }
         TyKind::Adt(def, generics) if is_annotated_ty(def, sym::__autoken_ref_ty_marker.get()) => {
@@ -99,7 +229,7 @@ pub fn instantiate_set_proc<'tcx>(
         TyKind::Adt(def, generics)
             if is_annotated_ty(def, sym::__autoken_downgrade_ty_marker.get()) =>
         {
-            let mut set = instantiate_set(tcx, generics[0].as_type().unwrap());
+            let mut set = instantiate_set(tcx, span, generics[0].as_type().unwrap());
 
             for (mutability, _) in set.values_mut() {
                 *mutability = Mutability::Not;
@@ -109,18 +239,25 @@ pub fn instantiate_set_proc<'tcx>(
                 add(ty, mutability);
             }
         }
+        TyKind::Adt(def, generics)
+            if is_annotated_ty(def, sym::__autoken_upgrade_ty_marker.get()) =>
+        {
+            for ty in instantiate_set(tcx, span, generics[0].as_type().unwrap()).into_keys() {
+                add(ty, Mutability::Mut);
+            }
+        }
         TyKind::Adt(def, generics) if is_annotated_ty(def, sym::__autoken_diff_ty_marker.get()) => {
-            let mut set = instantiate_set(tcx, generics[0].as_type().unwrap());
+            let mut set = instantiate_set(tcx, span, generics[0].as_type().unwrap());
 
             fn remover_func<'set, 'tcx>(
                 set: &'set mut FxHashMap<Ty<'tcx>, (Mutability, Option<Symbol>)>,
             ) -> impl FnMut(Ty<'tcx>, Mutability) + 'set {
                 |ty, mutability| match set.entry(ty) {
                     hash_map::Entry::Occupied(entry) => {
-                        if mutability.is_mut() {
+                        // N.B. a mutable borrow isn't an immutable one so subtracting `Ref<T>` leaves
+                        // `Mut<T>` intact. This mirrors the absorption rules.
+                        if mutability.is_mut() || entry.get().0 == Mutability::Not {
                             entry.remove();
-                        } else {
-                            entry.into_mut().0 = Mutability::Not;
                         }
                     }
                     hash_map::Entry::Vacant(_) => {}
@@ -129,6 +266,7 @@ pub fn instantiate_set_proc<'tcx>(
 
             instantiate_set_proc(
                 tcx,
+                span,
                 generics[1].as_type().unwrap(),
                 &mut remover_func(&mut set),
             );
@@ -137,6 +275,12 @@ pub fn instantiate_set_proc<'tcx>(
                 add(ty, mutability);
             }
         }
-        _ => unreachable!(),
+        // `TokenSet` is sealed so this should only be reachable if the user managed to smuggle some
+        // other type into a set parameter. Treat it as the empty set so analysis can continue.
+        _ => {
+            tcx.dcx()
+                .struct_span_err(span, format!("expected a TokenSet type, found {ty}"))
+                .emit_capped(tcx);
+        }
     }
 }
@@ -0,0 +1,175 @@
+use std::{fmt::Write, fs, path::Path};
+
+use rustc_hir::def_id::{DefId, LOCAL_CRATE};
+use rustc_middle::ty::{print::with_no_trimmed_paths, Instance, Mutability, TyCtxt};
+
+use crate::util::hash::FxHashMap;
+
+use super::{template::BodyTemplateFacts, trace::TraceFacts};
+
+// === Driver === //
+
+/// Writes a JSON description of every local function's token usage to `path`.
+///
+/// The document has the following shape:
+///
+/// ```json
+/// {
+///   "crate": "my_crate",
+///   "functions": [
+///     {
+///       "def_path": "my_crate::foo",
+///       "ties": [{ "lifetime": "'a", "set": "autoken::Mut<MyCap>" }],
+///       "instances": [
+///         { "instance": "my_crate::foo", "mut": ["MyCap"], "ref": [] }
+///       ]
+///     }
+///   ]
+/// }
+/// ```
+///
+/// `ties` lists the `tie!` directives of the function as written while `instances` lists the
+/// concrete token sets borrowed by each monomorphization of it reached by the analysis.
+pub fn save_json_sidecar<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    path: &Path,
+    templates: &FxHashMap<DefId, &BodyTemplateFacts<'tcx>>,
+    trace: &TraceFacts<'tcx>,
+) {
+    // N.B. trimmed paths are only meant for diagnostics and would make the output ambiguous.
+    let out = with_no_trimmed_paths!(render_json(tcx, templates, trace));
+
+    if let Err(err) = fs::write(path, out) {
+        tcx.dcx().fatal(format!(
+            "failed to write AuToken JSON sidecar to file: {err}"
+        ));
+    }
+}
+
+fn render_json<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    templates: &FxHashMap<DefId, &BodyTemplateFacts<'tcx>>,
+    trace: &TraceFacts<'tcx>,
+) -> String {
+    // Group instances by the function they instantiate.
+    let mut instances = FxHashMap::<DefId, Vec<Instance<'tcx>>>::default();
+    for &instance in trace.facts.keys() {
+        if templates.contains_key(&instance.def_id()) {
+            instances
+                .entry(instance.def_id())
+                .or_default()
+                .push(instance);
+        }
+    }
+
+    // Sort everything by path to keep the output stable between runs.
+    let mut functions = templates
+        .iter()
+        .map(|(&did, &template)| (tcx.def_path_str(did), did, template))
+        .collect::<Vec<_>>();
+
+    functions.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = String::new();
+    out.push_str("{\n");
+    writeln!(
+        out,
+        "  \"crate\": {},",
+        json_str(tcx.crate_name(LOCAL_CRATE).as_str())
+    )
+    .unwrap();
+    out.push_str("  \"functions\": [");
+
+    for (i, (def_path, did, template)) in functions.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+
+        out.push_str("\n    {\n");
+        writeln!(out, "      \"def_path\": {},", json_str(def_path)).unwrap();
+
+        // Write ties
+        let ties = template
+            .permitted_leaks
+            .iter()
+            .map(|(_span, re, set)| {
+                format!(
+                    "{{ \"lifetime\": {}, \"set\": {} }}",
+                    json_str(&re.to_string()),
+                    json_str(&set.to_string()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        writeln!(out, "      \"ties\": [{}],", ties.join(", ")).unwrap();
+
+        // Write instances
+        let mut instance_entries = instances
+            .get(did)
+            .into_iter()
+            .flatten()
+            .map(|&instance| {
+                let facts = trace.facts(instance).unwrap();
+                let mut muts = Vec::new();
+                let mut refs = Vec::new();
+
+                for (&ty, &(mutability, _)) in &facts.borrows {
+                    match mutability {
+                        Mutability::Mut => muts.push(json_str(&ty.to_string())),
+                        Mutability::Not => refs.push(json_str(&ty.to_string())),
+                    }
+                }
+
+                muts.sort_unstable();
+                refs.sort_unstable();
+
+                format!(
+                    "{{ \"instance\": {}, \"mut\": [{}], \"ref\": [{}] }}",
+                    json_str(&instance.to_string()),
+                    muts.join(", "),
+                    refs.join(", "),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        instance_entries.sort_unstable();
+
+        out.push_str("      \"instances\": [");
+        for (i, entry) in instance_entries.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(out, "\n        {entry}").unwrap();
+        }
+        if !instance_entries.is_empty() {
+            out.push_str("\n      ");
+        }
+        out.push_str("]\n    }");
+    }
+
+    if !functions.is_empty() {
+        out.push_str("\n  ");
+    }
+    out.push_str("]\n}\n");
+    out
+}
+
+fn json_str(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
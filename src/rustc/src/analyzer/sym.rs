@@ -10,11 +10,26 @@ macro_rules! define {
 
 define! {
     __autoken_declare_tied
+    __autoken_justify_tie
     __autoken_absorb_only
+    __autoken_assert_subset
     __autoken_mut_ty_marker
     __autoken_ref_ty_marker
     __autoken_downgrade_ty_marker
+    __autoken_upgrade_ty_marker
     __autoken_diff_ty_marker
+    __autoken_borrows_ty_marker
+    __autoken_skip_module
+    absorb
+    absorb_mut
+    absorb_ref
+    acquire_mut
+    acquire_ref
+    autoken
+    CapTarget
+    new_unchecked
+    provide
+    transmute_copy
     unnamed
 }
 
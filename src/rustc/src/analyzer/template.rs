@@ -1,13 +1,22 @@
-use rustc_hir::{def::DefKind, def_id::LocalDefId};
+use rustc_hir::{
+    def::DefKind,
+    def_id::{DefId, LocalDefId},
+    Constness,
+};
+use rustc_index::bit_set::BitSet;
 use rustc_macros::{TyDecodable, TyEncodable};
 use rustc_middle::{
-    mir::{BasicBlock, Local, Terminator, TerminatorKind},
+    mir::{
+        visit::{MutatingUseContext, NonMutatingUseContext, PlaceContext, Visitor},
+        AggregateKind, BasicBlock, Body, BorrowKind, Const, Local, Location, Operand, Place,
+        ProjectionElem, Rvalue, StatementKind, Terminator, TerminatorKind,
+    },
     ty::{
-        fold::RegionFolder, BoundVar, GenericArgsRef, Instance, InstanceDef, Mutability, ParamEnv,
-        Region, RegionKind, Ty, TyCtxt, TypeFoldable,
+        fold::RegionFolder, BoundVar, Clause, Instance, InstanceDef, Mutability, ParamEnv, Region,
+        RegionKind, Ty, TyCtxt, TyKind, TypeFoldable, TypeVisitableExt,
     },
 };
-use rustc_span::{Span, Symbol};
+use rustc_span::{DesugaringKind, Span, Symbol};
 
 use crate::util::{
     feeder::{
@@ -19,31 +28,86 @@ use crate::util::{
         read_feed,
     },
     hash::{FxHashMap, FxHashSet},
-    mir::{get_callee_from_terminator, TerminalCallKind},
+    mir::{find_natural_loops, get_callee_from_terminator, TerminalCallKind},
     ty::{
-        find_region_with_name, get_fn_sig_maybe_closure, try_resolve_instance,
-        FunctionCallAndRegions, GenericTransformer, MaybeConcretizedFunc, MutabilityExt,
+        find_region_with_name, get_fn_sig_maybe_closure, is_annotated_ty, try_resolve_instance,
+        FindRegionError, FunctionCallAndRegions, GenericTransformer, MaybeConcretizedFunc,
+        MutabilityExt,
     },
 };
 
 use super::{
     mir::TokenMirBuilder,
     overlap::BodyOverlapFacts,
-    sets::{instantiate_set_proc, parse_tie_func},
+    sets::{
+        instantiate_absorbed_set, instantiate_set, instantiate_set_proc, is_acquire_func,
+        is_assert_subset_func, is_cap_provide_func, is_new_unchecked_func, is_raw_ptr_access_func,
+        is_tie_func, is_tie_justification_func, parse_tie_func,
+    },
     sym,
     trace::TraceFacts,
+    EmitCapped, ExplainNote,
 };
 
 #[derive(Debug, Clone, TyEncodable, TyDecodable)]
 pub struct BodyTemplateFacts<'tcx> {
     /// The set of region-type-set pairs that can be leaked from the current function.
-    pub permitted_leaks: Vec<(Region<'tcx>, Ty<'tcx>)>,
+    pub permitted_leaks: Vec<(Span, Region<'tcx>, Ty<'tcx>)>,
 
     /// The set of calls made by this function.
     pub calls: Vec<TemplateCall<'tcx>>,
 
-    /// The set of locals held by yields.
-    pub yield_locals: FxHashSet<Local>,
+    /// The set of locals held by yields alongside the span of the yield holding them.
+    pub yield_locals: FxHashMap<Local, Span>,
+
+    /// The set of `assert_subset!` directives in this function.
+    pub subset_assertions: Vec<SubsetAssertion<'tcx>>,
+
+    /// The spans of the raw pointer reads and writes made by this function.
+    pub raw_ptr_accesses: Vec<Span>,
+}
+
+/// The lints checked by [`BodyTemplateFacts::new`].
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TemplateLints {
+    pub dynamic_boundaries: bool,
+    pub unsafe_tie_audit: bool,
+    pub provider_loops: bool,
+    pub shared_tokens: bool,
+    pub undominated_ties: bool,
+    pub empty_ties: bool,
+}
+
+impl TemplateLints {
+    /// Disables every lint for functions whose diagnostics weren't selected.
+    pub fn only_if(self, selected: bool) -> Self {
+        if selected {
+            self
+        } else {
+            Self::default()
+        }
+    }
+}
+
+/// The opt-in lints checked by [`BodyTemplateFacts::validate`].
+#[derive(Debug, Copy, Clone)]
+pub struct ValidationLints {
+    pub raw_ptr_accesses: bool,
+    pub token_swaps: bool,
+    pub absorbed_borrows: bool,
+    pub unused_acquires: bool,
+}
+
+#[derive(Debug, Clone, TyEncodable, TyDecodable)]
+pub struct SubsetAssertion<'tcx> {
+    /// The span of the directive.
+    pub span: Span,
+
+    /// The token set which must be a subset of `sup`.
+    pub sub: Ty<'tcx>,
+
+    /// The token set which must be a superset of `sub`.
+    pub sup: Ty<'tcx>,
 }
 
 #[derive(Debug, Clone, TyEncodable, TyDecodable)]
@@ -60,6 +124,9 @@ pub struct TemplateCall<'tcx> {
     /// The locals to which each free lifetime is tied after the call has been
     /// made.
     pub tied_locals: Vec<Local>,
+
+    /// Whether this call acquires a `Borrows` object which is never used.
+    pub is_unused_acquire: bool,
 }
 
 impl<'tcx> BodyTemplateFacts<'tcx> {
@@ -67,15 +134,99 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
         tcx: TyCtxt<'tcx>,
         param_env_user: ParamEnv<'tcx>,
         orig_id: LocalDefId,
+        lints: TemplateLints,
     ) -> (Self, LocalDefId) {
         let Some(mut body) = read_feed::<MirBuiltStasher>(tcx, orig_id).cloned() else {
             unreachable!();
         };
 
+        if lints.provider_loops {
+            lint_providers_in_loops(tcx, &body);
+        }
+
+        if lints.shared_tokens {
+            lint_shared_token_types(tcx, &body);
+        }
+
+        // Determine which blocks are always executed before the function returns. This must be done
+        // before the body is mutated.
+        let dominators = body.basic_blocks.dominators().clone();
+        let return_bbs = body
+            .basic_blocks
+            .iter_enumerated()
+            .filter(|(bb, bb_data)| {
+                dominators.is_reachable(*bb)
+                    && matches!(bb_data.terminator().kind, TerminatorKind::Return)
+            })
+            .map(|(bb, _)| bb)
+            .collect::<Vec<_>>();
+
+        // Determine the justifications given to `unsafe` ties. These calls are immediately followed
+        // by the call to the tie function they justify.
+        let mut tie_justifications = FxHashMap::<BasicBlock, String>::default();
+        if lints.unsafe_tie_audit {
+            for bb_data in body.basic_blocks.iter() {
+                let TerminatorKind::Call {
+                    func,
+                    args,
+                    target: Some(target),
+                    ..
+                } = &bb_data.terminator().kind
+                else {
+                    continue;
+                };
+
+                let Some((callee, _)) = func.const_fn_def() else {
+                    continue;
+                };
+
+                if !is_tie_justification_func(tcx, callee) {
+                    continue;
+                }
+
+                let Some(Const::Val(reason, _)) = args[0].node.constant().map(|c| c.const_) else {
+                    continue;
+                };
+
+                let Some(reason) = reason
+                    .try_get_slice_bytes_for_diagnostics(tcx)
+                    .and_then(|reason| std::str::from_utf8(reason).ok())
+                else {
+                    continue;
+                };
+
+                tie_justifications.insert(*target, reason.to_string());
+            }
+        }
+
+        // Determine which calls acquire `Borrows` objects that are never used. This must also be
+        // done before the body is mutated.
+        let unused_acquires = body
+            .basic_blocks
+            .iter_enumerated()
+            .filter(|(_, bb_data)| {
+                let TerminatorKind::Call {
+                    func, destination, ..
+                } = &bb_data.terminator().kind
+                else {
+                    return false;
+                };
+
+                func.const_fn_def()
+                    .is_some_and(|(callee, _)| is_acquire_func(tcx, callee))
+                    && destination
+                        .as_local()
+                        .is_some_and(|local| !is_local_read(&body, local))
+            })
+            .map(|(bb, _)| bb)
+            .collect::<FxHashSet<_>>();
+
         let mut body_mutator = TokenMirBuilder::new(tcx, param_env_user, &mut body);
         let mut permitted_leaks = Vec::new();
-        let mut yield_locals = FxHashSet::default();
+        let mut yield_locals = FxHashMap::default();
         let mut calls = Vec::new();
+        let mut subset_assertions = Vec::new();
+        let mut raw_ptr_accesses = Vec::new();
         let fn_ret_ty = get_fn_sig_maybe_closure(tcx, orig_id.to_def_id());
 
         let bb_count = body_mutator.body().basic_blocks.len();
@@ -85,10 +236,11 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
             // Yields can be treated as if they were function calls borrowing everything.
             if let Some(Terminator {
                 kind: TerminatorKind::Yield { .. },
-                ..
+                source_info,
             }) = &body_mutator.body()[bb].terminator
             {
-                yield_locals.insert(body_mutator.ensure_not_borrowed_at(bb));
+                let span = source_info.span;
+                yield_locals.insert(body_mutator.ensure_not_borrowed_at(bb), span);
             }
 
             // If the current basic block is a call...
@@ -100,32 +252,99 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
                     args: None,
                 },
                 &body_mutator.body().basic_blocks[bb].terminator,
-                &body_mutator.body().local_decls,
+                body_mutator.body(),
             ) {
                 Some(TerminalCallKind::Static(span, callee)) => (span, callee),
                 Some(TerminalCallKind::Generic(span, callee)) => (span, callee),
-                _ => {
+                Some(TerminalCallKind::Dynamic(span)) => {
+                    if lints.dynamic_boundaries {
+                        emit_dynamic_boundary_note(tcx, span, "function pointer");
+                    }
+                    continue;
+                }
+                None => {
                     continue;
                 }
             };
 
+            if lints.dynamic_boundaries && matches!(callee.def, InstanceDef::Virtual(..)) {
+                emit_dynamic_boundary_note(tcx, span, "trait object method");
+            }
+
+            // Record subset assertions so they can be checked once the sets are concrete.
+            if is_assert_subset_func(tcx, callee.def_id()) {
+                subset_assertions.push(SubsetAssertion {
+                    span,
+                    sub: callee.args[0].as_type().unwrap(),
+                    sup: callee.args[1].as_type().unwrap(),
+                });
+            }
+
+            // Record raw pointer accesses so they can be compared against the function's borrows.
+            if is_raw_ptr_access_func(tcx, callee.def_id()) {
+                raw_ptr_accesses.push(span);
+            }
+
             // Determine whether it has any special effects on ties.
             'tie: {
                 let Some(func) = parse_tie_func(tcx, callee) else {
                     break 'tie;
                 };
 
+                // Ties to generic sets may well be empty for some instantiations so we can only
+                // catch this for concrete sets.
+                if lints.empty_ties
+                    && !func.acquired_set.has_param()
+                    && instantiate_set(tcx, span, func.acquired_set).is_empty()
+                {
+                    tcx.dcx()
+                        .struct_span_warn(span, "this `tie!` acquires no tokens and has no effect")
+                        .with_help("name the tokens being acquired (e.g. `tie!('a => ref MyCap)`)")
+                        .emit();
+                }
+
+                if lints.unsafe_tie_audit && func.is_unsafe {
+                    emit_unsafe_tie_audit(
+                        tcx,
+                        span,
+                        func.tied_to,
+                        func.acquired_set,
+                        tie_justifications.get(&bb).map(String::as_str),
+                    );
+                }
+
                 let Some(tied_to) = func.tied_to else {
                     break 'tie;
                 };
 
+                // Ties only apply to borrows made after they run so warn users about ties which
+                // can be skipped on the way to a return.
+                if lints.undominated_ties
+                    && dominators.is_reachable(bb)
+                    && !return_bbs
+                        .iter()
+                        .all(|&ret_bb| dominators.dominates(bb, ret_bb))
+                {
+                    tcx.dcx()
+                        .struct_span_warn(
+                            span,
+                            format!("this tie of {tied_to} does not run on every path to a return"),
+                        )
+                        .with_note(format!(
+                            "values returned along paths which skip this directive may not be \
+                             treated as borrowing the tokens tied to {tied_to}",
+                        ))
+                        .with_help("move the `tie!` directive to the start of the function")
+                        .emit();
+                }
+
                 let region = match find_region_with_name(
                     tcx,
                     fn_ret_ty.skip_binder().skip_binder(),
                     tied_to,
                 ) {
                     Ok(region) => region,
-                    Err(symbols) => {
+                    Err(FindRegionError::NotFound(symbols)) => {
                         tcx.dcx()
                             .struct_err(format!(
                                 "lifetime with name {tied_to} not found in output of function{}",
@@ -147,42 +366,100 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
                                 "it is not currently possible to tie lifetimes which appear in input \
                                  parameters to tokens",
                             )
-                            .emit();
+                            .with_explain_note("tie-lifetime-not-found")
+                            .emit_capped(tcx);
+                        break 'tie;
+                    }
+                    Err(FindRegionError::Ambiguous) => {
+                        tcx.dcx()
+                            .struct_err(format!(
+                                "lifetime with name {tied_to} is ambiguous in output of function"
+                            ))
+                            .with_span(span)
+                            .with_note(format!(
+                                "several distinct lifetimes named {tied_to} are bound at the same \
+                                 level of the return type"
+                            ))
+                            .with_help("rename the lifetimes so that each has a unique name")
+                            .with_explain_note("tie-lifetime-not-found")
+                            .emit_capped(tcx);
                         break 'tie;
                     }
                 };
 
                 // HACK: Reject regions which appear anywhere other than the output type.
                 if !func.is_unsafe {
-                    let mut soundness_hole = false;
-
                     if !matches!(region.kind(), RegionKind::ReEarlyParam(_)) {
-                        soundness_hole = true;
+                        tcx.dcx()
+                            .struct_err(
+                                "ties to lifetimes appearing in input parameter types are \
+                                 currently rejected due to soundness issues",
+                            )
+                            .with_span(span)
+                            .with_help(
+                                "if this use is safe, prefix the `tie!` directive with `unsafe`",
+                            )
+                            .with_explain_note("tie-soundness")
+                            .emit_capped(tcx);
                     }
 
-                    for bound in param_env_user.caller_bounds() {
-                        bound.fold_with(&mut RegionFolder::new(tcx, &mut |re, _| {
+                    let mentions_region = |clause: Clause<'tcx>| {
+                        let mut mentions = false;
+                        clause.fold_with(&mut RegionFolder::new(tcx, &mut |re, _| {
                             if re == region {
-                                soundness_hole = true;
+                                mentions = true;
                             }
 
                             re
                         }));
+                        mentions
+                    };
+
+                    // Try to point at the specific bounds which mention the region. These come from
+                    // the function's explicit predicates since the `ParamEnv` doesn't keep spans.
+                    let mut reported_bound = false;
+                    let predicates = tcx.predicates_of(orig_id).instantiate_identity(tcx);
+
+                    for (clause, bound_span) in predicates {
+                        if !mentions_region(clause) {
+                            continue;
+                        }
+
+                        reported_bound = true;
+                        tcx.dcx()
+                            .struct_err(
+                                "ties to lifetimes appearing in generic bounds are currently \
+                                 rejected due to soundness issues",
+                            )
+                            .with_span(bound_span)
+                            .with_span_label(bound_span, format!("this bound mentions {tied_to}"))
+                            .with_span_note(span, format!("{tied_to} was tied here"))
+                            .with_help(
+                                "if this use is safe, prefix the `tie!` directive with `unsafe`",
+                            )
+                            .with_explain_note("tie-soundness")
+                            .emit_capped(tcx);
                     }
 
-                    if soundness_hole {
+                    // Elaborated bounds (e.g. those implied by supertraits) don't have a span of their
+                    // own so we fall back to pointing at the `tie!` directive.
+                    if !reported_bound && param_env_user.caller_bounds().iter().any(mentions_region)
+                    {
                         tcx.dcx()
                             .struct_err(
-                                "ties to lifetimes appearing in generic bounds or input parameters \
-                                types are currently rejected due to soundness issues",
+                                "ties to lifetimes appearing in generic bounds are currently \
+                                 rejected due to soundness issues",
                             )
                             .with_span(span)
-                            .with_help("if this use is safe, prefix the `tie!` directive with `unsafe`")
-                            .emit();
+                            .with_help(
+                                "if this use is safe, prefix the `tie!` directive with `unsafe`",
+                            )
+                            .with_explain_note("tie-soundness")
+                            .emit_capped(tcx);
                     }
                 }
 
-                permitted_leaks.push((region, func.acquired_set));
+                permitted_leaks.push((span, region, func.acquired_set));
             }
 
             // Determine mask
@@ -199,6 +476,7 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
                 prevent_call_local: enb_local,
                 tied_locals,
                 func: mask,
+                is_unused_acquire: unused_acquires.contains(&bb),
             });
         }
 
@@ -236,6 +514,8 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
                 permitted_leaks,
                 calls,
                 yield_locals,
+                subset_assertions,
+                raw_ptr_accesses,
             },
             shadow_def,
         )
@@ -246,8 +526,12 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
         tcx: TyCtxt<'tcx>,
         trace: &TraceFacts<'tcx>,
         overlaps: &BodyOverlapFacts<'tcx>,
-        args: GenericArgsRef<'tcx>,
+        instance: Instance<'tcx>,
+        lints: ValidationLints,
+        raw_ptr_warned: &mut FxHashSet<DefId>,
     ) {
+        let args = instance.args;
+
         // Determine what each local borrows
         let mut borrowing_locals =
             FxHashMap::<Local, (Instance<'tcx>, FxHashMap<Ty<'tcx>, Mutability>)>::default();
@@ -267,7 +551,25 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
                 .upgrade(mutability);
         }
 
+        // Determine which locals stand in for `Borrows::new_unchecked` fabrications
+        let mut fabricating_locals =
+            FxHashMap::<Local, FxHashMap<Ty<'tcx>, (Mutability, Option<Symbol>)>>::default();
+
+        // Determine which locals stand in for calls to trait methods whose implementation is chosen
+        // by the generic parameters of this function.
+        let mut generic_dispatch_locals = FxHashMap::<Local, DefId>::default();
+
         for call in &self.calls {
+            let generic_callee = call.func.instance;
+
+            if generic_callee.args.has_param()
+                && tcx.trait_of_item(generic_callee.def_id()).is_some()
+            {
+                for &local in std::iter::once(&call.prevent_call_local).chain(&call.tied_locals) {
+                    generic_dispatch_locals.insert(local, generic_callee.def_id());
+                }
+            }
+
             let callee = match try_resolve_instance(
                 tcx,
                 ParamEnv::reveal_all(),
@@ -277,6 +579,13 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
                 Ok(None) | Err(_) => continue,
             };
 
+            if is_new_unchecked_func(tcx, callee.def_id()) {
+                fabricating_locals.insert(
+                    call.prevent_call_local,
+                    instantiate_set(tcx, call.span, callee.args[0].as_type().unwrap()),
+                );
+            }
+
             let Some(callee_facts) = trace.facts(callee) else {
                 continue;
             };
@@ -292,13 +601,16 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
 
                 if let Some(borrow_sym) = borrow_sym {
                     let Some(linked) = call.func.get_linked(tcx, Some(args), borrow_sym) else {
-                        tcx.dcx().span_err(
-                            call.span,
-                            format!(
-                                "failed to find lifetime {borrow_sym} to which {borrow_ty} is tied \
-                                 in the return type of the function"
-                            ),
-                        );
+                        tcx.dcx()
+                            .struct_span_err(
+                                call.span,
+                                format!(
+                                    "failed to find lifetime {borrow_sym} to which {borrow_ty} is \
+                                     tied in the return type of the function"
+                                ),
+                            )
+                            .with_explain_note("tie-lifetime-not-found")
+                            .emit_capped(tcx);
 
                         continue;
                     };
@@ -324,9 +636,9 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
                         continue;
                     };
 
-                    if !self.yield_locals.contains(types.right) {
+                    let Some(&yield_span) = self.yield_locals.get(types.right) else {
                         continue;
-                    }
+                    };
 
                     let Some((token, mutability)) = first.1.iter().next() else {
                         continue;
@@ -336,11 +648,25 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
                         token.to_string(),
                         types.map(
                             (*mutability, first.0.to_string()),
-                            (Mutability::Mut, "`.await`".to_string()),
+                            (Mutability::Mut, describe_yield(tcx, yield_span)),
                         ),
+                        None,
                     ));
                 }
 
+                // If both borrows come from generic dispatches to the same trait method, the
+                // restriction is really imposed by this function's calling pattern.
+                let dispatch_note = generic_dispatch_locals
+                    .get(&types.left)
+                    .filter(|&method| generic_dispatch_locals.get(&types.right) == Some(method))
+                    .map(|&method| {
+                        format!(
+                            "this restriction is imposed by the generic calls to `{}`, which may \
+                             resolve to implementations whose borrows conflict",
+                            tcx.def_path_str(method),
+                        )
+                    });
+
                 // Handle regular borrows
                 let types = types.map(
                     borrowing_locals.get(&types.left)?,
@@ -361,6 +687,7 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
                                 (*first_mut, types.left.0.to_string()),
                                 (*second_mut, types.right.0.to_string()),
                             ),
+                            dispatch_note,
                         ));
                     }
                 }
@@ -369,31 +696,730 @@ impl<'tcx> BodyTemplateFacts<'tcx> {
             })
         }
 
+        // Validate fabricated tokens
+        rustc_middle::ty::print::with_forced_trimmed_paths! {
+            overlaps.validate_fabrications(tcx, |fabricating, other| {
+                let fabricated = fabricating_locals.get(&fabricating)?;
+                let (borrower, borrowed) = borrowing_locals.get(&other)?;
+
+                for (token, (fabricated_mut, _)) in fabricated {
+                    let Some(borrowed_mut) = borrowed.get(token) else {
+                        continue;
+                    };
+
+                    if !fabricated_mut.is_compatible_with(*borrowed_mut) {
+                        return Some((token.to_string(), borrower.to_string()));
+                    }
+                }
+
+                None
+            })
+        }
+
+        // Validate subset assertions
+        rustc_middle::ty::print::with_forced_trimmed_paths! {
+            for assertion in &self.subset_assertions {
+                let sub = args.instantiate_arg(tcx, ParamEnv::reveal_all(), assertion.sub);
+                let sup = args.instantiate_arg(tcx, ParamEnv::reveal_all(), assertion.sup);
+                let sup_set = instantiate_set(tcx, assertion.span, sup);
+
+                let mut violations = instantiate_set(tcx, assertion.span, sub)
+                    .into_iter()
+                    .filter_map(|(ty, (sub_mut, _))| match sup_set.get(&ty) {
+                        Some(&(sup_mut, _)) if sup_mut >= sub_mut => None,
+                        Some(_) => Some(format!(
+                            "{ty} is borrowed mutably by the subset but only immutably by the \
+                             superset",
+                        )),
+                        None => Some(format!("{ty} is not borrowed by the superset")),
+                    })
+                    .collect::<Vec<_>>();
+
+                if violations.is_empty() {
+                    continue;
+                }
+
+                violations.sort_unstable();
+
+                let mut diag = tcx.dcx().struct_span_err(
+                    assertion.span,
+                    format!("token set {sub} is not a subset of {sup}"),
+                );
+
+                for violation in violations {
+                    diag.note(violation);
+                }
+
+                diag.with_explain_note("subset-assertion").emit_capped(tcx);
+            }
+        }
+
+        // Validate transmuted borrows
+        rustc_middle::ty::print::with_forced_trimmed_paths! {
+            overlaps.validate_transmutes(tcx, |local| {
+                let (borrower, borrowed) = borrowing_locals.get(&local)?;
+                let token = borrowed.keys().map(|token| token.to_string()).min()?;
+
+                Some((token, borrower.to_string()))
+            })
+        }
+
+        // Validate exchanges between values guarded by the same token
+        if lints.token_swaps {
+            rustc_middle::ty::print::with_forced_trimmed_paths! {
+                overlaps.validate_swaps(tcx, |local| {
+                    borrowing_locals
+                        .get(&local)
+                        .map(|(_, borrowed)| {
+                            borrowed.keys().map(|token| token.to_string()).collect()
+                        })
+                        .unwrap_or_default()
+                })
+            }
+        }
+
+        // Validate absorbs hiding borrows which their caller still holds
+        if lints.absorbed_borrows {
+            rustc_middle::ty::print::with_forced_trimmed_paths! {
+                overlaps.validate_absorbs(tcx, |span, kind, set, local| {
+                    let (borrower, borrowed) = borrowing_locals.get(&local)?;
+                    let set = args.instantiate_arg(tcx, ParamEnv::reveal_all(), set);
+                    let absorbed = instantiate_absorbed_set(tcx, span, kind, set);
+
+                    borrowed
+                        .iter()
+                        .filter(|(token, held_mut)| {
+                            absorbed.get(token).is_some_and(|absorbed_mut| {
+                                !absorbed_mut.is_compatible_with(**held_mut)
+                            })
+                        })
+                        .map(|(token, &held_mut)| {
+                            (token.to_string(), held_mut, borrower.to_string())
+                        })
+                        .min()
+                })
+            }
+        }
+
+        // Validate acquires whose `Borrows` object is never used
+        if lints.unused_acquires {
+            for call in self.calls.iter().filter(|call| call.is_unused_acquire) {
+                let Some((_, borrowed)) = borrowing_locals.get(&call.prevent_call_local) else {
+                    continue;
+                };
+
+                // Tokens which are borrowed at least as strongly by another call would still be
+                // borrowed without this one so removing it wouldn't change anything for callers.
+                let mut tokens = rustc_middle::ty::print::with_forced_trimmed_paths! {
+                    borrowed
+                        .iter()
+                        .filter(|&(token, &mutability)| {
+                            !self.calls.iter().any(|other| {
+                                other.prevent_call_local != call.prevent_call_local
+                                    && borrowing_locals
+                                        .get(&other.prevent_call_local)
+                                        .and_then(|(_, other)| other.get(token))
+                                        .is_some_and(|&other_mut| other_mut >= mutability)
+                            })
+                        })
+                        .map(|(token, &mutability)| (token.to_string(), mutability))
+                        .collect::<Vec<_>>()
+                };
+
+                if tokens.is_empty() {
+                    continue;
+                }
+
+                tokens.sort_unstable();
+
+                let borrows = tokens
+                    .iter()
+                    .map(|(token, mutability)| {
+                        format!(
+                            "{token} {}",
+                            if mutability.is_mut() {
+                                "mutably"
+                            } else {
+                                "immutably"
+                            }
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                tcx.dcx()
+                    .struct_span_warn(
+                        call.span,
+                        format!(
+                            "this `Borrows` object is never used but still borrows {} {borrows}",
+                            if tokens.len() == 1 { "token" } else { "tokens" },
+                        ),
+                    )
+                    .with_note(
+                        "callers of this function still cannot hold conflicting borrows of these \
+                         tokens while calling it",
+                    )
+                    .with_help(
+                        "remove the call if it is a leftover from a refactor or declare the borrow \
+                         with `tie!` if it is intended",
+                    )
+                    .with_explain_note("unused-acquire")
+                    .emit();
+            }
+        }
+
+        // Validate raw pointer accesses. These are only reported once per function rather than once
+        // per instantiation of it.
+        if lints.raw_ptr_accesses
+            && !self.raw_ptr_accesses.is_empty()
+            && !raw_ptr_warned.contains(&instance.def_id())
+        {
+            let mut_borrow = rustc_middle::ty::print::with_forced_trimmed_paths! {
+                borrowing_locals
+                    .values()
+                    .flat_map(|(borrower, borrowed)| {
+                        borrowed
+                            .iter()
+                            .filter(|(_, &mutability)| mutability == Mutability::Mut)
+                            .map(move |(token, _)| (token.to_string(), borrower.to_string()))
+                    })
+                    .min()
+            };
+
+            if let Some((token, borrower)) = mut_borrow {
+                raw_ptr_warned.insert(instance.def_id());
+
+                let mut diag = tcx.dcx().struct_span_warn(
+                    self.raw_ptr_accesses.clone(),
+                    format!("raw pointer access in a function which borrows token {token} mutably"),
+                );
+
+                for &span in &self.raw_ptr_accesses {
+                    diag = diag.with_span_label(span, "this access is not tracked by AuToken");
+                }
+
+                diag.with_help(format!("mutable borrow originates from {borrower}"))
+                    .with_note(
+                        "AuToken cannot see through raw pointers so these accesses may alias \
+                         state guarded by the token",
+                    )
+                    .with_explain_note("raw-pointer-access")
+                    .emit();
+            }
+        }
+
         // Validate leaked locals
-        let mut permitted_leaks = FxHashSet::default();
-        for &(re, set) in &self.permitted_leaks {
+        let mut permitted_leaks = FxHashMap::<(Region<'tcx>, Ty<'tcx>), Mutability>::default();
+        for &(span, re, set) in &self.permitted_leaks {
             let set = args.instantiate_arg(tcx, ParamEnv::reveal_all(), set);
 
-            instantiate_set_proc(tcx, set, &mut |ty, _| {
-                permitted_leaks.insert((re, ty));
+            instantiate_set_proc(tcx, span, set, &mut |ty, mutability| {
+                permitted_leaks
+                    .entry((re, ty))
+                    .or_insert(Mutability::Not)
+                    .upgrade(mutability);
             });
         }
 
         overlaps.validate_leaks(tcx, |re, local| {
             let borrows = borrowing_locals.get(&local)?;
 
-            for &borrow in borrows.1.keys() {
-                if permitted_leaks.contains(&(re, borrow)) {
-                    continue;
-                }
+            for (&borrow, &borrow_mut) in &borrows.1 {
+                let re_name = re.get_name().unwrap_or(sym::ANON_LT.get());
+
+                match permitted_leaks.get(&(re, borrow)) {
+                    // Mutable borrows may only be leaked through mutable ties.
+                    Some(&permitted_mut) if permitted_mut >= borrow_mut => {
+                        continue;
+                    }
+                    Some(_) => {
+                        return Some(format!(
+                            "since the token {borrow} is borrowed mutably but is only tied \
+                             immutably to the return region {re_name}",
+                        ));
+                    }
+                    None => {
+                        let borrow_kind = if borrow_mut.is_mut() {
+                            "mutably"
+                        } else {
+                            "immutably"
+                        };
 
-                return Some(format!(
-                    "since the token {borrow} is not tied to the return region {}",
-                    re.get_name().unwrap_or(sym::ANON_LT.get()),
-                ));
+                        return Some(format!(
+                            "since the token {borrow} is borrowed {borrow_kind} but is not tied to \
+                             the return region {re_name}",
+                        ));
+                    }
+                }
             }
 
             None
         });
+
+        // Validate locals borrowing tokens forever
+        rustc_middle::ty::print::with_forced_trimmed_paths! {
+            overlaps.validate_static_borrows(tcx, |local| {
+                let (_, borrowed) = borrowing_locals.get(&local)?;
+                borrowed
+                    .iter()
+                    .filter(|(_, mutability)| mutability.is_mut())
+                    .map(|(token, _)| token.to_string())
+                    .min()
+            })
+        }
+
+        // Validate locals escaping closures
+        rustc_middle::ty::print::with_forced_trimmed_paths! {
+            overlaps.validate_escapes(tcx, |local| {
+                let (_, borrowed) = borrowing_locals.get(&local)?;
+                borrowed.keys().map(|token| token.to_string()).min()
+            })
+        }
+    }
+}
+
+/// Rejects `tie!` directives in bodies which are evaluated at compile time, such as the initializers
+/// of constants and statics. These bodies are never analyzed since they can't be reached from any
+/// entry point so their ties would otherwise be silently ignored.
+pub fn validate_const_context_ties(tcx: TyCtxt<'_>, did: LocalDefId) {
+    let Some(body) = read_feed::<MirBuiltStasher>(tcx, did) else {
+        return;
+    };
+
+    let context = match tcx.def_kind(did) {
+        DefKind::Const | DefKind::AssocConst => "constant",
+        DefKind::Static(_) => "static",
+        DefKind::AnonConst | DefKind::InlineConst => "constant expression",
+        DefKind::Fn | DefKind::AssocFn if tcx.constness(did) == Constness::Const => "`const fn`",
+        _ => return,
+    };
+
+    for bb in body.basic_blocks.iter() {
+        let TerminatorKind::Call { func, fn_span, .. } = &bb.terminator().kind else {
+            continue;
+        };
+
+        let Some((callee, _)) = func.const_fn_def() else {
+            continue;
+        };
+
+        // `Borrows` objects stand in for borrows of their tokens, which a constant evaluated at
+        // compile time can never hold.
+        if is_acquire_func(tcx, callee) {
+            tcx.dcx()
+                .struct_span_err(
+                    fn_span.source_callsite(),
+                    format!("`Borrows` cannot be acquired in a {context}"),
+                )
+                .with_note(format!(
+                    "the body of a {context} is evaluated at compile time, where no tokens exist \
+                     to be borrowed"
+                ))
+                .with_help("acquire the `Borrows` object at runtime in the function which needs it")
+                .emit_capped(tcx);
+            continue;
+        }
+
+        if !is_tie_func(tcx, callee) {
+            continue;
+        }
+
+        tcx.dcx()
+            .struct_span_err(
+                fn_span.source_callsite(),
+                "`tie!` has no effect in this context",
+            )
+            .with_note(format!(
+                "this `tie!` is in the body of a {context}, which is evaluated at compile time and \
+                 never borrows any tokens"
+            ))
+            .with_help(
+                "move the `tie!` directive into the function whose return value it describes",
+            )
+            .emit_capped(tcx);
+    }
+}
+
+fn emit_unsafe_tie_audit<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    span: Span,
+    tied_to: Option<Symbol>,
+    set: Ty<'tcx>,
+    justification: Option<&str>,
+) {
+    let set = rustc_middle::ty::print::with_forced_trimmed_paths!(set.to_string());
+    let mut diag = tcx.dcx().struct_span_note(
+        span,
+        match tied_to {
+            Some(tied_to) => format!("`unsafe` tie of {tied_to} to {set}"),
+            None => format!("`unsafe` tie of {set}"),
+        },
+    );
+
+    diag = match justification {
+        Some(justification) => diag.with_note(format!("justification: {justification}")),
+        None => diag.with_help(
+            "document why this tie is sound by prefixing its lifetime with a justification \
+             string (e.g. `tie!(unsafe \"reason\", 'a => ref T)`)",
+        ),
+    };
+
+    diag.emit();
+}
+
+fn emit_dynamic_boundary_note(tcx: TyCtxt<'_>, span: Span, callee_kind: &str) {
+    tcx.dcx()
+        .struct_span_note(
+            span,
+            format!("tokens cannot flow through this dynamically dispatched {callee_kind} call"),
+        )
+        .with_note(
+            "AuToken assumes that dynamically dispatched functions borrow no tokens from their \
+             caller and checks this wherever a function is unsized",
+        )
+        .emit();
+}
+
+/// Warns about `cap!` providers which are re-entered on every iteration of a loop even though the
+/// values they provide don't change between iterations.
+fn lint_providers_in_loops<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>) {
+    let loops = find_natural_loops(body);
+
+    for (bb, bb_data) in body.basic_blocks.iter_enumerated() {
+        let TerminatorKind::Call { func, args, .. } = &bb_data.terminator().kind else {
+            continue;
+        };
+
+        let Some((callee, _)) = func.const_fn_def() else {
+            continue;
+        };
+
+        // We only care about the innermost loop since that's the one the provider can be hoisted
+        // out of.
+        let Some((header, loop_blocks)) = loops
+            .iter()
+            .filter(|(_, blocks)| blocks.contains(bb))
+            .min_by_key(|(_, blocks)| blocks.count())
+        else {
+            continue;
+        };
+
+        // Providers can either be called directly or, as `cap!` does, through a closure which
+        // evaluates the provided values before calling the provider.
+        let is_invariant = if is_cap_provide_func(tcx, callee) {
+            is_operand_loop_invariant(body, loop_blocks, &args[0].node, 0)
+        } else if tcx
+            .trait_of_item(callee)
+            .is_some_and(|trait_did| tcx.fn_trait_kind_from_def_id(trait_did).is_some())
+        {
+            let Some((closure_did, captures)) = args[0]
+                .node
+                .place()
+                .and_then(|place| find_closure_def(body, loop_blocks, place.as_local()?, 0))
+            else {
+                continue;
+            };
+
+            let calls_provider =
+                read_feed::<MirBuiltStasher>(tcx, closure_did).is_some_and(|closure_body| {
+                    closure_body.basic_blocks.iter().any(|bb_data| {
+                        matches!(
+                            &bb_data.terminator().kind,
+                            TerminatorKind::Call { func, .. }
+                                if func
+                                    .const_fn_def()
+                                    .is_some_and(|(did, _)| is_cap_provide_func(tcx, did)),
+                        )
+                    })
+                });
+
+            // N.B. captured closures are the bodies being provided to, which are free to depend
+            // on the loop.
+            calls_provider
+                && captures.iter().all(|capture| {
+                    capture.ty(body, tcx).peel_refs().is_closure()
+                        || is_operand_loop_invariant(body, loop_blocks, capture, 0)
+                })
+        } else {
+            false
+        };
+
+        if !is_invariant {
+            continue;
+        }
+
+        let span = bb_data.terminator().source_info.span.source_callsite();
+        let loop_span = body.basic_blocks[*header]
+            .terminator()
+            .source_info
+            .span
+            .source_callsite();
+
+        tcx.dcx()
+            .struct_span_warn(
+                span,
+                "`cap!` provider is re-entered on every iteration of a loop",
+            )
+            .with_span_label(span, "this provider only wraps a single iteration")
+            .with_span_label(loop_span, "in this loop")
+            .with_help(
+                "the provided values don't change between iterations so consider moving the \
+                 provider outside of the loop",
+            )
+            .emit();
+    }
+}
+
+/// Warns about primitives and standard library types used directly as tokens. Tokens are keyed by
+/// their type so every crate borrowing, e.g., `u32` as a token would conflict with every other.
+fn lint_shared_token_types<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>) {
+    let mut reported = FxHashSet::default();
+
+    for bb_data in body.basic_blocks.iter() {
+        let TerminatorKind::Call { func, .. } = &bb_data.terminator().kind else {
+            continue;
+        };
+
+        let Some((_, args)) = func.const_fn_def() else {
+            continue;
+        };
+
+        let span = bb_data.terminator().source_info.span.source_callsite();
+
+        for ty in args
+            .types()
+            .flat_map(|ty| ty.walk())
+            .filter_map(|arg| arg.as_type())
+        {
+            let TyKind::Adt(def, generics) = ty.kind() else {
+                continue;
+            };
+
+            if !is_annotated_ty(def, sym::__autoken_ref_ty_marker.get())
+                && !is_annotated_ty(def, sym::__autoken_mut_ty_marker.get())
+            {
+                continue;
+            }
+
+            let token = generics[0].as_type().unwrap();
+            if !is_shared_token_ty(tcx, token) || !reported.insert((span, token)) {
+                continue;
+            }
+
+            let token = rustc_middle::ty::print::with_forced_trimmed_paths!(token.to_string());
+            tcx.dcx()
+                .struct_span_warn(span, format!("`{token}` is used directly as a token type"))
+                .with_note(format!(
+                    "tokens are identified by their type so this borrow conflicts with every other \
+                     borrow of `{token}`, including those made by unrelated crates",
+                ))
+                .with_help("declare a dedicated marker type for this token using `cap!`")
+                .emit();
+        }
+    }
+}
+
+fn is_shared_token_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> bool {
+    match ty.kind() {
+        TyKind::Bool | TyKind::Char | TyKind::Int(_) | TyKind::Uint(_) | TyKind::Float(_) => true,
+        TyKind::Str => true,
+        TyKind::Adt(def, _) => [
+            rustc_span::sym::core,
+            rustc_span::sym::alloc,
+            rustc_span::sym::std,
+        ]
+        .contains(&tcx.crate_name(def.did().krate)),
+        _ => false,
+    }
+}
+
+/// Finds the closure which `local` was assigned, or borrowed from, in a block of `loop_blocks`
+/// alongside its captures.
+fn find_closure_def<'a, 'tcx>(
+    body: &'a Body<'tcx>,
+    loop_blocks: &BitSet<BasicBlock>,
+    local: Local,
+    depth: u32,
+) -> Option<(LocalDefId, &'a [Operand<'tcx>])> {
+    if depth > 8 {
+        return None;
+    }
+
+    let rvalue = loop_blocks.iter().find_map(|bb| {
+        body.basic_blocks[bb]
+            .statements
+            .iter()
+            .find_map(|stmt| match &stmt.kind {
+                StatementKind::Assign(assign) if assign.0.as_local() == Some(local) => {
+                    Some(&assign.1)
+                }
+                _ => None,
+            })
+    })?;
+
+    match rvalue {
+        Rvalue::Aggregate(kind, captures) => match **kind {
+            AggregateKind::Closure(did, _) => Some((did.as_local()?, &captures.raw[..])),
+            _ => None,
+        },
+        Rvalue::Ref(_, _, place) | Rvalue::Use(Operand::Copy(place) | Operand::Move(place)) => {
+            find_closure_def(body, loop_blocks, place.as_local()?, depth + 1)
+        }
+        _ => None,
+    }
+}
+
+/// Determines whether `operand` evaluates to the same value on every iteration of the loop made
+/// up of `loop_blocks`. This is conservative and only sees through constants, copies, and borrows
+/// of places which aren't assigned within the loop.
+fn is_operand_loop_invariant<'tcx>(
+    body: &Body<'tcx>,
+    loop_blocks: &BitSet<BasicBlock>,
+    operand: &Operand<'tcx>,
+    depth: u32,
+) -> bool {
+    match operand {
+        Operand::Constant(_) => true,
+        Operand::Copy(place) | Operand::Move(place) => {
+            is_place_loop_invariant(body, loop_blocks, *place, None, depth)
+        }
+    }
+}
+
+/// Determines whether `place` holds the same value on every iteration of the loop. The borrow at
+/// `ignored_borrow`, if any, is the one whose invariance is being determined and so doesn't count
+/// as a mutation of the place.
+fn is_place_loop_invariant<'tcx>(
+    body: &Body<'tcx>,
+    loop_blocks: &BitSet<BasicBlock>,
+    place: Place<'tcx>,
+    ignored_borrow: Option<Location>,
+    depth: u32,
+) -> bool {
+    if depth > 8 {
+        return false;
+    }
+
+    let index_locals = place.projection.iter().filter_map(|elem| match elem {
+        ProjectionElem::Index(local) => Some(local),
+        _ => None,
+    });
+
+    std::iter::once(place.local)
+        .chain(index_locals)
+        .all(|local| is_local_loop_invariant(body, loop_blocks, local, ignored_borrow, depth))
+}
+
+fn is_local_loop_invariant(
+    body: &Body<'_>,
+    loop_blocks: &BitSet<BasicBlock>,
+    local: Local,
+    ignored_borrow: Option<Location>,
+    depth: u32,
+) -> bool {
+    let mut defs = Vec::new();
+
+    for bb in loop_blocks.iter() {
+        let bb_data = &body.basic_blocks[bb];
+
+        for (statement_index, stmt) in bb_data.statements.iter().enumerate() {
+            let StatementKind::Assign(assign) = &stmt.kind else {
+                continue;
+            };
+
+            let (place, rvalue) = &**assign;
+
+            // The local could be mutated through mutable references or raw pointers to it.
+            let location = Location {
+                block: bb,
+                statement_index,
+            };
+            if let Rvalue::Ref(_, BorrowKind::Mut { .. }, borrowed)
+            | Rvalue::AddressOf(_, borrowed) = rvalue
+            {
+                if borrowed.local == local && Some(location) != ignored_borrow {
+                    return false;
+                }
+            }
+
+            if place.local != local {
+                continue;
+            }
+
+            // Partial assignments could come from anywhere.
+            if !place.projection.is_empty() {
+                return false;
+            }
+
+            defs.push((location, rvalue));
+        }
+
+        if let TerminatorKind::Call { destination, .. } = &bb_data.terminator().kind {
+            if destination.local == local {
+                return false;
+            }
+        }
+    }
+
+    match defs[..] {
+        [] => true,
+        [(_, Rvalue::Use(operand))] => {
+            is_operand_loop_invariant(body, loop_blocks, operand, depth + 1)
+        }
+        [(location, Rvalue::Ref(_, _, place))] => {
+            is_place_loop_invariant(body, loop_blocks, *place, Some(location), depth + 1)
+        }
+        _ => false,
     }
 }
+
+/// Determines whether the value stored in `local` is ever read. Mentioning a place without reading
+/// it (e.g. in `let _ = ...`) doesn't count.
+fn is_local_read(body: &Body<'_>, local: Local) -> bool {
+    struct ReadFinder {
+        local: Local,
+        found: bool,
+    }
+
+    impl<'tcx> Visitor<'tcx> for ReadFinder {
+        fn visit_local(&mut self, local: Local, context: PlaceContext, _location: Location) {
+            if local != self.local {
+                return;
+            }
+
+            self.found |= !matches!(
+                context,
+                PlaceContext::NonUse(_)
+                    | PlaceContext::MutatingUse(
+                        MutatingUseContext::Store
+                            | MutatingUseContext::Call
+                            | MutatingUseContext::Drop
+                    )
+                    | PlaceContext::NonMutatingUse(
+                        NonMutatingUseContext::PlaceMention | NonMutatingUseContext::Inspect
+                    )
+            );
+        }
+    }
+
+    let mut finder = ReadFinder {
+        local,
+        found: false,
+    };
+    finder.visit_body(body);
+    finder.found
+}
+
+fn describe_yield(tcx: TyCtxt<'_>, span: Span) -> String {
+    // Coroutines lower every suspension point to a `Yield` terminator so we use the desugaring of its
+    // span to tell `.await`s apart from explicit `yield`s.
+    let kind = if span.is_desugaring(DesugaringKind::Await) {
+        "`.await`"
+    } else {
+        "`yield`"
+    };
+
+    let line = tcx.sess.source_map().lookup_char_pos(span.lo()).line;
+
+    format!("the {kind} on line {line}")
+}
@@ -1,7 +1,8 @@
 use std::collections::hash_map;
 
-use rustc_middle::ty::{Instance, Mutability, ParamEnv, Ty, TyCtxt};
+use rustc_middle::ty::{print::with_no_trimmed_paths, Instance, Mutability, ParamEnv, Ty, TyCtxt};
 use rustc_span::Symbol;
+use tracing::{info, info_span};
 
 use crate::{
     analyzer::sets::{
@@ -28,6 +29,8 @@ pub struct TraceFacts<'tcx> {
 #[derive(Debug, Clone)]
 pub struct TracedFuncFacts<'tcx> {
     pub borrows: FxHashMap<Ty<'tcx>, (Mutability, Option<Symbol>)>,
+    pub calls: Vec<Instance<'tcx>>,
+    pub unsizes: Vec<Instance<'tcx>>,
 }
 
 impl<'tcx> TraceFacts<'tcx> {
@@ -72,6 +75,21 @@ impl<'tcx> TraceFacts<'tcx> {
     pub fn facts(&self, instance: Instance<'tcx>) -> Option<&TracedFuncFacts<'tcx>> {
         self.facts.get(&instance)
     }
+
+    /// Lists the traced instances in source order so that their diagnostics are reported in a
+    /// stable order between runs.
+    pub fn sorted_instances(&self, tcx: TyCtxt<'tcx>) -> Vec<Instance<'tcx>> {
+        let mut instances = self.facts.keys().copied().collect::<Vec<_>>();
+
+        instances.sort_by_cached_key(|instance| {
+            (
+                tcx.def_span(instance.def_id()),
+                with_no_trimmed_paths!(instance.to_string()),
+            )
+        });
+
+        instances
+    }
 }
 
 // === Trace routine === //
@@ -90,13 +108,23 @@ fn analyze_fn_facts<'tcx>(
     instance: Instance<'tcx>,
 ) -> TracedFuncFacts<'tcx> {
     let tcx = cx.cx().tcx;
+    let _span = info_span!("analyze_fn_facts", %instance).entered();
 
     assert!(should_analyze(tcx, instance));
 
     // If this function has a hardcoded fact set, use those.
     if is_tie_func(tcx, instance.def_id()) {
+        let borrows = instantiate_set(
+            tcx,
+            tcx.def_span(instance.def_id()),
+            instance.args[1].as_type().unwrap(),
+        );
+        info!(?borrows, "borrows hardcoded by `tie!`");
+
         return TracedFuncFacts {
-            borrows: instantiate_set(tcx, instance.args[1].as_type().unwrap()),
+            borrows,
+            calls: Vec::new(),
+            unsizes: Vec::new(),
         };
     }
 
@@ -108,6 +136,8 @@ fn analyze_fn_facts<'tcx>(
     //
     // We use `reveal_all` since we're tracing fully concrete function instantiations which will
     // always be revealable without where clauses.
+    let mut unsizes = Vec::new();
+
     for_each_concrete_unsized_func(
         tcx,
         ParamEnv::reveal_all(),
@@ -116,12 +146,14 @@ fn analyze_fn_facts<'tcx>(
         |_span, instance| {
             if should_analyze(tcx, instance) {
                 cx.cx().analysis_queue.push(instance);
+                unsizes.push(instance);
             }
         },
     );
 
     // See who th e function may call and where.
     let mut borrows = FxHashMap::default();
+    let mut calls = Vec::new();
 
     for bb in body.basic_blocks.iter() {
         // If the terminator is a call terminator.
@@ -130,7 +162,7 @@ fn analyze_fn_facts<'tcx>(
             ParamEnv::reveal_all(),
             instance.into(),
             &bb.terminator,
-            &body.local_decls,
+            body,
         ) else {
             continue;
         };
@@ -140,6 +172,8 @@ fn analyze_fn_facts<'tcx>(
             continue;
         }
 
+        calls.push(target_instance);
+
         let Some(target_facts) = cx.analyze(target_instance) else {
             continue;
         };
@@ -165,6 +199,7 @@ fn analyze_fn_facts<'tcx>(
     if is_absorb_func(tcx, instance.def_id()) {
         instantiate_set_proc(
             tcx,
+            tcx.def_span(instance.def_id()),
             instance.args[0].as_type().unwrap(),
             &mut |ty, mutability| match borrows.entry(ty) {
                 hash_map::Entry::Occupied(entry) => {
@@ -177,5 +212,11 @@ fn analyze_fn_facts<'tcx>(
         );
     }
 
-    TracedFuncFacts { borrows }
+    info!(?borrows, "traced function");
+
+    TracedFuncFacts {
+        borrows,
+        calls,
+        unsizes,
+    }
 }
@@ -0,0 +1,204 @@
+//! A stable representation of the diagnostics emitted while compiling a crate with AuToken so that
+//! tools embedding the analyzer can receive them programmatically rather than by parsing stderr.
+
+use rustc_data_structures::{marker::IntoDynSyncSend, sync::Lrc};
+use rustc_errors::{
+    emitter::Emitter,
+    translation::{to_fluent_args, Translate},
+    DiagArgValue, DiagInner, FluentBundle, LazyFallbackBundle, Level,
+};
+use rustc_span::{source_map::SourceMap, Span};
+
+/// The severity of a [`Diagnostic`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DiagnosticLevel {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+/// A location in the source code being compiled. Lines and columns are 1-indexed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DiagnosticSpan {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The diagnostic argument through which the analyzer attaches a [`Diagnostic::slug`].
+pub(crate) const EXPLAIN_SLUG_ARG: &str = "autoken_explain_slug";
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub level: DiagnosticLevel,
+    pub message: String,
+    pub primary_span: Option<DiagnosticSpan>,
+    pub labels: Vec<(DiagnosticSpan, String)>,
+    pub notes: Vec<String>,
+
+    /// The slug under which `cargo autoken explain` documents this diagnostic, if any.
+    pub slug: Option<String>,
+}
+
+pub type DiagnosticSink = Box<dyn FnMut(Diagnostic) + Send>;
+
+/// A rustc emitter which forwards every diagnostic to a [`DiagnosticSink`] instead of rendering it.
+pub(crate) struct SinkEmitter {
+    source_map: Lrc<SourceMap>,
+    fallback_bundle: LazyFallbackBundle,
+    sink: IntoDynSyncSend<DiagnosticSink>,
+}
+
+impl SinkEmitter {
+    pub fn new(source_map: Lrc<SourceMap>, sink: DiagnosticSink) -> Self {
+        Self {
+            source_map,
+            fallback_bundle: rustc_errors::fallback_fluent_bundle(
+                rustc_driver::DEFAULT_LOCALE_RESOURCES.to_vec(),
+                false,
+            ),
+            sink: IntoDynSyncSend(sink),
+        }
+    }
+
+    fn convert_span(&self, span: Span) -> Option<DiagnosticSpan> {
+        if span.is_dummy() {
+            return None;
+        }
+
+        let loc = self.source_map.lookup_char_pos(span.lo());
+
+        Some(DiagnosticSpan {
+            file: loc.file.name.prefer_local().to_string(),
+            line: loc.line,
+            column: loc.col_display + 1,
+        })
+    }
+}
+
+impl Translate for SinkEmitter {
+    fn fluent_bundle(&self) -> Option<&Lrc<FluentBundle>> {
+        None
+    }
+
+    fn fallback_fluent_bundle(&self) -> &FluentBundle {
+        &self.fallback_bundle
+    }
+}
+
+impl Emitter for SinkEmitter {
+    fn emit_diagnostic(&mut self, diag: DiagInner) {
+        let args = to_fluent_args(diag.args.iter());
+        let message = self.translate_messages(&diag.messages, &args).into_owned();
+
+        let level = match diag.level() {
+            // N.B. rustc summarizes the errors and warnings it reported at the end of the
+            // compilation. Those summaries aren't diagnostics of their own so, like failure notes,
+            // we deliver them as notes to keep embedders from counting them twice.
+            _ if is_count_summary(&diag, &message) => DiagnosticLevel::Note,
+            Level::Bug | Level::Fatal | Level::Error | Level::DelayedBug => DiagnosticLevel::Error,
+            Level::Warning | Level::ForceWarning(_) => DiagnosticLevel::Warning,
+            Level::Note | Level::OnceNote | Level::FailureNote => DiagnosticLevel::Note,
+            Level::Help | Level::OnceHelp => DiagnosticLevel::Help,
+            Level::Allow | Level::Expect(_) => return,
+        };
+
+        let primary_span = diag
+            .span
+            .primary_span()
+            .and_then(|span| self.convert_span(span));
+
+        let labels = diag
+            .span
+            .span_labels()
+            .into_iter()
+            .filter_map(|label| {
+                let text = label.label?;
+                let text = self.translate_message(&text, &args).ok()?.into_owned();
+                Some((self.convert_span(label.span)?, text))
+            })
+            .collect();
+
+        let notes = diag
+            .children
+            .iter()
+            .map(|child| self.translate_messages(&child.messages, &args).into_owned())
+            .collect::<Vec<_>>();
+
+        let slug = match diag.args.get(EXPLAIN_SLUG_ARG) {
+            Some(DiagArgValue::Str(slug)) => Some(slug.to_string()),
+            _ => None,
+        };
+
+        (self.sink.0)(Diagnostic {
+            level,
+            message,
+            primary_span,
+            labels,
+            notes,
+            slug,
+        });
+    }
+
+    fn source_map(&self) -> Option<&Lrc<SourceMap>> {
+        Some(&self.source_map)
+    }
+}
+
+/// Determines whether `diag` is the "aborting due to N previous errors" or "N warnings emitted"
+/// summary rustc emits once it is done compiling. Unlike failure notes, this toolchain emits these
+/// summaries at the level they summarize, without a span or an error code.
+fn is_count_summary(diag: &DiagInner, message: &str) -> bool {
+    diag.span.is_dummy()
+        && diag.code.is_none()
+        && diag.children.is_empty()
+        && (message.starts_with("aborting due to ")
+            || message.ends_with(" warning emitted")
+            || message.ends_with(" warnings emitted"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use rustc_data_structures::sync::Lrc;
+    use rustc_errors::DiagCtxt;
+    use rustc_span::source_map::{FilePathMapping, SourceMap};
+
+    use crate::analyzer::ExplainNote;
+
+    use super::{DiagnosticLevel, SinkEmitter};
+
+    #[test]
+    fn carries_explain_slugs() {
+        rustc_span::create_default_session_globals_then(|| {
+            let collected = Arc::new(Mutex::new(Vec::new()));
+            let sink = collected.clone();
+            // N.B. this mirrors how rustc creates its own source map, which isn't `Send` either.
+            #[allow(clippy::arc_with_non_send_sync)]
+            let source_map = Lrc::new(SourceMap::new(FilePathMapping::empty()));
+            let dcx = DiagCtxt::new(Box::new(SinkEmitter::new(
+                source_map,
+                Box::new(move |diag| sink.lock().unwrap().push(diag)),
+            )));
+
+            dcx.struct_warn("explained")
+                .with_explain_note("conflicting-borrows")
+                .emit();
+
+            // The slug is structured data so notes which merely look like ours don't carry one.
+            dcx.struct_warn("unexplained")
+                .with_note(
+                    "for more information about this diagnostic, try `cargo autoken explain x`",
+                )
+                .emit();
+
+            let collected = collected.lock().unwrap();
+            assert_eq!(collected.len(), 2);
+            assert_eq!(collected[0].level, DiagnosticLevel::Warning);
+            assert_eq!(collected[0].slug.as_deref(), Some("conflicting-borrows"));
+            assert_eq!(collected[1].slug, None);
+        });
+    }
+}
@@ -2,53 +2,193 @@ use std::{path::PathBuf, process};
 
 use rustc_data_structures::steal::Steal;
 use rustc_driver::{
-    catch_with_exit_code, init_rustc_env_logger, install_ice_hook, Callbacks, Compilation,
-    RunCompiler,
+    catch_with_exit_code, init_logger, init_rustc_env_logger, install_ice_hook, Callbacks,
+    Compilation, RunCompiler,
 };
 
+use rustc_errors::{emitter::HumanReadableErrorType, ColorConfig, DiagCtxt};
 use rustc_hir::{
     def::DefKind,
     def_id::{DefId, LocalDefId},
     HirId,
 };
 use rustc_interface::{interface::Compiler, Queries};
+use rustc_log::LoggerConfig;
 use rustc_middle::{
     dep_graph::DepNodeIndex,
     mir::Body,
     ty::{AssocItem, TyCtxt, Visibility},
 };
 use rustc_session::{config::ErrorOutputType, EarlyDiagCtxt};
+use rustc_span::Symbol;
+use tracing::level_filters::{LevelFilter, STATIC_MAX_LEVEL};
 
-use crate::util::feeder::{
-    feed,
-    feeders::{
-        AssociatedItemFeeder, DefKindFeeder, MirBuiltFeeder, MirBuiltStasher,
-        OptLocalDefIdToHirIdFeeder, VisibilityFeeder,
+use crate::{
+    diagnostic::{DiagnosticSink, SinkEmitter},
+    util::feeder::{
+        feed,
+        feeders::{
+            AssociatedItemFeeder, DefKindFeeder, MirBuiltFeeder, MirBuiltStasher,
+            OptLocalDefIdToHirIdFeeder, VisibilityFeeder,
+        },
+        once_val, read_feed,
     },
-    once_val, read_feed,
 };
 
 const ICE_URL: &str = "https://www.github.com/Radbuglet/autoken/issues";
 
 pub fn main_inner(args: Vec<String>) -> ! {
-    // Install rustc's default logger
+    // Install rustc's logger along with our own
     let handler = EarlyDiagCtxt::new(ErrorOutputType::default());
-    init_rustc_env_logger(&handler);
+    init_autoken_logger(&handler);
 
     // Install a custom ICE hook for ourselves
     install_ice_hook(ICE_URL, |_| ());
 
     // Run the compiler with the user's specified arguments
     process::exit(catch_with_exit_code(|| {
-        RunCompiler::new(&args, &mut AnalyzeMirCallbacks).run()
+        RunCompiler::new(&args, &mut AnalyzeMirCallbacks::default()).run()
     }));
 }
 
+/// Installs rustc's logger with AuToken's own spans enabled according to `AUTOKEN_LOG`. That
+/// variable takes the same directives as `RUSTC_LOG`, which is still honored alongside it, except
+/// that a bare level like `debug` only applies to AuToken rather than to every crate in rustc.
+fn init_autoken_logger(handler: &EarlyDiagCtxt) {
+    let Ok(autoken_log) = std::env::var("AUTOKEN_LOG") else {
+        init_rustc_env_logger(handler);
+        return;
+    };
+
+    let mut config = LoggerConfig::from_env("RUSTC_LOG");
+    let directives = config
+        .filter
+        .iter()
+        .flat_map(|filter| filter.split(','))
+        .map(str::to_string)
+        .chain(autoken_log.split(',').map(|directive| {
+            // N.B. distributed toolchains compile out every event finer than `info` so AuToken
+            // logs everything at that level and we clamp requests for more to avoid a warning.
+            match directive.parse::<LevelFilter>() {
+                Ok(level) => format!("autoken_rustc={}", level.min(STATIC_MAX_LEVEL)),
+                Err(_) => directive.to_string(),
+            }
+        }))
+        .filter(|directive| !directive.is_empty())
+        .collect::<Vec<_>>();
+
+    config.filter = Ok(directives.join(","));
+    init_logger(handler, config);
+}
+
+/// Runs the compiler with the specified arguments, forwarding every diagnostic it emits—AuToken's
+/// own as well as rustc's—to `sink` instead of rendering it to stderr. Returns the exit code the
+/// compiler would have exited with.
+///
+/// N.B. the analyzer overrides rustc's queries through process-wide state so this can only be
+/// called once per process.
+pub fn run_with_diagnostic_sink(args: Vec<String>, sink: DiagnosticSink) -> i32 {
+    catch_with_exit_code(|| {
+        RunCompiler::new(
+            &args,
+            &mut AnalyzeMirCallbacks {
+                diagnostic_sink: Some(sink),
+            },
+        )
+        .run()
+    })
+}
+
+/// Reads the diagnostic color mode forced by `cargo autoken --color`. `None` leaves the choice to
+/// rustc's own `--color` and `--json` flags. This runs before there is a session so the variable is
+/// tracked once the analysis starts.
+fn diagnostic_color_override() -> Option<ColorConfig> {
+    match std::env::var("AUTOKEN_COLOR").ok()?.as_str() {
+        "always" => Some(ColorConfig::Always),
+        "never" => Some(ColorConfig::Never),
+        _ => None,
+    }
+}
+
+fn with_color(kind: HumanReadableErrorType, color: ColorConfig) -> HumanReadableErrorType {
+    match kind {
+        HumanReadableErrorType::Default(_) => HumanReadableErrorType::Default(color),
+        HumanReadableErrorType::AnnotateSnippet(_) => {
+            HumanReadableErrorType::AnnotateSnippet(color)
+        }
+        HumanReadableErrorType::Short(_) => HumanReadableErrorType::Short(color),
+    }
+}
+
 pub fn should_run_analysis() -> bool {
     std::env::var("AUTOKEN_SKIP_ANALYSIS").is_err()
 }
 
-struct AnalyzeMirCallbacks;
+pub fn should_warn_dynamic_boundaries(tcx: TyCtxt<'_>) -> bool {
+    read_tracked_env(tcx, "AUTOKEN_WARN_DYNAMIC_BOUNDARIES").is_some()
+}
+
+pub fn should_warn_token_swaps(tcx: TyCtxt<'_>) -> bool {
+    read_tracked_env(tcx, "AUTOKEN_WARN_TOKEN_SWAPS").is_some()
+}
+
+pub fn should_warn_absorbed_borrows(tcx: TyCtxt<'_>) -> bool {
+    read_tracked_env(tcx, "AUTOKEN_WARN_ABSORBED_BORROWS").is_some()
+}
+
+pub fn should_warn_unused_acquires(tcx: TyCtxt<'_>) -> bool {
+    read_tracked_env(tcx, "AUTOKEN_WARN_UNUSED_ACQUIRES").is_some()
+}
+
+pub fn should_audit_unsafe_ties(tcx: TyCtxt<'_>) -> bool {
+    read_tracked_env(tcx, "AUTOKEN_AUDIT").is_some()
+}
+
+pub fn should_emit_json_sidecar(tcx: TyCtxt<'_>) -> bool {
+    read_tracked_env(tcx, "AUTOKEN_EMIT_JSON").is_some()
+}
+
+pub fn should_emit_metadata_only(tcx: TyCtxt<'_>) -> bool {
+    read_tracked_env(tcx, "AUTOKEN_METADATA_ONLY").is_some()
+}
+
+pub fn diagnostic_path_filter(tcx: TyCtxt<'_>) -> Option<Vec<String>> {
+    read_tracked_env(tcx, "AUTOKEN_ONLY").map(|v| v.split(',').map(str::to_string).collect())
+}
+
+pub fn changed_file_filter(tcx: TyCtxt<'_>) -> Option<Vec<PathBuf>> {
+    read_tracked_env(tcx, "AUTOKEN_CHANGED_FILES").map(|v| {
+        std::env::split_paths(&v)
+            .filter(|path| !path.as_os_str().is_empty())
+            .collect()
+    })
+}
+
+pub fn max_error_count(tcx: TyCtxt<'_>) -> Option<usize> {
+    read_tracked_env(tcx, "AUTOKEN_MAX_ERRORS").and_then(|v| v.parse().ok())
+}
+
+pub fn call_graph_dump_path(tcx: TyCtxt<'_>) -> Option<PathBuf> {
+    read_tracked_env(tcx, "AUTOKEN_DUMP_CALLGRAPH").map(PathBuf::from)
+}
+
+fn read_tracked_env(tcx: TyCtxt<'_>, name: &str) -> Option<String> {
+    let value = std::env::var(name).ok();
+
+    // Ensure that cargo re-runs the analysis when this flag is toggled.
+    tcx.sess
+        .psess
+        .env_depinfo
+        .borrow_mut()
+        .insert((Symbol::intern(name), value.as_deref().map(Symbol::intern)));
+
+    value
+}
+
+#[derive(Default)]
+struct AnalyzeMirCallbacks {
+    diagnostic_sink: Option<DiagnosticSink>,
+}
 
 impl Callbacks for AnalyzeMirCallbacks {
     fn config(&mut self, config: &mut rustc_interface::Config) {
@@ -59,12 +199,41 @@ impl Callbacks for AnalyzeMirCallbacks {
         // which are reachable from the main function so this is an okay limitation.
         config.opts.unstable_opts.always_encode_mir = true;
 
+        // Cargo asks for colored JSON diagnostics based on its own terminal detection so we let
+        // users override the color of both rendered and JSON diagnostics.
+        if let Some(color) = diagnostic_color_override() {
+            config.opts.error_format = match config.opts.error_format {
+                ErrorOutputType::HumanReadable(kind) => {
+                    ErrorOutputType::HumanReadable(with_color(kind, color))
+                }
+                ErrorOutputType::Json {
+                    pretty,
+                    json_rendered,
+                } => ErrorOutputType::Json {
+                    pretty,
+                    json_rendered: with_color(json_rendered, color),
+                },
+            };
+        }
+
+        // Embedders can ask for diagnostics to be delivered to them rather than to stderr. We swap
+        // out the emitter as soon as the parse session exists so that no diagnostic slips past.
+        if let Some(sink) = self.diagnostic_sink.take() {
+            config.psess_created = Some(Box::new(move |psess| {
+                let emitter = SinkEmitter::new(psess.clone_source_map(), sink);
+                psess.dcx = DiagCtxt::new(Box::new(emitter));
+            }));
+        }
+
         // We also have to hack in a little environment variable to override the sysroot.
         if let Ok(ovr) = std::env::var("AUTOKEN_OVERRIDE_SYSROOT") {
             config.opts.maybe_sysroot = Some(PathBuf::from(ovr));
         }
 
         if should_run_analysis() {
+            // Let users write `#[cfg(autoken)]` code which only exists while being analyzed.
+            config.crate_cfg.push("autoken".to_string());
+
             config.override_queries = Some(|_sess, query| {
                 // Feeders
                 once_val! {
@@ -149,10 +318,16 @@ impl Callbacks for AnalyzeMirCallbacks {
         queries: &'tcx Queries<'tcx>,
     ) -> Compilation {
         if should_run_analysis() {
-            queries
-                .global_ctxt()
-                .unwrap()
-                .enter(|tcx| crate::analyzer::analyze(tcx));
+            queries.global_ctxt().unwrap().enter(|tcx| {
+                // N.B. the logger is installed and the diagnostic colors are chosen before there is
+                // a session to track `AUTOKEN_LOG` and `AUTOKEN_COLOR` with so we do it here to
+                // ensure that cargo re-runs the analysis to print a log or recolor diagnostics.
+                read_tracked_env(tcx, "AUTOKEN_LOG");
+                read_tracked_env(tcx, "AUTOKEN_COLOR");
+
+                tcx.sess
+                    .time("autoken_analysis", || crate::analyzer::analyze(tcx))
+            });
         }
 
         Compilation::Continue
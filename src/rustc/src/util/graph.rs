@@ -24,6 +24,10 @@ pub struct GraphPropagator<'f, Cx, Node, Data> {
 
     // An index-map from depths to the set of nodes which recurse back to it.
     scc_sets: Vec<FxHashSet<Node>>,
+
+    // A mapping from nodes which have finished computing but whose SCC has yet to be unified to the
+    // depth of the earliest ancestor they recurse back to. Their facts are still incomplete.
+    pending_map: FxHashMap<Node, u32>,
 }
 
 impl<'f, Cx, Node, Data> GraphPropagator<'f, Cx, Node, Data>
@@ -38,6 +42,7 @@ where
             fact_map: FxHashMap::default(),
             depth_map: FxHashMap::default(),
             scc_sets: Vec::new(),
+            pending_map: FxHashMap::default(),
         }
     }
 
@@ -73,7 +78,14 @@ where
     fn analyze_inner(&mut self, my_node: Node, my_depth: u32) -> u32 {
         // Ensure that we're not recursing to a node which is in the process of being visited.
         if self.fact_map.contains_key(&my_node) {
-            return INFINITE_DEPTH;
+            // If the node belongs to an SCC which is still being visited, we belong to that SCC as
+            // well and must have its facts propagated to us once the SCC is unified. Otherwise,
+            // we would be stuck with whatever facts the node had collected so far.
+            return self
+                .pending_map
+                .get(&my_node)
+                .copied()
+                .unwrap_or(INFINITE_DEPTH);
         }
 
         if let Some(depth) = self.depth_map.get(&my_node) {
@@ -99,6 +111,7 @@ where
         // We don't push ourself because there's no need to clone our own facts.
         if min_back_depth != INFINITE_DEPTH && min_back_depth != my_depth {
             self.scc_sets[min_back_depth as usize].insert(my_node);
+            self.pending_map.insert(my_node, min_back_depth);
         }
 
         // If we're the root of the SCC, let's copy over all our facts to everything in the SCC.
@@ -106,6 +119,7 @@ where
         let min_back_depth_for_caller = if min_back_depth == my_depth {
             for node in my_scc_set {
                 self.fact_map.get_mut(&node).unwrap().clone_from(&my_facts);
+                self.pending_map.remove(&node);
             }
 
             // We just discharged the back-references and parent functions only care whether
@@ -118,7 +132,12 @@ where
             // their ancestors were referenced by a descendant.
             INFINITE_DEPTH
         } else {
-            // Otherwise, an even earlier function has to take care of unifying the SCC.
+            // Otherwise, an even earlier function has to take care of unifying the SCC. Our depth is
+            // about to be reused by our siblings so nodes waiting on us must now wait on it instead.
+            for &node in &my_scc_set {
+                self.pending_map.insert(node, min_back_depth);
+            }
+
             self.scc_sets.last_mut().unwrap().extend(my_scc_set);
 
             // An ancestor still has to handle this.
@@ -3,7 +3,6 @@ use std::{
     io::ErrorKind,
     mem,
     path::{Path, PathBuf},
-    str::FromStr,
 };
 
 use rustc_ast::AttrId;
@@ -26,11 +25,25 @@ use rustc_type_ir::{TyDecoder, TyEncoder};
 
 // === Entry-points === //
 
+/// Identifies a file as AuToken metadata.
+const META_MAGIC: &[u8; 8] = b"AUTOKEN\0";
+
+/// The version of the metadata layout. This must be bumped whenever the layout of any serialized
+/// fact changes so that stale files from older versions of the analyzer are ignored rather than
+/// mis-decoded.
+const META_FORMAT_VERSION: u32 = 4;
+
 pub fn save_to_file<'tcx, T>(tcx: TyCtxt<'tcx>, name: &str, path: &Path, item: &T)
 where
     T: for<'a> Encodable<AutokenEncoder<'tcx, 'a>>,
 {
-    let encoder = FileEncoder::new(path).unwrap_or_else(|err| {
+    // We write to a temporary file in the same directory and move it into place once it's complete
+    // so that an interrupted build can never leave a truncated file behind for dependents to load.
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let encoder = FileEncoder::new(&tmp_path).unwrap_or_else(|err| {
         tcx.dcx()
             .fatal(format!("failed to serialize {name} to file: {err}"));
     });
@@ -43,6 +56,10 @@ where
         predicate_shorthands: FxHashMap::default(),
     };
 
+    // Encode the header
+    encoder.emit_raw_bytes(META_MAGIC);
+    encoder.emit_raw_bytes(&META_FORMAT_VERSION.to_le_bytes());
+
     // Encode set of source files to preload for span generation
     for file in tcx.sess.source_map().files().iter() {
         let FileName::Real(RealFileName::LocalPath(path)) = &file.name else {
@@ -66,6 +83,13 @@ where
     item.encode(&mut encoder);
 
     if let Err((_, err)) = encoder.encoder.finish() {
+        let _ = fs::remove_file(&tmp_path);
+        tcx.dcx()
+            .fatal(format!("failed to serialize {name} to file: {err}"));
+    }
+
+    if let Err(err) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
         tcx.dcx()
             .fatal(format!("failed to serialize {name} to file: {err}"));
     }
@@ -86,9 +110,23 @@ where
         }
     };
 
+    // Ensure that the file was produced by a compatible analyzer
+    let header_len = META_MAGIC.len() + mem::size_of::<u32>();
+    let is_compatible = data.len() >= header_len
+        && data.starts_with(META_MAGIC)
+        && data[META_MAGIC.len()..header_len] == META_FORMAT_VERSION.to_le_bytes();
+
+    if !is_compatible {
+        tcx.dcx().warn(format!(
+            "ignoring {name} at {} since it was generated by an incompatible version of AuToken",
+            path.display(),
+        ));
+        return None;
+    }
+
     let mut decoder = AutokenDecoder {
         tcx,
-        decoder: MemDecoder::new(&data, 0),
+        decoder: MemDecoder::new(&data, header_len),
         ty_cache: FxHashMap::default(),
     };
 
@@ -107,14 +145,24 @@ where
 }
 
 pub fn get_crate_cache_path(tcx: TyCtxt<'_>, krate: CrateNum) -> PathBuf {
-    // TODO: Find a better way
-    PathBuf::from_str(&format!(
-        "{}/autoken_{}_{:x}.meta",
-        std::env::var("CARGO_TARGET_DIR").unwrap(),
+    // `cargo-autoken` tells us exactly where to store our metadata. We fall back to the cargo target
+    // directory for users invoking the wrapper through plain `cargo`.
+    let Some(meta_dir) =
+        std::env::var_os("AUTOKEN_META_DIR").or_else(|| std::env::var_os("CARGO_TARGET_DIR"))
+    else {
+        tcx.dcx().fatal(
+            "failed to determine where to store AuToken metadata; set either the \
+             `AUTOKEN_META_DIR` or the `CARGO_TARGET_DIR` environment variable",
+        );
+    };
+
+    let mut path = PathBuf::from(meta_dir);
+    path.push(format!(
+        "autoken_{}_{:x}.meta",
         tcx.crate_name(krate),
         tcx.stable_crate_id(krate)
-    ))
-    .unwrap()
+    ));
+    path
 }
 
 // === Encoder === //
@@ -12,11 +12,16 @@ use rustc_hir::{
     def_id::{DefId, DefIndex, LocalDefId},
     ExprKind, ImplItemKind, ItemKind, Node, TraitFn, TraitItemKind,
 };
+use rustc_index::bit_set::BitSet;
 use rustc_middle::{
-    mir::{Body, CastKind, LocalDecls, Rvalue, StatementKind, Terminator, TerminatorKind},
+    mir::{
+        visit::{MutatingUseContext, NonMutatingUseContext, PlaceContext, Visitor},
+        BasicBlock, Body, CastKind, Local, Location, Operand, Rvalue, StatementKind, Terminator,
+        TerminatorKind,
+    },
     ty::{
-        adjustment::PointerCoercion, fold::FnMutDelegate, GenericArg, Instance, InstanceDef,
-        ParamEnv, Ty, TyCtxt, TyKind, TypeAndMut, VtblEntry,
+        adjustment::PointerCoercion, fold::FnMutDelegate, ClosureKind, GenericArg, Instance,
+        InstanceDef, ParamEnv, Ty, TyCtxt, TyKind, TypeAndMut, VtblEntry,
     },
 };
 use rustc_span::{Span, Symbol};
@@ -105,6 +110,8 @@ pub fn try_grab_base_mir_of_def_id(tcx: TyCtxt<'_>, id: LocalDefId) -> Option<&S
 // === `has_optimized_mir` === //
 
 pub fn has_optimized_mir(tcx: TyCtxt<'_>, did: DefId) -> bool {
+    // N.B. coroutines (e.g. the state machines generated for `async fn` bodies and `async` blocks)
+    // and coroutine-closures are all given `DefKind::Closure` so they're accepted here as well.
     let is_func_kind = matches!(
         tcx.def_kind(did),
         DefKind::Fn | DefKind::AssocFn | DefKind::Closure
@@ -176,9 +183,38 @@ pub fn try_grab_optimized_mir_of_instance<'tcx>(
             MirGrabResult::Dynamic
         }
 
-        // TODO: Handle these properly.
-        InstanceDef::ConstructCoroutineInClosureShim { .. }
-        | InstanceDef::CoroutineKindShim { .. } => MirGrabResult::Dynamic,
+        // These are used to call async closures through `AsyncFnMut` or `AsyncFnOnce`. Rustc never
+        // builds them for `AsyncFn` since that trait is implemented by the closure's body directly.
+        // The by-mut and by-move variants of a coroutine are stored alongside its body so we only
+        // need to make sure that its MIR is available. We fall back to treating them as dynamic if
+        // not, like we did before these shims were supported.
+        InstanceDef::ConstructCoroutineInClosureShim { target_kind, .. } => {
+            if target_kind != ClosureKind::Fn {
+                MirGrabResult::Found(tcx.instance_mir(instance))
+            } else {
+                MirGrabResult::Dynamic
+            }
+        }
+        InstanceDef::CoroutineKindShim {
+            coroutine_def_id,
+            target_kind,
+        } => {
+            let has_body = tcx.is_mir_available(coroutine_def_id) && {
+                let body = tcx.optimized_mir(coroutine_def_id);
+
+                match target_kind {
+                    ClosureKind::Fn => false,
+                    ClosureKind::FnMut => body.coroutine_by_mut_body().is_some(),
+                    ClosureKind::FnOnce => body.coroutine_by_move_body().is_some(),
+                }
+            };
+
+            if has_body {
+                MirGrabResult::Found(tcx.instance_mir(instance))
+            } else {
+                MirGrabResult::Dynamic
+            }
+        }
     }
 }
 
@@ -188,7 +224,7 @@ pub fn try_grab_optimized_mir_of_instance<'tcx>(
 pub enum TerminalCallKind<'tcx> {
     Static(Span, Instance<'tcx>),
     Generic(Span, Instance<'tcx>),
-    Dynamic,
+    Dynamic(Span),
 }
 
 pub fn get_callee_from_terminator<'tcx>(
@@ -196,22 +232,40 @@ pub fn get_callee_from_terminator<'tcx>(
     param_env: ParamEnv<'tcx>,
     instance: MaybeConcretizedFunc<'tcx>,
     terminator: &Option<Terminator<'tcx>>,
-    local_decls: &LocalDecls<'tcx>,
+    body: &Body<'tcx>,
 ) -> Option<TerminalCallKind<'tcx>> {
+    // N.B. calls introduced by desugaring (e.g. `Try::branch` and `FromResidual::from_residual` for
+    // `?`, or `IntoIterator::into_iter` and `Iterator::next` for `for` loops) are ordinary `Call`
+    // terminators to trait methods so they're resolved to the user's impls like any other call.
     match &terminator.as_ref()?.kind {
         TerminatorKind::Call {
-            func: dest_func,
+            func: dest_func_op,
             fn_span,
             ..
         } => {
             // Get the type of the function local we're calling.
-            let dest_func = dest_func.ty(local_decls, tcx);
+            let dest_func = dest_func_op.ty(body, tcx);
             let dest_func = instance.instantiate_arg(tcx, param_env, dest_func);
 
             // Attempt to fetch a `DefId` and arguments for the callee.
             let (dest_did, dest_args) = match dest_func.kind() {
                 TyKind::FnPtr(_) => {
-                    return Some(TerminalCallKind::Dynamic);
+                    // Function pointers reified from a known function in this body are still
+                    // static calls.
+                    let Some(reified) = dest_func_op
+                        .place()
+                        .and_then(|place| place.as_local())
+                        .and_then(|local| resolve_reified_fn_ptr(tcx, body, local, 0))
+                    else {
+                        return Some(TerminalCallKind::Dynamic(*fn_span));
+                    };
+
+                    let reified = instance.instantiate_arg(tcx, param_env, reified);
+                    let TyKind::FnDef(did, args) = reified.kind() else {
+                        unreachable!();
+                    };
+
+                    (*did, *args)
                 }
                 TyKind::FnDef(did, args) => (*did, *args),
                 TyKind::Closure(did, args) => (*did, args.as_closure().args),
@@ -231,10 +285,143 @@ pub fn get_callee_from_terminator<'tcx>(
                 Err(_) => None,
             }
         }
+        // N.B. the pinned toolchain has no `TailCall` terminator. If a toolchain bump introduces
+        // one, it must be handled here as a `Call` whose callee's borrows are attributed to the
+        // tail-caller rather than being silently skipped like the other non-call terminators.
         _ => None,
     }
 }
 
+/// Determines the `FnDef` type a function pointer local was reified from. This only succeeds if the
+/// local is assigned exactly once, is never mutated through a borrow, and its sole definition
+/// either reifies a function item or copies another local satisfying the same constraints.
+fn resolve_reified_fn_ptr<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    body: &Body<'tcx>,
+    local: Local,
+    depth: u32,
+) -> Option<Ty<'tcx>> {
+    // Arguments are implicitly defined by the caller.
+    if depth > 8 || local.as_usize() <= body.arg_count {
+        return None;
+    }
+
+    struct DefFinder {
+        local: Local,
+        defs: Vec<(Location, MutatingUseContext)>,
+    }
+
+    impl<'tcx> Visitor<'tcx> for DefFinder {
+        fn visit_local(&mut self, local: Local, context: PlaceContext, location: Location) {
+            if local == self.local {
+                if let PlaceContext::MutatingUse(context) = context {
+                    self.defs.push((location, context));
+                }
+            }
+        }
+    }
+
+    let mut finder = DefFinder {
+        local,
+        defs: Vec::new(),
+    };
+    finder.visit_body(body);
+
+    let [(location, MutatingUseContext::Store)] = finder.defs[..] else {
+        return None;
+    };
+
+    let StatementKind::Assign(assign) = &body.stmt_at(location).left()?.kind else {
+        return None;
+    };
+
+    let (place, rvalue) = &**assign;
+    if place.as_local() != Some(local) {
+        return None;
+    }
+
+    match rvalue {
+        Rvalue::Cast(CastKind::PointerCoercion(PointerCoercion::ReifyFnPointer), op, _) => {
+            let reified = op.ty(body, tcx);
+            matches!(reified.kind(), TyKind::FnDef(..)).then_some(reified)
+        }
+        Rvalue::Use(Operand::Copy(src) | Operand::Move(src)) => {
+            resolve_reified_fn_ptr(tcx, body, src.as_local()?, depth + 1)
+        }
+        _ => None,
+    }
+}
+
+/// Determines whether the function pointer stored in `local` is only ever called by this body,
+/// either directly or through copies which [`resolve_reified_fn_ptr`] can see through.
+fn is_fn_ptr_only_called_locally<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    body: &Body<'tcx>,
+    local: Local,
+    depth: u32,
+) -> bool {
+    struct UseFinder {
+        local: Local,
+        uses: Vec<(Location, PlaceContext)>,
+    }
+
+    impl<'tcx> Visitor<'tcx> for UseFinder {
+        fn visit_local(&mut self, local: Local, context: PlaceContext, location: Location) {
+            if local == self.local {
+                self.uses.push((location, context));
+            }
+        }
+    }
+
+    if depth > 8 || resolve_reified_fn_ptr(tcx, body, local, 0).is_none() {
+        return false;
+    }
+
+    let mut finder = UseFinder {
+        local,
+        uses: Vec::new(),
+    };
+    finder.visit_body(body);
+
+    finder
+        .uses
+        .iter()
+        .all(|&(location, context)| match context {
+            PlaceContext::NonUse(_) | PlaceContext::MutatingUse(MutatingUseContext::Store) => true,
+            PlaceContext::NonMutatingUse(
+                NonMutatingUseContext::Copy | NonMutatingUseContext::Move,
+            ) => {
+                let bb_data = &body.basic_blocks[location.block];
+
+                if let Some(stmt) = bb_data.statements.get(location.statement_index) {
+                    let StatementKind::Assign(assign) = &stmt.kind else {
+                        return false;
+                    };
+
+                    let (place, rvalue) = &**assign;
+                    let Rvalue::Use(Operand::Copy(src) | Operand::Move(src)) = rvalue else {
+                        return false;
+                    };
+
+                    src.as_local() == Some(local)
+                        && place.as_local().is_some_and(|dest| {
+                            is_fn_ptr_only_called_locally(tcx, body, dest, depth + 1)
+                        })
+                } else {
+                    let TerminatorKind::Call { func, args, .. } = &bb_data.terminator().kind else {
+                        return false;
+                    };
+
+                    func.place().and_then(|place| place.as_local()) == Some(local)
+                        && args
+                            .iter()
+                            .all(|arg| arg.node.place().map_or(true, |place| place.local != local))
+                }
+            }
+            _ => false,
+        })
+}
+
 // === Unsizing Analysis === //
 
 // Referenced from https://github.com/rust-lang/rust/blob/4b85902b438f791c5bfcb6b1c5b476d5b88e2bef/compiler/rustc_codegen_cranelift/src/unsize.rs#L62
@@ -291,7 +478,7 @@ pub fn for_each_concrete_unsized_func<'tcx>(
             let StatementKind::Assign(stmt) = &stmt.kind else {
                 continue;
             };
-            let (_place, rvalue) = &**stmt;
+            let (place, rvalue) = &**stmt;
 
             let Rvalue::Cast(CastKind::PointerCoercion(kind), from_op, to_ty) = rvalue else {
                 continue;
@@ -307,6 +494,15 @@ pub fn for_each_concrete_unsized_func<'tcx>(
                         unreachable!()
                     };
 
+                    // Calls through pointers which never leave this body are analyzed as static
+                    // calls so the function isn't really unsized.
+                    if place
+                        .as_local()
+                        .is_some_and(|local| is_fn_ptr_only_called_locally(tcx, body, local, 0))
+                    {
+                        continue;
+                    }
+
                     if let Ok(Some(func)) =
                         try_resolve_instance(tcx, param_env, Instance::new(*def, generics))
                     {
@@ -484,3 +680,43 @@ fn unpack_steal<T>(steal: &Steal<T>) -> &RdsRwLock<Option<T>> {
         &*(steal as *const Steal<T> as *const RdsRwLock<Option<T>>)
     }
 }
+
+// === Loops === //
+
+/// Collects the natural loops of `body` as pairs of loop headers and the blocks belonging to the
+/// loop, including the header. Loops sharing a header are merged.
+pub fn find_natural_loops(body: &Body<'_>) -> Vec<(BasicBlock, BitSet<BasicBlock>)> {
+    let dominators = body.basic_blocks.dominators();
+    let predecessors = body.basic_blocks.predecessors();
+    let mut loops = FxHashMap::<BasicBlock, BitSet<BasicBlock>>::default();
+
+    for (latch, latch_data) in body.basic_blocks.iter_enumerated() {
+        if !dominators.is_reachable(latch) {
+            continue;
+        }
+
+        for header in latch_data.terminator().successors() {
+            // Back-edges are those which jump to a block dominating their source.
+            if !dominators.dominates(header, latch) {
+                continue;
+            }
+
+            let blocks = loops
+                .entry(header)
+                .or_insert_with(|| BitSet::new_empty(body.basic_blocks.len()));
+
+            blocks.insert(header);
+
+            let mut stack = vec![latch];
+            while let Some(bb) = stack.pop() {
+                if blocks.insert(bb) {
+                    stack.extend(predecessors[bb].iter().copied());
+                }
+            }
+        }
+    }
+
+    let mut loops = loops.into_iter().collect::<Vec<_>>();
+    loops.sort_unstable_by_key(|(header, _)| *header);
+    loops
+}
@@ -11,6 +11,7 @@ use rustc_middle::ty::{
 };
 use rustc_span::{ErrorGuaranteed, Symbol};
 use rustc_trait_selection::traits::ObligationCtxt;
+use tracing::info;
 
 use super::hash::{FxHashMap, FxHashSet};
 
@@ -50,25 +51,81 @@ pub fn get_fn_sig_maybe_closure(tcx: TyCtxt<'_>, def_id: DefId) -> UnboundFnRetu
     EarlyBinder::bind(match tcx.type_of(def_id).skip_binder().kind() {
         TyKind::Coroutine(_, args) => Binder::dummy(args.as_coroutine().sig().return_ty),
         TyKind::Closure(_, args) => args.as_closure().sig().output(),
+        // N.B. async closures return a coroutine whose output is what the user actually wrote.
+        TyKind::CoroutineClosure(_, args) => args
+            .as_coroutine_closure()
+            .coroutine_closure_sig()
+            .map_bound(|sig| sig.return_ty),
         _ => tcx.fn_sig(def_id).skip_binder().output(),
     })
 }
 
+#[derive(Debug, Clone)]
+pub enum FindRegionError {
+    /// No free region has the requested name. Contains the names of the free regions which do
+    /// exist.
+    NotFound(Vec<Symbol>),
+
+    /// Several distinct free regions bound at the same level have the requested name.
+    Ambiguous,
+}
+
+/// Finds the free region of `ty` with the given name.
+///
+/// Late-bound regions which are referenced from within a nested binder (e.g. that of a `for<'b>`
+/// function pointer) are shifted out so that they can be compared against, and used in place of,
+/// references to the same region from outside that binder. If several distinct regions share the
+/// name, the outermost one is picked.
 pub fn find_region_with_name<'tcx>(
     tcx: TyCtxt<'tcx>,
     ty: Ty<'tcx>,
     name: Symbol,
-) -> Result<Region<'tcx>, Vec<Symbol>> {
-    let mut found_region = None;
+) -> Result<Region<'tcx>, FindRegionError> {
+    let mut candidates = Vec::new();
+
+    // N.B. opaque types (e.g. `impl Iterator<Item = &'a T>`) list the lifetimes they capture in
+    // their generic arguments so this finds those lifetimes without peeking at the hidden type.
+    let _ = ty.fold_with(&mut RegionFolder::new(tcx, &mut |region, idx| {
+        if region.get_name() == Some(name) {
+            // N.B. `RegionFolder` doesn't call us for regions bound within `ty` so `debruijn` is
+            // always at least `idx`.
+            let normalized = match region.kind() {
+                RegionKind::ReBound(debruijn, br) => {
+                    Region::new_bound(tcx, debruijn.shifted_out(idx.as_u32()), br)
+                }
+                _ => region,
+            };
 
-    let _ = ty.fold_with(&mut RegionFolder::new(tcx, &mut |region, _idx| {
-        if found_region.is_none() && region.get_name() == Some(name) {
-            found_region = Some(region);
+            if !candidates.contains(&normalized) {
+                candidates.push(normalized);
+            }
         }
         region
     }));
 
-    found_region.ok_or_else(|| extract_free_region_list(tcx, ty, |re| re.get_name()))
+    // Regions bound by binders further out are "more outer". Everything else (e.g. early-bound
+    // generic parameters) is bound by the item itself and is therefore outermost.
+    let depth = |re: Region<'tcx>| match re.kind() {
+        RegionKind::ReBound(debruijn, _) => debruijn.as_u32(),
+        _ => u32::MAX,
+    };
+
+    let Some(outermost) = candidates.iter().map(|&re| depth(re)).max() else {
+        return Err(FindRegionError::NotFound(extract_free_region_list(
+            tcx,
+            ty,
+            |re| re.get_name(),
+        )));
+    };
+
+    let mut outermost = candidates.into_iter().filter(|&re| depth(re) == outermost);
+    let found = outermost.next().unwrap();
+
+    if outermost.next().is_some() {
+        return Err(FindRegionError::Ambiguous);
+    }
+
+    Ok(found)
 }
 
 pub fn extract_free_region_list<'tcx, R>(
@@ -509,9 +566,20 @@ where
     //     - If old solver:
     //       TODO: Document
     //
-    ObligationCtxt::new(&tcx.infer_ctxt().build())
-        .deeply_normalize(&ObligationCause::dummy(), param_env, ty)
-        .unwrap()
+    // Normalization can fail on ill-formed inputs such as those found in crates with type errors.
+    // Those crates have already been rejected so we just hand back the un-normalized value and let
+    // the analysis limp along.
+    match ObligationCtxt::new(&tcx.infer_ctxt().build()).deeply_normalize(
+        &ObligationCause::dummy(),
+        param_env,
+        ty.clone(),
+    ) {
+        Ok(ty) => ty,
+        Err(errors) => {
+            info!(?ty, ?param_env, ?errors, "failed to normalize");
+            ty
+        }
+    }
 }
 
 // === FunctionCallAndRegions === //
@@ -761,6 +829,15 @@ where
             (TyKind::Closure(_, left), TyKind::Closure(_, right)) => {
                 self.traverse_generics(left, right);
             }
+            (TyKind::CoroutineClosure(_, left), TyKind::CoroutineClosure(_, right)) => {
+                self.traverse_generics(left, right);
+            }
+            (TyKind::Coroutine(_, left), TyKind::Coroutine(_, right)) => {
+                self.traverse_generics(left, right);
+            }
+            (TyKind::CoroutineWitness(_, left), TyKind::CoroutineWitness(_, right)) => {
+                self.traverse_generics(left, right);
+            }
             (TyKind::Tuple(left), TyKind::Tuple(right)) => {
                 self.traverse_type_lists(left, right);
             }
@@ -768,11 +845,6 @@ where
                 self.traverse_generics(left.args, right.args);
             }
 
-            // Unsupported.
-            (TyKind::CoroutineClosure(..), TyKind::CoroutineClosure(..)) => todo!(),
-            (TyKind::Coroutine(..), TyKind::Coroutine(..)) => todo!(),
-            (TyKind::CoroutineWitness(..), TyKind::CoroutineWitness(..)) => todo!(),
-
             // All these types are dead ends.
             (TyKind::Bool, TyKind::Bool) => {}
             (TyKind::Char, TyKind::Char) => {}
@@ -0,0 +1,77 @@
+#![feature(rustc_private)]
+
+use std::{
+    env, fs, process,
+    sync::{Arc, Mutex},
+};
+
+use autoken_rustc::{
+    diagnostic::{Diagnostic, DiagnosticLevel},
+    entry::run_with_diagnostic_sink,
+};
+
+/// Compiles `source` as a library crate and collects every diagnostic the compiler emits.
+///
+/// N.B. the analyzer can only run once per process so each test binary may only call this once.
+fn collect_diagnostics(name: &str, source: &str) -> (i32, Vec<Diagnostic>) {
+    let dir = env::temp_dir().join(format!("autoken-sink-{name}-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("lib.rs");
+    fs::write(&path, source).unwrap();
+
+    let collected = Arc::new(Mutex::new(Vec::new()));
+    let sink = collected.clone();
+    let code = run_with_diagnostic_sink(
+        vec![
+            "autoken-rustc".to_string(),
+            path.display().to_string(),
+            "--crate-type=lib".to_string(),
+            format!("--crate-name={name}"),
+            "--edition=2021".to_string(),
+            "--emit=metadata".to_string(),
+            format!("--out-dir={}", dir.display()),
+        ],
+        Box::new(move |diag| sink.lock().unwrap().push(diag)),
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+
+    let diagnostics = collected.lock().unwrap().clone();
+    (code, diagnostics)
+}
+
+#[test]
+fn collects_rustc_errors() {
+    let (code, diagnostics) = collect_diagnostics(
+        "type_error",
+        "pub fn number() -> u32 {\n    \"not a number\"\n}\n",
+    );
+
+    assert_ne!(code, 0);
+
+    let errors = diagnostics
+        .iter()
+        .filter(|diag| diag.level == DiagnosticLevel::Error)
+        .collect::<Vec<_>>();
+
+    assert_eq!(errors.len(), 1, "{diagnostics:#?}");
+    assert!(errors[0].message.contains("mismatched types"));
+    assert_eq!(
+        errors[0]
+            .primary_span
+            .as_ref()
+            .map(|span| (span.line, span.column)),
+        Some((2, 5)),
+    );
+    assert_eq!(errors[0].slug, None);
+
+    // rustc's closing "aborting due to..." summary is delivered as a note.
+    assert!(
+        diagnostics
+            .iter()
+            .any(|diag| diag.level == DiagnosticLevel::Note
+                && diag.message.starts_with("aborting due to")),
+        "{diagnostics:#?}",
+    );
+}
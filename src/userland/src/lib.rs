@@ -97,6 +97,17 @@
 #![doc = "\tcargo run"]
 //! ```
 //!
+//! If you find yourself passing the same flags to `cargo autoken check` every time, you can specify
+//! their defaults in a `[package.metadata.autoken]` (or `[workspace.metadata.autoken]`) table in
+//! your `Cargo.toml` file. Flags passed on the command line take precedence over these defaults.
+//!
+//! ```toml
+//! [package.metadata.autoken]
+//! target = "x86_64-unknown-linux-gnu"
+//! old-artifacts = "delete"
+//! disable-toolchain-checks = false
+//! ```
+//!
 //! And that it! Have fun!
 //!
 //! # High-Level Usage
@@ -256,11 +267,12 @@
 //!   --> src/main.rs:11:25
 //!    |
 //! 11 |     let my_func: fn() = increment_counter;
-//!    |                         ^^^^^^^^^^^^^^^^^
+//!    |                         ^^^^^^^^^^^^^^^^^ increment_counter is unsized here
 //!    |
 //!    = note: uses &mut MyCap.
+//!    = note: for more information about this diagnostic, try `cargo autoken explain cannot-unsize`
 //!
-//! note: increment_counter was unsized
+//! note: increment_counter is defined here
 //!   --> src/main.rs:4:1
 //!    |
 //! 4  | fn increment_counter() {
@@ -269,6 +281,8 @@
 //!
 //! If, for some reason, you need to "smuggle" access to a `cap!` past a dynamic dispatch boundary,
 //! you can use the [`Borrows`](crate:Borrows) object and its alias [`BorrowsOne`](crate:BorrowsOne).
+//! The [`smuggle!`](crate::smuggle) macro is shorthand for acquiring one of these for a single
+//! `cap!` token.
 //!
 //! `Borrows` is an object representing a borrow of a set of capabilities. If you have an mutable
 //! reference to it, you are effectively borrowing that entire set of capabilities mutably. You can
@@ -360,6 +374,7 @@
 //!    |
 //!    = help: first borrow originates from get_singleton::<'_>
 //!    = help: later borrow originates from get_singleton::<'_>
+//!    = note: for more information about this diagnostic, try `cargo autoken explain conflicting-borrows`
 //! ```
 //!
 //! In effect, you could think of `autoken::tie!` as introducing a new virtual parameter to your
@@ -486,6 +501,27 @@
 //! to indicate that borrows inside its block don't affect its caller. Feel free to read the macro's
 //! source code for all the gory details!
 //!
+//! Finally, `cargo-autoken` compiles every crate it analyzes with `--cfg autoken`. You can use this
+//! to write code which should only exist while AuToken is looking at it—for example, a borrow which
+//! only serves to tell the analyzer that a function must never be called while some token is
+//! borrowed:
+//!
+//! ```rust
+//! autoken::cap! {
+//!     pub MyCap = Vec<u32>;
+//! }
+//!
+//! fn clear_everything() {
+//!     #[cfg(autoken)]
+//!     let _ = autoken::BorrowsOne::<MyCap>::acquire_mut();
+//!
+//!     // ...
+//! }
+//! ```
+//!
+//! Since `cargo-autoken` only ever checks your crates, code behind `#[cfg(autoken)]` never makes it
+//! into a binary built by a stock `cargo build`.
+//!
 //! # Semantics of Generics
 //!
 //! AuToken takes a ["substitution failure is not an error"](https://en.wikipedia.org/wiki/Substitution_failure_is_not_an_error)
@@ -628,8 +664,9 @@
 //! 7 |     let b = g.run();
 //!   |             ^^^^^^^ value later borrowed mutably
 //!   |
-//!  = help: first borrow originates from <Breaks as MyTrait>::run::<'_>
-//!  = help: later borrow originates from <Breaks as MyTrait>::run::<'_>
+//!   = help: first borrow originates from <Breaks as MyTrait>::run::<'_>
+//!   = help: later borrow originates from <Breaks as MyTrait>::run::<'_>
+//!   = note: this restriction is imposed by the generic calls to `MyTrait::run`, which may resolve to implementations whose borrows conflict
 //! ```
 //!
 //! Same goes with just unsizing functions. In this case, `my_func`'s unsizing of the provided closure
@@ -659,11 +696,11 @@
 //!   --> src/main.rs:2:31
 //!    |
 //! 2  |     let f: &mut dyn FnMut() = &mut f;
-//!    |                               ^^^^^^
+//!    |                               ^^^^^^ demo_breaks::{closure#0} is unsized here
 //!    |
 //!    = note: uses &mut u32.
 //!
-//! note: demo_breaks::{closure#0} was unsized
+//! note: demo_breaks::{closure#0} is defined here
 //!   --> src/main.rs:12:13
 //!    |
 //! 12 |     my_func(|| {
@@ -1163,15 +1200,72 @@
 //! of the "Rust Programming Language Community" Discord server and of the [rust-lang Zulip chat](https://rust-lang.zulipchat.com/).
 //! Thank you all, so very much, for your help!
 
-use std::{fmt, marker::PhantomData};
+use std::{any::TypeId, fmt, marker::PhantomData, ptr::NonNull};
 
 // === TokenSet === //
 
 mod sealed {
-    pub trait TokenSet {}
+    use std::fmt;
+
+    pub trait TokenSet {
+        fn describe(f: &mut fmt::Formatter<'_>) -> fmt::Result;
+    }
 }
 
-pub trait TokenSet: sealed::TokenSet {}
+/// A type-level set of token borrows.
+///
+/// Tuples of up to 32 token sets are the union of their elements. Since tuples are token sets
+/// themselves, larger unions can be written by nesting tuples, which the analyzer flattens:
+///
+/// ```
+/// use autoken::{Mut, Ref, TokenSet};
+///
+/// type Quad = (Mut<u8>, Mut<u16>, Ref<u32>, Ref<u64>);
+/// type Wide = (Quad, Quad, Quad, Quad, Quad, Quad, Quad, Quad, Quad);
+/// type Deep = ((((Wide,),),), (((Mut<i8>,),),));
+///
+/// let mut count = 0;
+/// Deep::for_each_component(&mut |_, _| count += 1);
+/// assert_eq!(count, 37);
+/// ```
+pub trait TokenSet: sealed::TokenSet {
+    /// Calls `f` with the [`TypeId`] and [`Mutability`] of every token borrowed by this set.
+    ///
+    /// This reflects the set at runtime, which is mostly useful for logging and debugging. A token
+    /// may be reported more than once if several components of a union borrow it.
+    ///
+    /// ```
+    /// use std::any::TypeId;
+    /// use autoken::{Diff, DowngradeRef, Mut, Mutability, Ref, TokenSet};
+    ///
+    /// type Set = (Mut<u32>, DowngradeRef<Mut<i32>>, Diff<(Ref<u8>, Mut<u16>), Ref<u8>>);
+    ///
+    /// let mut components = Vec::new();
+    /// Set::for_each_component(&mut |id, mutability| {
+    ///     println!("{id:?}: {mutability:?}");
+    ///     components.push((id, mutability));
+    /// });
+    ///
+    /// assert_eq!(
+    ///     components,
+    ///     [
+    ///         (TypeId::of::<u32>(), Mutability::Mut),
+    ///         (TypeId::of::<i32>(), Mutability::Ref),
+    ///         (TypeId::of::<u16>(), Mutability::Mut),
+    ///     ],
+    /// );
+    /// ```
+    fn for_each_component(f: &mut dyn FnMut(TypeId, Mutability))
+    where
+        Self: 'static;
+}
+
+/// The manner in which a [`TokenSet`] borrows one of its tokens.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Mutability {
+    Ref,
+    Mut,
+}
 
 // Ref
 pub struct Ref<T: ?Sized> {
@@ -1180,8 +1274,19 @@ pub struct Ref<T: ?Sized> {
     __autoken_ref_ty_marker: PhantomData<T>,
 }
 
-impl<T: ?Sized> TokenSet for Ref<T> {}
-impl<T: ?Sized> sealed::TokenSet for Ref<T> {}
+impl<T: ?Sized> TokenSet for Ref<T> {
+    fn for_each_component(f: &mut dyn FnMut(TypeId, Mutability))
+    where
+        Self: 'static,
+    {
+        f(TypeId::of::<T>(), Mutability::Ref);
+    }
+}
+impl<T: ?Sized> sealed::TokenSet for Ref<T> {
+    fn describe(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Ref<{}>", std::any::type_name::<T>())
+    }
+}
 
 // Mut
 pub struct Mut<T: ?Sized> {
@@ -1190,38 +1295,179 @@ pub struct Mut<T: ?Sized> {
     __autoken_mut_ty_marker: PhantomData<T>,
 }
 
-impl<T: ?Sized> TokenSet for Mut<T> {}
-impl<T: ?Sized> sealed::TokenSet for Mut<T> {}
+impl<T: ?Sized> TokenSet for Mut<T> {
+    fn for_each_component(f: &mut dyn FnMut(TypeId, Mutability))
+    where
+        Self: 'static,
+    {
+        f(TypeId::of::<T>(), Mutability::Mut);
+    }
+}
+impl<T: ?Sized> sealed::TokenSet for Mut<T> {
+    fn describe(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Mut<{}>", std::any::type_name::<T>())
+    }
+}
 
 // DowngradeRef
+/// The token set `T` with every borrow downgraded to an immutable one.
+///
+/// Downgrading is idempotent and composes with the other combinators. For example,
+/// `DowngradeRef<DowngradeRef<Mut<T>>>` is just `Ref<T>` and `DowngradeRef<Diff<A, B>>` is whatever
+/// `Diff<A, B>` borrows, only immutably.
 pub struct DowngradeRef<T: TokenSet> {
     // N.B. we intentionally include `T` as a type in this structure to ensure that it inherits all
     // the auto-traits of the type.
     __autoken_downgrade_ty_marker: PhantomData<T>,
 }
 
-impl<T: TokenSet> TokenSet for DowngradeRef<T> {}
-impl<T: TokenSet> sealed::TokenSet for DowngradeRef<T> {}
+impl<T: TokenSet> TokenSet for DowngradeRef<T> {
+    fn for_each_component(f: &mut dyn FnMut(TypeId, Mutability))
+    where
+        Self: 'static,
+    {
+        T::for_each_component(&mut |id, _| f(id, Mutability::Ref));
+    }
+}
+impl<T: TokenSet> sealed::TokenSet for DowngradeRef<T> {
+    fn describe(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DowngradeRef<")?;
+        T::describe(f)?;
+        f.write_str(">")
+    }
+}
+
+/// An alias for [`DowngradeRef`] which reads more naturally next to [`MutOf`].
+pub type RefOf<T> = DowngradeRef<T>;
+
+// MutOf
+/// The token set `T` with every borrow upgraded to a mutable one.
+///
+/// This is the counterpart of [`RefOf`] and is mostly useful in generic code which needs exclusive
+/// access to every token in a set regardless of how the set's author chose to borrow them:
+///
+/// ```
+/// use autoken::{Borrows, MutOf, Ref, TokenSet};
+///
+/// autoken::cap! {
+///     pub Config = u32;
+///     pub Log = Vec<u32>;
+/// }
+///
+/// fn exclusively<S: TokenSet, R>(f: impl FnOnce() -> R) -> R {
+///     Borrows::<MutOf<S>>::acquire_mut().absorb(f)
+/// }
+///
+/// fn main() {
+///     let mut log = Vec::new();
+///
+///     autoken::cap! {
+///         Config: &mut 3,
+///         Log: &mut log,
+///     =>
+///         exclusively::<(Ref<Config>, Ref<Log>), _>(|| {
+///             autoken::cap!(mut Log).push(*autoken::cap!(ref Config));
+///         });
+///     }
+///
+///     assert_eq!(log, [3]);
+/// }
+/// ```
+pub struct MutOf<T: TokenSet> {
+    // N.B. we intentionally include `T` as a type in this structure to ensure that it inherits all
+    // the auto-traits of the type.
+    __autoken_upgrade_ty_marker: PhantomData<T>,
+}
+
+impl<T: TokenSet> TokenSet for MutOf<T> {
+    fn for_each_component(f: &mut dyn FnMut(TypeId, Mutability))
+    where
+        Self: 'static,
+    {
+        T::for_each_component(&mut |id, _| f(id, Mutability::Mut));
+    }
+}
+impl<T: TokenSet> sealed::TokenSet for MutOf<T> {
+    fn describe(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MutOf<")?;
+        T::describe(f)?;
+        f.write_str(">")
+    }
+}
 
 // Diff
+/// The token set `A` without the borrows in `B`.
+///
+/// A borrow is only removed if `B` borrows the token with at least the same mutability. In other
+/// words, `Diff<Mut<T>, Mut<T>>` and `Diff<Ref<T>, Ref<T>>` are empty while `Diff<Mut<T>, Ref<T>>`
+/// still borrows `T` mutably.
 pub struct Diff<A: TokenSet, B: TokenSet> {
     // N.B. we intentionally include `T` as a type in this structure to ensure that it inherits all
     // the auto-traits of the type.
     __autoken_diff_ty_marker: PhantomData<(A, B)>,
 }
 
-impl<A: TokenSet, B: TokenSet> TokenSet for Diff<A, B> {}
-impl<A: TokenSet, B: TokenSet> sealed::TokenSet for Diff<A, B> {}
+impl<A: TokenSet, B: TokenSet> TokenSet for Diff<A, B> {
+    fn for_each_component(f: &mut dyn FnMut(TypeId, Mutability))
+    where
+        Self: 'static,
+    {
+        let mut removed = Vec::new();
+        B::for_each_component(&mut |id, mutability| removed.push((id, mutability)));
+
+        A::for_each_component(&mut |id, mutability| {
+            if !removed
+                .iter()
+                .any(|&(removed_id, removed_mut)| removed_id == id && removed_mut >= mutability)
+            {
+                f(id, mutability);
+            }
+        });
+    }
+}
+impl<A: TokenSet, B: TokenSet> sealed::TokenSet for Diff<A, B> {
+    fn describe(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Diff<")?;
+        A::describe(f)?;
+        f.write_str(", ")?;
+        B::describe(f)?;
+        f.write_str(">")
+    }
+}
 
 // Union
-impl TokenSet for () {}
-impl sealed::TokenSet for () {}
+impl TokenSet for () {
+    fn for_each_component(_f: &mut dyn FnMut(TypeId, Mutability)) {}
+}
+impl sealed::TokenSet for () {
+    fn describe(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("()")
+    }
+}
 
 macro_rules! impl_union {
     () => {};
     ($first:ident $($rest:ident)*) => {
-        impl<$first: TokenSet $(, $rest: TokenSet)*> TokenSet for ($first, $($rest,)*) {}
-        impl<$first: TokenSet $(, $rest: TokenSet)*> sealed::TokenSet for ($first, $($rest,)*) {}
+        impl<$first: TokenSet $(, $rest: TokenSet)*> TokenSet for ($first, $($rest,)*) {
+            fn for_each_component(f: &mut dyn FnMut(TypeId, Mutability))
+            where
+                Self: 'static,
+            {
+                $first::for_each_component(f);
+                $($rest::for_each_component(f);)*
+            }
+        }
+        impl<$first: TokenSet $(, $rest: TokenSet)*> sealed::TokenSet for ($first, $($rest,)*) {
+            fn describe(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("(")?;
+                $first::describe(f)?;
+                $(
+                    f.write_str(", ")?;
+                    $rest::describe(f)?;
+                )*
+                f.write_str(")")
+            }
+        }
 
         impl_union!($($rest)*);
     };
@@ -1229,8 +1475,49 @@ macro_rules! impl_union {
 
 impl_union!(T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12 T13 T14 T15 T16 T17 T18 T19 T20 T21 T22 T23 T24 T25 T26 T27 T28 T29 T30 T31 T32);
 
+/// Single-letter re-exports of [`Ref`] and [`Mut`] for code with many token annotations.
+///
+/// These are the same types under a different name so the analyzer treats `M<T>` exactly like
+/// `Mut<T>`.
+///
+/// ```
+/// use autoken::shorthand::{M, R};
+///
+/// autoken::cap! {
+///     pub Config = u32;
+///     pub Log = Vec<u32>;
+/// }
+///
+/// fn record() {
+///     autoken::cap!(mut Log).push(*autoken::cap!(ref Config));
+/// }
+///
+/// fn main() {
+///     let mut log = Vec::new();
+///
+///     autoken::cap! {
+///         Config: &mut 3,
+///         Log: &mut log,
+///     =>
+///         unsafe { autoken::absorb::<(R<Config>, M<Log>), _>(record) };
+///     }
+///
+///     assert_eq!(log, [3]);
+/// }
+/// ```
+pub mod shorthand {
+    pub use crate::{Mut as M, Ref as R};
+}
+
 // === Absorb === //
 
+/// Runs `f`, hiding its borrows of the token set `T` from the caller.
+///
+/// # Safety
+///
+/// The analyzer will no longer report conflicts between `f`'s borrows of `T` and borrows held by
+/// the caller. The caller must ensure that no borrow of a token in `T` is live across this call in
+/// a way which would conflict with `f`'s usage of it.
 pub unsafe fn absorb<T: TokenSet, R>(f: impl FnOnce() -> R) -> R {
     #[doc(hidden)]
     #[allow(clippy::extra_unused_type_parameters)]
@@ -1241,12 +1528,71 @@ pub unsafe fn absorb<T: TokenSet, R>(f: impl FnOnce() -> R) -> R {
     __autoken_absorb_only::<T, R>(f)
 }
 
+/// Runs `f`, hiding its mutable and immutable borrows of the token `T` from the caller.
+///
+/// This is shorthand for [`absorb::<Mut<T>, R>`](absorb).
+///
+/// ```
+/// autoken::cap! {
+///     pub MyCap = u32;
+/// }
+///
+/// fn increment() {
+///     *autoken::cap!(mut MyCap) += 1;
+/// }
+///
+/// fn main() {
+///     autoken::cap! {
+///         MyCap: &mut 1
+///     =>
+///         unsafe { autoken::absorb_mut::<MyCap, _>(increment) };
+///         assert_eq!(*autoken::cap!(ref MyCap), 2);
+///     }
+/// }
+/// ```
+///
+/// # Safety
+///
+/// See [`absorb`].
+pub unsafe fn absorb_mut<T: ?Sized, R>(f: impl FnOnce() -> R) -> R {
+    absorb::<Mut<T>, R>(f)
+}
+
+/// Runs `f`, hiding its immutable borrows of the token `T` from the caller.
+///
+/// This is shorthand for [`absorb::<Ref<T>, R>`](absorb).
+///
+/// ```
+/// autoken::cap! {
+///     pub MyCap = u32;
+/// }
+///
+/// fn read() -> u32 {
+///     *autoken::cap!(ref MyCap)
+/// }
+///
+/// fn main() {
+///     autoken::cap! {
+///         MyCap: &mut 1
+///     =>
+///         assert_eq!(unsafe { autoken::absorb_ref::<MyCap, _>(read) }, 1);
+///     }
+/// }
+/// ```
+///
+/// # Safety
+///
+/// See [`absorb`].
+pub unsafe fn absorb_ref<T: ?Sized, R>(f: impl FnOnce() -> R) -> R {
+    absorb::<Ref<T>, R>(f)
+}
+
 pub type BorrowsOne<T> = Borrows<Mut<T>>;
 
 pub struct Borrows<T: TokenSet> {
     // N.B. we intentionally include `T` as a type in this structure to ensure that it inherits all
     // the auto-traits of the type.
-    _ty: PhantomData<T>,
+    __autoken_borrows_ty_marker: PhantomData<T>,
 }
 
 impl<T: TokenSet> fmt::Debug for Borrows<T> {
@@ -1255,14 +1601,43 @@ impl<T: TokenSet> fmt::Debug for Borrows<T> {
     }
 }
 
+/// Prints the structure of the token set guarded by this object.
+///
+/// ```
+/// use autoken::{Borrows, DowngradeRef, Mut, Ref};
+///
+/// let borrows = unsafe { Borrows::<(Mut<u32>, DowngradeRef<Mut<i32>>)>::new_unchecked() };
+/// assert_eq!(borrows.to_string(), "Borrows<(Mut<u32>, DowngradeRef<Mut<i32>>)>");
+///
+/// let borrows = unsafe { Borrows::<Ref<str>>::new_unchecked() };
+/// assert_eq!(borrows.to_string(), "Borrows<Ref<str>>");
+/// ```
+impl<T: TokenSet> fmt::Display for Borrows<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Borrows<")?;
+        <T as sealed::TokenSet>::describe(f)?;
+        f.write_str(">")
+    }
+}
+
 impl<T: TokenSet> Borrows<T> {
+    /// Fabricates a `Borrows` object without borrowing its tokens from the surrounding context.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the tokens in `T` are not borrowed elsewhere in a way which
+    /// conflicts with the borrows granted by this object.
     pub unsafe fn new_unchecked() -> Self {
-        Self { _ty: PhantomData }
+        Self {
+            __autoken_borrows_ty_marker: PhantomData,
+        }
     }
 
     pub fn acquire_ref<'a>() -> &'a Self {
         tie!('a => set DowngradeRef<T>);
-        &Self { _ty: PhantomData }
+        &Self {
+            __autoken_borrows_ty_marker: PhantomData,
+        }
     }
 
     pub fn acquire_mut<'a>() -> &'a mut Self {
@@ -1270,6 +1645,42 @@ impl<T: TokenSet> Borrows<T> {
         unsafe { &mut *(0x1 as *mut Self) }
     }
 
+    /// Reborrows this object for a shorter lifetime, mirroring `&mut *self`.
+    ///
+    /// This lets a single `&mut Borrows<T>` be handed to several calls in sequence. Unlike calling
+    /// [`Borrows::acquire_mut`] again, which would make a fresh borrow conflicting with this one,
+    /// the reborrow is derived from the tokens this object already holds.
+    ///
+    /// ```
+    /// use autoken::BorrowsOne;
+    ///
+    /// autoken::cap! {
+    ///     pub Counter = u32;
+    /// }
+    ///
+    /// fn bump(borrows: &mut BorrowsOne<Counter>) {
+    ///     Counter::get_mut(borrows, |v| *v += 1);
+    /// }
+    ///
+    /// fn bump_twice(borrows: &mut BorrowsOne<Counter>) {
+    ///     bump(borrows.reborrow());
+    ///     bump(borrows.reborrow());
+    /// }
+    ///
+    /// let mut counter = 0;
+    ///
+    /// autoken::cap! {
+    ///     Counter: &mut counter
+    /// =>
+    ///     bump_twice(BorrowsOne::acquire_mut());
+    /// }
+    ///
+    /// assert_eq!(counter, 2);
+    /// ```
+    pub fn reborrow(&mut self) -> &mut Self {
+        self
+    }
+
     pub fn absorb<R>(&mut self, f: impl FnOnce() -> R) -> R {
         unsafe { absorb::<T, R>(f) }
     }
@@ -1277,6 +1688,225 @@ impl<T: TokenSet> Borrows<T> {
     pub fn absorb_ref<R>(&self, f: impl FnOnce() -> R) -> R {
         unsafe { absorb::<DowngradeRef<T>, R>(f) }
     }
+
+    /// Runs `f` like [`Borrows::absorb`] but lets it return a reference derived from the absorbed
+    /// tokens which keeps this object borrowed for as long as it is alive.
+    ///
+    /// Since this object stands in for the tokens it holds, the returned reference can't be
+    /// invalidated by another user of those tokens. Once it is dead, the object can be used again.
+    ///
+    /// ```
+    /// use autoken::BorrowsOne;
+    ///
+    /// autoken::cap! {
+    ///     pub MyCap = Vec<u32>;
+    /// }
+    ///
+    /// fn first<'a>() -> &'a u32 {
+    ///     autoken::tie!('a => ref MyCap);
+    ///     &autoken::cap!(ref MyCap)[0]
+    /// }
+    ///
+    /// fn push(value: u32) {
+    ///     autoken::cap!(mut MyCap).push(value);
+    /// }
+    ///
+    /// let mut values = vec![1, 2];
+    ///
+    /// autoken::cap! {
+    ///     MyCap: &mut values
+    /// =>
+    ///     let borrows = BorrowsOne::<MyCap>::acquire_mut();
+    ///     let head = *borrows.with_absorbed(first);
+    ///     assert_eq!(head, 1);
+    ///
+    ///     // The returned reference is dead by now so we can use `borrows` again.
+    ///     borrows.absorb(|| push(head));
+    /// }
+    ///
+    /// assert_eq!(values, [1, 2, 1]);
+    /// ```
+    ///
+    /// An arbitrary `R: 'a` can't be returned since nothing would stop it from outliving the borrow
+    /// of this object, which is why `f` has to return a reference with the lifetime of that borrow.
+    pub fn with_absorbed<'a, R: ?Sized>(&'a mut self, f: impl FnOnce() -> &'a R) -> &'a R {
+        unsafe { absorb::<T, &'a R>(f) }
+    }
+
+    /// Like [`Borrows::with_absorbed`] but for closures returning mutable references.
+    pub fn with_absorbed_mut<'a, R: ?Sized>(
+        &'a mut self,
+        f: impl FnOnce() -> &'a mut R,
+    ) -> &'a mut R {
+        unsafe { absorb::<T, &'a mut R>(f) }
+    }
+}
+
+impl<A: TokenSet, B: TokenSet> Borrows<(A, B)> {
+    /// Splits this object into one object for each half of its token set, mirroring
+    /// `slice::split_at_mut`.
+    ///
+    /// Both halves can be used at the same time, which lets two calls requiring different exclusive
+    /// tokens share a single `&mut Borrows` object.
+    ///
+    /// ```
+    /// use autoken::{Borrows, BorrowsOne, Mut};
+    ///
+    /// autoken::cap! {
+    ///     pub Source = Vec<u32>;
+    ///     pub Sink = Vec<u32>;
+    /// }
+    ///
+    /// fn drain<'a>(source: &'a mut BorrowsOne<Source>) -> &'a mut Vec<u32> {
+    ///     Source::get_mut(source, |v| v)
+    /// }
+    ///
+    /// fn transfer(borrows: &mut Borrows<(Mut<Source>, Mut<Sink>)>) {
+    ///     let (source, sink) = borrows.split_disjoint();
+    ///     let source = drain(source);
+    ///     Sink::get_mut(sink, |v| v).append(source);
+    /// }
+    ///
+    /// let mut source = vec![1, 2];
+    /// let mut sink = vec![0];
+    ///
+    /// autoken::cap! {
+    ///     Source: &mut source,
+    ///     Sink: &mut sink,
+    /// =>
+    ///     transfer(Borrows::acquire_mut());
+    /// }
+    ///
+    /// assert_eq!(sink, [0, 1, 2]);
+    /// ```
+    ///
+    /// The two halves must be disjoint. A token that is borrowed mutably by one half and borrowed
+    /// at all by the other is reported as a conflicting borrow within `split_disjoint` once it is
+    /// instantiated with those sets.
+    pub fn split_disjoint(&mut self) -> (&mut Borrows<A>, &mut Borrows<B>) {
+        fn halves<'a, A: TokenSet, B: TokenSet>() -> (&'a mut Borrows<A>, &'a mut Borrows<B>) {
+            tie!('a => set (A, B));
+            (Borrows::acquire_mut(), Borrows::acquire_mut())
+        }
+
+        unsafe { absorb::<(A, B), _>(halves::<A, B>) }
+    }
+}
+
+impl<A: ?Sized, B: ?Sized> Borrows<(Ref<A>, Mut<B>)> {
+    /// Acquires an immutable borrow of `A` alongside a mutable borrow of `B`.
+    ///
+    /// ```
+    /// use autoken::{Borrows, Mut, Ref};
+    ///
+    /// autoken::cap! {
+    ///     pub Config = u32;
+    ///     pub Log = Vec<u32>;
+    /// }
+    ///
+    /// fn record() {
+    ///     let pair = Borrows::<(Ref<Config>, Mut<Log>)>::acquire_ref_mut_pair();
+    ///     let (config, log) = pair.split();
+    ///     let config = Config::get(config, |v| v);
+    ///     Log::get_mut(log, |v| v).push(*config);
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut log = Vec::new();
+    ///
+    ///     autoken::cap! {
+    ///         Config: &mut 3,
+    ///         Log: &mut log,
+    ///     =>
+    ///         record();
+    ///     }
+    ///
+    ///     assert_eq!(log, [3]);
+    /// }
+    /// ```
+    ///
+    /// The pair borrows both tokens for as long as it lives and [`RefMutPair::split`] hands out the
+    /// two borrows at the same time. If `A` and `B` are the same token, the two borrows are reported
+    /// as conflicting within `split` once it is instantiated with that token.
+    pub fn acquire_ref_mut_pair<'a>() -> &'a mut RefMutPair<A, B> {
+        tie!('a => set (Ref<A>, Mut<B>));
+        unsafe { NonNull::<RefMutPair<A, B>>::dangling().as_mut() }
+    }
+}
+
+/// The pair of borrows returned by [`Borrows::acquire_ref_mut_pair`].
+pub struct RefMutPair<A: ?Sized, B: ?Sized> {
+    _ty: PhantomData<Borrows<(Ref<A>, Mut<B>)>>,
+}
+
+impl<A: ?Sized, B: ?Sized> fmt::Debug for RefMutPair<A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RefMutPair").finish_non_exhaustive()
+    }
+}
+
+impl<A: ?Sized, B: ?Sized> RefMutPair<A, B> {
+    /// Splits the pair into its immutable borrow of `A` and its mutable borrow of `B`.
+    pub fn split(&mut self) -> (&BorrowsOne<A>, &mut BorrowsOne<B>) {
+        fn halves<'a, A: ?Sized, B: ?Sized>() -> (&'a BorrowsOne<A>, &'a mut BorrowsOne<B>) {
+            tie!('a => set (Ref<A>, Mut<B>));
+            (BorrowsOne::acquire_ref(), BorrowsOne::acquire_mut())
+        }
+
+        unsafe { absorb::<(Ref<A>, Mut<B>), _>(halves::<A, B>) }
+    }
+}
+
+/// Acquires a [`BorrowsOne`] for a `cap!` token from the surrounding context so that it can be
+/// smuggled past a dynamic dispatch boundary.
+///
+/// `smuggle!(mut MyCap)` expands to `BorrowsOne::<MyCap>::acquire_mut()` and `smuggle!(ref MyCap)`
+/// expands to `BorrowsOne::<MyCap>::acquire_ref()`. Code which runs inside [`Borrows::absorb`] (or
+/// [`Borrows::absorb_ref`] for the `ref` form) of the resulting guard can access the token even if
+/// it is reached through a `dyn` object or function pointer.
+///
+/// ```
+/// autoken::cap! {
+///     pub MyCap = u32;
+/// }
+///
+/// fn increment_counter() {
+///     *autoken::cap!(mut MyCap) += 1;
+/// }
+///
+/// fn read_counter() -> u32 {
+///     *autoken::cap!(ref MyCap)
+/// }
+///
+/// fn main() {
+///     let mut counter = 0;
+///
+///     autoken::cap! {
+///         MyCap: &mut counter
+///     =>
+///         let guard = autoken::smuggle!(mut MyCap);
+///         let mut increment = || guard.absorb(increment_counter);
+///         let increment_dyn: &mut dyn FnMut() = &mut increment;
+///
+///         increment_dyn();
+///         increment_dyn();
+///
+///         let guard = autoken::smuggle!(ref MyCap);
+///         let read = || guard.absorb_ref(read_counter);
+///         let read_dyn: &dyn Fn() -> u32 = &read;
+///
+///         assert_eq!(read_dyn(), 2);
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! smuggle {
+    (ref $ty:ty) => {
+        $crate::BorrowsOne::<$ty>::acquire_ref()
+    };
+    (mut $ty:ty) => {
+        $crate::BorrowsOne::<$ty>::acquire_mut()
+    };
 }
 
 // === Tie === //
@@ -1284,8 +1914,68 @@ impl<T: TokenSet> Borrows<T> {
 #[doc(hidden)]
 pub mod tie_macro_internals {
     pub fn __autoken_declare_tied<I, T: crate::TokenSet, IsUnsafe>() {}
+
+    pub fn __autoken_justify_tie(_reason: &'static str) {}
 }
 
+/// Declares that the borrows of a token set made by the enclosing function are tied to one of the
+/// lifetimes in its return type.
+///
+/// `tie!('a => mut T)` and `tie!('a => ref T)` let the function return values borrowing the token
+/// `T` through the lifetime `'a` while `tie!(set S)` does the same for an entire token set `S`.
+/// Omitting the lifetime declares the borrow without tying it to anything.
+///
+/// The `unsafe` forms skip the analyzer's check that the tied lifetime only appears in the
+/// function's output and not in its inputs or generic bounds. They can be prefixed with a string
+/// justifying why the tie is sound anyways, which `cargo autoken audit` reports alongside the
+/// directive.
+///
+/// ```
+/// autoken::cap! {
+///     pub Names = Vec<String>;
+/// }
+///
+/// struct NameView;
+///
+/// impl NameView {
+///     fn first<'a>(&'a self) -> &'a str {
+///         autoken::tie!(
+///             unsafe "`NameView` holds no borrows of its own which the caller could rely upon",
+///             'a => ref Names
+///         );
+///         &autoken::cap!(ref Names)[0]
+///     }
+/// }
+///
+/// let mut names = vec!["Ferris".to_string()];
+///
+/// autoken::cap! {
+///     Names: &mut names
+/// =>
+///     assert_eq!(NameView.first(), "Ferris");
+/// }
+/// ```
+///
+/// The tied lifetime may also be one captured by an `impl Trait` return type:
+///
+/// ```
+/// autoken::cap! {
+///     pub Numbers = Vec<u32>;
+/// }
+///
+/// fn evens<'a>() -> impl Iterator<Item = &'a u32> {
+///     autoken::tie!('a => ref Numbers);
+///     autoken::cap!(ref Numbers).iter().filter(|&&v| v % 2 == 0)
+/// }
+///
+/// let mut numbers = vec![1, 2, 3, 4];
+///
+/// autoken::cap! {
+///     Numbers: &mut numbers
+/// =>
+///     assert_eq!(evens().copied().collect::<Vec<_>>(), [2, 4]);
+/// }
+/// ```
 #[macro_export]
 macro_rules! tie {
     // Safe variants
@@ -1339,6 +2029,98 @@ macro_rules! tie {
     (unsafe ref $ty:ty) => {
         $crate::tie!(unsafe set $crate::Ref<$ty>);
     };
+
+    // Justified unsafe variants
+    (unsafe $reason:literal, $($rest:tt)*) => {{
+        $crate::tie_macro_internals::__autoken_justify_tie($reason);
+        $crate::tie!(unsafe $($rest)*);
+    }};
+}
+
+// === Subset Assertions === //
+
+#[doc(hidden)]
+pub mod assert_subset_macro_internals {
+    pub fn __autoken_assert_subset<Sub: crate::TokenSet, Super: crate::TokenSet>() {}
+}
+
+/// Asserts that every token borrowed by the token set `Sub` is also borrowed by the token set
+/// `Super` with at least the same mutability.
+///
+/// The assertion costs nothing at runtime. It is checked by the analyzer once the generic
+/// parameters of the enclosing function are known, which makes it useful for guaranteeing that an
+/// adapter only ever forwards a subset of the tokens it was given.
+///
+/// ```
+/// use autoken::{DowngradeRef, Mut, Ref};
+///
+/// fn forward_reads<T: autoken::TokenSet>() {
+///     autoken::assert_subset!(DowngradeRef<T>, T);
+/// }
+///
+/// fn main() {
+///     autoken::assert_subset!(Ref<u32>, (Mut<u32>, Ref<i32>));
+///     forward_reads::<(Mut<u32>, Ref<i32>)>();
+/// }
+/// ```
+///
+/// Borrowing a token which `Super` doesn't borrow, or borrowing it mutably when `Super` only borrows
+/// it immutably, is an error:
+///
+/// ```ignore
+/// autoken::assert_subset!(Mut<u32>, (Ref<u32>, Mut<i32>));
+/// ```
+///
+/// ```plain_text
+/// error: token set Mut<u32> is not a subset of (Ref<u32>, Mut<i32>)
+///  --> src/main.rs:4:5
+///   |
+/// 4 |     autoken::assert_subset!(Mut<u32>, (Ref<u32>, Mut<i32>));
+///   |     ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+///   |
+///   = note: u32 is borrowed mutably by the subset but only immutably by the superset
+///   = note: for more information about this diagnostic, try `cargo autoken explain subset-assertion`
+/// ```
+#[macro_export]
+macro_rules! assert_subset {
+    ($sub:ty, $super:ty $(,)?) => {
+        $crate::assert_subset_macro_internals::__autoken_assert_subset::<$sub, $super>()
+    };
+}
+
+// === Module Skipping === //
+
+/// Opts the enclosing module and all of its descendants out of validation.
+///
+/// Functions in a skipped module are still traced so their callers see the tokens they borrow but
+/// conflicts within their own bodies are never reported. This is useful when adopting AuToken
+/// incrementally in a crate with large modules which predate it.
+///
+/// Rust doesn't let libraries define custom attributes without a procedural macro so this is a
+/// macro invoked in item position rather than a `#![autoken::skip]` attribute.
+///
+/// ```
+/// mod legacy {
+///     autoken::skip_module!();
+///
+///     autoken::cap! {
+///         pub MyCap = Vec<u32>;
+///     }
+///
+///     pub fn push_while_reading() {
+///         // This conflict is not reported.
+///         let first = &autoken::cap!(ref MyCap)[0];
+///         autoken::cap!(mut MyCap).push(*first);
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! skip_module {
+    () => {
+        #[doc(hidden)]
+        #[allow(dead_code, non_upper_case_globals)]
+        const __autoken_skip_module: () = ();
+    };
 }
 
 // === `cap!` === //
@@ -1357,28 +2139,477 @@ pub mod cap_macro_internals {
 
     impl CxScope {
         pub fn new(tls: &'static LocalKey<Cell<*mut ()>>, new_ptr: *mut ()) -> Self {
-            tls.set(new_ptr);
+            // N.B. we must capture the previous pointer before overwriting it or else nested
+            // providers of the same capability would never restore their outer value.
+            let prev = tls.replace(new_ptr);
 
-            Self {
-                tls,
-                prev: tls.get(),
-            }
+            Self { tls, prev }
         }
     }
 
+    pub fn as_cell<T>(cell: &Cell<T>) -> &Cell<T> {
+        cell
+    }
+
+    pub fn copy_of<T: Copy>(value: &T) -> T {
+        *value
+    }
+
+    // N.B. the TLS cell only has room for a thin pointer so, to support unsized capabilities such as
+    // `dyn Trait`, providers store a pointer to a slot on their stack containing the (potentially fat)
+    // pointer to the provided value. The slot lives until the provider's body exits.
+    pub unsafe fn slot_to_ref<'a, T: ?Sized>(slot: *mut ()) -> &'a T {
+        &**slot.cast::<*mut T>()
+    }
+
+    pub unsafe fn slot_to_mut<'a, T: ?Sized>(slot: *mut ()) -> &'a mut T {
+        &mut **slot.cast::<*mut T>()
+    }
+
     impl Drop for CxScope {
         fn drop(&mut self) {
             self.tls.set(self.prev);
         }
     }
+
+    pub struct CapProvider<C, T> {
+        pub(crate) value: T,
+        pub(crate) _ty: std::marker::PhantomData<fn() -> C>,
+    }
+
+    pub trait CapProviderChain {
+        fn run<R>(self, f: impl FnOnce() -> R) -> R;
+    }
+
+    impl CapProviderChain for () {
+        fn run<R>(self, f: impl FnOnce() -> R) -> R {
+            f()
+        }
+    }
+
+    impl<C: crate::CapTarget<T>, T, P: CapProviderChain> CapProviderChain for (CapProvider<C, T>, P) {
+        fn run<R>(self, f: impl FnOnce() -> R) -> R {
+            let (head, tail) = self;
+
+            // Earlier providers wrap later ones so later providers shadow earlier providers of the
+            // same capability.
+            tail.run(move || C::provide(head.value, f))
+        }
+    }
 }
 
+/// Implemented by every capability defined with [`cap!`](crate::cap) for each way in which it can be
+/// provided.
+///
+/// A capability can be provided by mutable reference, in which case its body may fetch it with
+/// either `ref` or `mut`, or by shared reference, in which case its body may only fetch it with
+/// `ref`. Fetching a capability mutably from within the body of a shared provider is reported as a
+/// conflicting borrow.
+///
+/// Providing a [`Cell`](std::cell::Cell) by shared reference is a first-class pattern: every
+/// function can fetch it with `ref` and mutate it through the `Cell`, even while other fetches of it
+/// are still live further up the stack.
+///
+/// ```
+/// use std::cell::Cell;
+///
+/// autoken::cap! {
+///     pub Hits = Cell<u32>;
+/// }
+///
+/// fn hit() {
+///     let hits = autoken::cap!(ref Hits);
+///     hits.set(hits.get() + 1);
+/// }
+///
+/// fn hit_around() {
+///     let hits = autoken::cap!(ref Hits);
+///     hits.set(hits.get() + 1);
+///     hit();
+///     hits.set(hits.get() + 1);
+/// }
+///
+/// fn main() {
+///     let hits = Cell::new(0);
+///
+///     autoken::cap! {
+///         Hits: &hits
+///     =>
+///         hit();
+///         hit_around();
+///     }
+///
+///     assert_eq!(hits.get(), 4);
+/// }
+/// ```
 pub trait CapTarget<T> {
     fn provide<R>(value: T, f: impl FnOnce() -> R) -> R;
 }
 
+/// A builder which provides several capabilities to a single scope.
+///
+/// This is the runtime equivalent of the comma form of [`cap!`](crate::cap) and is useful when the
+/// values being provided are only known at runtime.
+///
+/// ```
+/// autoken::cap! {
+///     pub Counter = u32;
+///     pub Name = String;
+/// }
+///
+/// fn describe() -> String {
+///     format!("{} #{}", autoken::cap!(ref Name), autoken::cap!(ref Counter))
+/// }
+///
+/// fn main() {
+///     let mut counter = 1;
+///     let mut name = "plugin".to_string();
+///
+///     let description = autoken::CapScope::new()
+///         .provide::<Counter, _>(&mut counter)
+///         .provide::<Name, _>(&mut name)
+///         .enter(describe);
+///
+///     assert_eq!(description, "plugin #1");
+/// }
+/// ```
+///
+/// Each call to `provide` is recorded in the type of the builder so that `enter` can statically call
+/// into each capability's provider. This is what lets the analyzer see every token absorbed by the
+/// scope. As a consequence, while the *values* being provided can be chosen at runtime, the *set* of
+/// capabilities being provided must still be known at compile time. Capabilities which may or may not
+/// be provided depending on runtime state (e.g. those enumerated from a plugin list) cannot be
+/// expressed with this builder since the analyzer would have no way of knowing whether they were
+/// actually provided.
+#[must_use]
+pub struct CapScope<P = ()> {
+    providers: P,
+}
+
+impl CapScope {
+    pub fn new() -> Self {
+        Self { providers: () }
+    }
+}
+
+impl Default for CapScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P> fmt::Debug for CapScope<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CapScope").finish_non_exhaustive()
+    }
+}
+
+impl<P: cap_macro_internals::CapProviderChain> CapScope<P> {
+    /// Provides `value` to the capability `C` for the duration of the scope. Later providers of the
+    /// same capability shadow earlier ones.
+    pub fn provide<C: CapTarget<T>, T>(
+        self,
+        value: T,
+    ) -> CapScope<(cap_macro_internals::CapProvider<C, T>, P)> {
+        CapScope {
+            providers: (
+                cap_macro_internals::CapProvider {
+                    value,
+                    _ty: PhantomData,
+                },
+                self.providers,
+            ),
+        }
+    }
+
+    /// Runs `f` with every capability provided to this builder in its context.
+    pub fn enter<R>(self, f: impl FnOnce() -> R) -> R {
+        self.providers.run(f)
+    }
+}
+
+/// A provider whose set of provided capabilities is only known at runtime.
+///
+/// Unlike with [`CapScope`], providers can be collected into heterogeneous lists (e.g. from plugins
+/// registered at runtime) and entered all at once with [`provide_all`].
+///
+/// The analyzer can't see through these providers so it treats them as a dynamic dispatch boundary.
+/// Code run inside them borrows nothing from its caller and, conversely, can't assume that any
+/// particular capability has been provided. Bodies must therefore [`absorb`] the capabilities they
+/// expect to be provided, which is where the user takes responsibility for them being present.
+/// Fetching a capability which no provider supplied panics.
+pub trait DynProvider {
+    /// Runs `f` with this provider's capabilities in its context.
+    fn enter(&mut self, f: &mut dyn FnMut());
+}
+
+/// A [`DynProvider`] which provides a mutable reference to the capability `C`.
+pub struct DynCap<'a, C, V: ?Sized> {
+    value: &'a mut V,
+    _ty: PhantomData<fn() -> C>,
+}
+
+impl<'a, C, V: ?Sized> DynCap<'a, C, V> {
+    pub fn new(value: &'a mut V) -> Self {
+        Self {
+            value,
+            _ty: PhantomData,
+        }
+    }
+}
+
+impl<C, V: ?Sized> fmt::Debug for DynCap<'_, C, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynCap").finish_non_exhaustive()
+    }
+}
+
+impl<C, V: ?Sized> DynProvider for DynCap<'_, C, V>
+where
+    C: for<'b> CapTarget<&'b mut V>,
+{
+    fn enter(&mut self, f: &mut dyn FnMut()) {
+        C::provide(&mut *self.value, f)
+    }
+}
+
+/// Runs `f` with the capabilities of every provider in `providers` in its context. Later providers of
+/// the same capability shadow earlier ones.
+///
+/// See [`DynProvider`] for how this interacts with the analysis.
+///
+/// ```
+/// use autoken::{DynCap, DynProvider, Mut};
+///
+/// autoken::cap! {
+///     pub Counter = u32;
+///     pub Log = Vec<String>;
+/// }
+///
+/// fn bump() {
+///     *autoken::cap!(mut Counter) += 1;
+///     autoken::cap!(mut Log).push("bumped".to_string());
+/// }
+///
+/// fn main() {
+///     let mut counter = 0;
+///     let mut log = Vec::new();
+///
+///     let mut providers: Vec<Box<dyn DynProvider>> = vec![
+///         Box::new(DynCap::<Counter, _>::new(&mut counter)),
+///         Box::new(DynCap::<Log, _>::new(&mut log)),
+///     ];
+///
+///     autoken::provide_all(&mut providers, || {
+///         // SAFETY: both capabilities are registered above.
+///         unsafe { autoken::absorb::<(Mut<Counter>, Mut<Log>), _>(bump) };
+///     });
+///
+///     drop(providers);
+///     assert_eq!(counter, 1);
+///     assert_eq!(log, ["bumped"]);
+/// }
+/// ```
+pub fn provide_all<R>(providers: &mut [Box<dyn DynProvider + '_>], f: impl FnOnce() -> R) -> R {
+    fn provide_all_inner(providers: &mut [Box<dyn DynProvider + '_>], f: &mut dyn FnMut()) {
+        match providers.split_first_mut() {
+            Some((first, rest)) => first.enter(&mut || provide_all_inner(rest, f)),
+            None => f(),
+        }
+    }
+
+    let mut f = Some(f);
+    let mut res = None;
+    provide_all_inner(providers, &mut || res = f.take().map(|f| f()));
+    res.expect("a `DynProvider` did not run its body")
+}
+
+/// Defines, provides, and fetches implicitly-passed capabilities.
+///
+/// See the [crate-level documentation](crate) for a tour of its various forms.
+///
+/// Providers of the same capability can be nested. The inner provider shadows the outer one for the
+/// duration of its body and the outer value is restored once the inner body exits.
+///
+/// ```
+/// autoken::cap! {
+///     pub MyCap = u32;
+/// }
+///
+/// fn read_cap() -> u32 {
+///     *autoken::cap!(ref MyCap)
+/// }
+///
+/// fn main() {
+///     autoken::cap! {
+///         MyCap: &mut 1
+///     =>
+///         autoken::cap! {
+///             MyCap: &mut 2
+///         =>
+///             assert_eq!(read_cap(), 2);
+///         }
+///
+///         assert_eq!(read_cap(), 1);
+///     }
+/// }
+/// ```
+///
+/// Capabilities whose value is a [`Cell`](std::cell::Cell) can be fetched with the `cell` form,
+/// which returns a `&Cell<T>` while only borrowing the capability immutably. This lets several
+/// overlapping call paths mutate the value without having to tie or thread a mutable borrow
+/// through each of them:
+///
+/// ```
+/// use std::cell::Cell;
+///
+/// autoken::cap! {
+///     pub Visits = Cell<u32>;
+/// }
+///
+/// fn visit() {
+///     let visits = autoken::cap!(cell Visits);
+///     visits.set(visits.get() + 1);
+/// }
+///
+/// fn visit_twice() {
+///     // `visits` stays live across the call to `visit`, which would be rejected if either of
+///     // them fetched `Visits` mutably.
+///     let visits = autoken::cap!(cell Visits);
+///     visit();
+///     visits.set(visits.get() + 1);
+/// }
+///
+/// fn main() {
+///     autoken::cap! {
+///         Visits: &Cell::new(0)
+///     =>
+///         visit();
+///         visit_twice();
+///         assert_eq!(autoken::cap!(cell Visits).get(), 3);
+///     }
+/// }
+/// ```
+///
+/// The tradeoff is that AuToken no longer checks anything about these mutations. A `Cell` can only
+/// be read by copying its value out so this is mostly useful for counters, flags, and other small
+/// `Copy` values. A `RefCell` can be fetched with the `ref` form in the same way but its borrows are
+/// checked at runtime and will panic on overlap, which is exactly what `mut` would have caught
+/// statically.
+///
+/// Capabilities whose value is `Copy` can also be read by value with the `copy` form. Its borrow
+/// ends as soon as the value is read so, unlike with `*autoken::cap!(ref MyCap)`, nothing stops the
+/// capability from being fetched mutably while the copy is still around:
+///
+/// ```
+/// autoken::cap! {
+///     pub Counter = u32;
+/// }
+///
+/// fn double() {
+///     let before = autoken::cap!(copy Counter);
+///     *autoken::cap!(mut Counter) += before;
+/// }
+///
+/// fn main() {
+///     autoken::cap! {
+///         Counter: &mut 3
+///     =>
+///         double();
+///         assert_eq!(autoken::cap!(copy Counter), 6);
+///     }
+/// }
+/// ```
+///
+/// The body of a provider runs in a closure so `return` and `?` leave the body rather than the
+/// enclosing function. A body using them can still forward its result to the caller but, since
+/// nothing else constrains it, the closure's return type often can't be inferred. In that case, the
+/// return type can be written out by wrapping the body in braces after a `->`:
+///
+/// ```
+/// use std::num::ParseIntError;
+///
+/// autoken::cap! {
+///     pub Total = u32;
+/// }
+///
+/// fn add(text: &str) -> Result<(), ParseIntError> {
+///     *autoken::cap!(mut Total) += text.parse::<u32>()?;
+///     Ok(())
+/// }
+///
+/// fn sum(texts: &[&str]) -> Result<u32, Box<dyn std::error::Error>> {
+///     let mut total = 0;
+///
+///     autoken::cap! {
+///         Total: &mut total
+///     => -> Result<(), ParseIntError> {
+///             for text in texts {
+///                 add(text)?;
+///             }
+///
+///             Ok(())
+///         }
+///     }?;
+///
+///     Ok(total)
+/// }
+///
+/// fn main() {
+///     assert_eq!(sum(&["1", "2", "3"]).unwrap(), 6);
+///     assert!(sum(&["1", "two"]).is_err());
+/// }
+/// ```
+///
+/// Capabilities can also be unsized, which is useful for injecting trait objects into plugin-style
+/// code without committing to a concrete service type:
+///
+/// ```
+/// trait Logger {
+///     fn log(&mut self, message: &str);
+/// }
+///
+/// struct VecLogger(Vec<String>);
+///
+/// impl Logger for VecLogger {
+///     fn log(&mut self, message: &str) {
+///         self.0.push(message.to_string());
+///     }
+/// }
+///
+/// autoken::cap! {
+///     pub Log = dyn Logger;
+/// }
+///
+/// fn run_plugin() {
+///     autoken::cap!(mut Log).log("plugin started");
+/// }
+///
+/// fn main() {
+///     let mut logger = VecLogger(Vec::new());
+///
+///     autoken::cap! {
+///         Log: &mut logger as &mut dyn Logger
+///     =>
+///         run_plugin();
+///     }
+///
+///     assert_eq!(logger.0, ["plugin started"]);
+/// }
+/// ```
 #[macro_export]
 macro_rules! cap {
+    ( $($ty:ty: $expr:expr),*$(,)? => -> $ret:ty { $($body:tt)* } ) => {{
+        #[allow(unused_mut)]
+        let mut f = || -> $ret { $($body)* };
+
+        $(
+            #[allow(unused_mut)]
+            let mut f = || <$ty as $crate::CapTarget<_>>::provide($expr, f);
+        )*
+
+        f()
+    }};
     ( $($ty:ty: $expr:expr),*$(,)? => $($body:tt)* ) => {{
         #[allow(unused_mut)]
         let mut f = || { $($body)* };
@@ -1396,6 +2627,16 @@ macro_rules! cap {
     (mut $ty:ty) => {
         <$ty>::get_mut($crate::cap_macro_internals::BorrowsOne::acquire_mut(), |v| v)
     };
+    (cell $ty:ty) => {
+        <$ty>::get($crate::cap_macro_internals::BorrowsOne::acquire_ref(), |v| {
+            $crate::cap_macro_internals::as_cell(v)
+        })
+    };
+    (copy $ty:ty) => {
+        <$ty>::get($crate::cap_macro_internals::BorrowsOne::acquire_ref(), |v| {
+            $crate::cap_macro_internals::copy_of(v)
+        })
+    };
     (ref $ty:ty => $name:ident in $out:expr) => {
         <$ty>::get($crate::cap_macro_internals::BorrowsOne::acquire_ref(), |$name| $out)
     };
@@ -1425,20 +2666,25 @@ macro_rules! cap {
                 _borrows: &'out $crate::cap_macro_internals::BorrowsOne<$name>,
                 f: impl $(for<$($lt,)*>)? $crate::cap_macro_internals::FnOnce(&'out $ty) -> R,
             ) -> R {
-                f(Self::tls().with(|ptr| unsafe { &*ptr.get().cast() }))
+                f(Self::tls().with(|ptr| unsafe {
+                    $crate::cap_macro_internals::slot_to_ref(ptr.get())
+                }))
             }
 
             $vis fn get_mut<'out, R: 'out>(
                 _borrows: &'out mut $crate::cap_macro_internals::BorrowsOne<$name>,
                 f: impl $(for<$($lt,)*>)? $crate::cap_macro_internals::FnOnce(&'out mut $ty) -> R,
             ) -> R {
-                f(Self::tls().with(|ptr| unsafe { &mut *ptr.get().cast() }))
+                f(Self::tls().with(|ptr| unsafe {
+                    $crate::cap_macro_internals::slot_to_mut(ptr.get())
+                }))
             }
         }
 
         impl<'out $($(, $lt)*)?> $crate::CapTarget<&'out mut $ty> for $name {
             fn provide<R>(value: &'out mut $ty, f: impl $crate::cap_macro_internals::FnOnce() -> R) -> R {
-                let _scope = $crate::cap_macro_internals::CxScope::new(Self::tls(), value as *mut $ty as *mut ());
+                let mut slot = value as *mut $ty;
+                let _scope = $crate::cap_macro_internals::CxScope::new(Self::tls(), &mut slot as *mut *mut $ty as *mut ());
 
                 unsafe {
                     $crate::absorb::<$crate::Mut<Self>, R>(f)
@@ -1448,7 +2694,8 @@ macro_rules! cap {
 
         impl<'out $($(, $lt)*)?> $crate::CapTarget<&'out $ty> for $name {
             fn provide<R>(value: &'out $ty, f: impl $crate::cap_macro_internals::FnOnce() -> R) -> R {
-                let _scope = $crate::cap_macro_internals::CxScope::new(Self::tls(), value as *const $ty as *const () as *mut ());
+                let mut slot = value as *const $ty as *mut $ty;
+                let _scope = $crate::cap_macro_internals::CxScope::new(Self::tls(), &mut slot as *mut *mut $ty as *mut ());
 
                 fn tier<'a>() -> &'a () {
                     $crate::tie!('a => mut $name);